@@ -0,0 +1,225 @@
+//! An axum router for operating a [`BackupManager`] over HTTP, either shared
+//! behind a mutex ([`router`]) or running in its own task ([`handle_router`]).
+//!
+//! Every route requires the extractor `A` to succeed, so authentication is
+//! whatever the application already uses: an extractor that checks a bearer
+//! token or session and rejects the request otherwise. Use `()` to leave the
+//! routes open, e.g. when they are only bound to localhost.
+//!
+//! | Route | |
+//! |---|---|
+//! | `GET /stats` | [`BackupStats`](crate::BackupStats) |
+//! | `GET /health` | [`Health`](crate::Health), with status 503 when failing |
+//! | `GET /generations` | manifests of all generations, oldest first |
+//! | `POST /snapshot` | upload a snapshot of the current generation |
+//! | `POST /checkpoint` | checkpoint and start a new generation |
+//! | `POST /retention` | delete expired generations |
+//! | `POST /compact?max_segment_size=` | compact the current generation's WAL segments |
+//! | `GET /restore-plan?timestamp_ms=` | [`RestorePlan`](crate::RestorePlan) of a restore, without downloading |
+
+use std::sync::Arc;
+
+use axum::extract::{FromRequestParts, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::error::{Error, Result};
+use crate::handle::{ManagerFuture, ReplicationHandle};
+use crate::health::HealthStatus;
+use crate::manager::BackupManager;
+
+/// The manager shared between the application and the admin routes.
+pub type SharedManager = Arc<Mutex<BackupManager>>;
+
+/// Access to the manager behind the routes.
+trait ManagerAccess: Clone + Send + Sync + 'static {
+    fn with_manager<T, F>(&self, f: F) -> impl Future<Output = Result<T>> + Send
+    where
+        T: Send + 'static,
+        F: for<'a> FnOnce(&'a mut BackupManager) -> ManagerFuture<'a, T> + Send + 'static;
+}
+
+impl ManagerAccess for SharedManager {
+    async fn with_manager<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: for<'a> FnOnce(&'a mut BackupManager) -> ManagerFuture<'a, T> + Send + 'static,
+    {
+        let mut mgr = self.lock().await;
+        Ok(f(&mut mgr).await)
+    }
+}
+
+impl ManagerAccess for ReplicationHandle {
+    async fn with_manager<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: for<'a> FnOnce(&'a mut BackupManager) -> ManagerFuture<'a, T> + Send + 'static,
+    {
+        ReplicationHandle::with_manager(self, f).await
+    }
+}
+
+/// A failed operation, returned as status 500 with `{"error": "..."}`.
+struct ApiError(Error);
+
+impl From<Error> for ApiError {
+    fn from(e: Error) -> Self {
+        Self(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({ "error": self.0.to_string(), "kind": self.0.kind() });
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+    }
+}
+
+type ApiResult<T> = std::result::Result<Json<T>, ApiError>;
+
+#[derive(Deserialize)]
+struct CompactParams {
+    max_segment_size: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct RestorePlanParams {
+    timestamp_ms: Option<u64>,
+}
+
+/// Admin routes for `mgr`, guarded by the extractor `A`. Nest them under a
+/// path of the application's choosing:
+///
+/// ```ignore
+/// let app = Router::new().nest("/admin/backup", waloy::admin::router::<BearerAuth>(mgr));
+/// ```
+pub fn router<A>(mgr: SharedManager) -> Router
+where
+    A: FromRequestParts<SharedManager> + Send + 'static,
+{
+    routes::<A, _>(mgr)
+}
+
+/// Admin routes for a manager started with
+/// [`BackupManager::spawn`](crate::BackupManager::spawn), guarded by the
+/// extractor `A`. Requests are served by the replication task between syncs.
+pub fn handle_router<A>(handle: ReplicationHandle) -> Router
+where
+    A: FromRequestParts<ReplicationHandle> + Send + 'static,
+{
+    routes::<A, _>(handle)
+}
+
+fn routes<A, M>(mgr: M) -> Router
+where
+    M: ManagerAccess,
+    A: FromRequestParts<M> + Send + 'static,
+{
+    Router::new()
+        .route("/stats", get(stats::<A, M>))
+        .route("/health", get(health::<A, M>))
+        .route("/generations", get(generations::<A, M>))
+        .route("/snapshot", post(snapshot::<A, M>))
+        .route("/checkpoint", post(checkpoint::<A, M>))
+        .route("/retention", post(retention::<A, M>))
+        .route("/compact", post(compact::<A, M>))
+        .route("/restore-plan", get(restore_plan::<A, M>))
+        .with_state(mgr)
+}
+
+async fn stats<A, M: ManagerAccess>(_: A, State(mgr): State<M>) -> ApiResult<crate::BackupStats> {
+    let stats = mgr
+        .with_manager(|mgr| Box::pin(async move { mgr.stats() }))
+        .await?;
+    Ok(Json(stats))
+}
+
+async fn health<A, M: ManagerAccess>(
+    _: A,
+    State(mgr): State<M>,
+) -> std::result::Result<Response, ApiError> {
+    let health = mgr
+        .with_manager(|mgr| Box::pin(async move { mgr.health() }))
+        .await?;
+    let status = match health.status {
+        HealthStatus::Failing => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK,
+    };
+    Ok((status, Json(health)).into_response())
+}
+
+async fn generations<A, M: ManagerAccess>(
+    _: A,
+    State(mgr): State<M>,
+) -> ApiResult<Vec<crate::GenerationManifest>> {
+    let generations = mgr
+        .with_manager(|mgr| Box::pin(mgr.generations()))
+        .await??;
+    Ok(Json(generations))
+}
+
+async fn snapshot<A, M: ManagerAccess>(_: A, State(mgr): State<M>) -> ApiResult<serde_json::Value> {
+    let generation = mgr
+        .with_manager(|mgr| {
+            Box::pin(async move {
+                mgr.snapshot().await?;
+                Ok::<_, Error>(mgr.generation().to_string())
+            })
+        })
+        .await??;
+    Ok(Json(serde_json::json!({ "generation": generation })))
+}
+
+async fn checkpoint<A, M: ManagerAccess>(
+    _: A,
+    State(mgr): State<M>,
+) -> ApiResult<serde_json::Value> {
+    let generation = mgr
+        .with_manager(|mgr| {
+            Box::pin(async move {
+                mgr.checkpoint().await?;
+                Ok::<_, Error>(mgr.generation().to_string())
+            })
+        })
+        .await??;
+    Ok(Json(serde_json::json!({ "generation": generation })))
+}
+
+async fn retention<A, M: ManagerAccess>(
+    _: A,
+    State(mgr): State<M>,
+) -> ApiResult<serde_json::Value> {
+    let deleted = mgr
+        .with_manager(|mgr| Box::pin(mgr.enforce_retention()))
+        .await??;
+    Ok(Json(serde_json::json!({ "deleted": deleted })))
+}
+
+async fn compact<A, M: ManagerAccess>(
+    _: A,
+    State(mgr): State<M>,
+    Query(params): Query<CompactParams>,
+) -> ApiResult<crate::CompactionResult> {
+    let result = mgr
+        .with_manager(move |mgr| Box::pin(mgr.compact(params.max_segment_size)))
+        .await??;
+    Ok(Json(result))
+}
+
+async fn restore_plan<A, M: ManagerAccess>(
+    _: A,
+    State(mgr): State<M>,
+    Query(params): Query<RestorePlanParams>,
+) -> ApiResult<crate::RestorePlan> {
+    // Don't hold the manager while reading from S3.
+    let config = mgr
+        .with_manager(|mgr| Box::pin(async move { mgr.config().clone() }))
+        .await?;
+    let plan = BackupManager::restore_plan(&config, params.timestamp_ms).await?;
+    Ok(Json(plan))
+}
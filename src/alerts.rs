@@ -0,0 +1,278 @@
+use std::process::Stdio;
+
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::broadcast;
+
+use crate::config::{AlertConfig, AlertTarget};
+use crate::error::{Error, Result};
+use crate::events::{GenerationReason, ReplicationEvent};
+use crate::manager::now_ms;
+
+/// What an [`Alert`] is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    /// `consecutive_failures` syncs in a row failed.
+    SyncFailing,
+    /// A snapshot could not be uploaded.
+    SnapshotFailed,
+    /// A WAL discontinuity forced a new generation.
+    Recovery,
+}
+
+impl AlertKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::SyncFailing => "sync_failing",
+            Self::SnapshotFailed => "snapshot_failed",
+            Self::Recovery => "recovery",
+        }
+    }
+}
+
+/// A notification delivered to the [`AlertTarget`]s of a
+/// [`BackupConfig`](crate::BackupConfig), serialized as JSON.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Alert {
+    pub kind: AlertKind,
+    /// Path of the replicated database.
+    pub db: String,
+    pub message: String,
+    /// When the alert was raised, in milliseconds since the Unix epoch.
+    pub at_ms: u64,
+    /// The event that raised it.
+    pub event: ReplicationEvent,
+}
+
+impl Alert {
+    /// The alert raised by `event`, if any. Repeated sync failures alert once,
+    /// when the count reaches `consecutive_failures`.
+    pub fn from_event(
+        db: &str,
+        event: &ReplicationEvent,
+        consecutive_failures: u32,
+    ) -> Option<Self> {
+        let (kind, message) = match event {
+            ReplicationEvent::SyncFailed {
+                error,
+                consecutive_errors,
+                ..
+            } if *consecutive_errors == consecutive_failures.max(1) => (
+                AlertKind::SyncFailing,
+                format!("{consecutive_errors} WAL syncs failed in a row: {error}"),
+            ),
+            ReplicationEvent::SnapshotFailed {
+                generation, error, ..
+            } => (
+                AlertKind::SnapshotFailed,
+                format!("snapshot of generation {generation} failed: {error}"),
+            ),
+            ReplicationEvent::GenerationStarted {
+                generation,
+                reason: GenerationReason::Recovery,
+            } => (
+                AlertKind::Recovery,
+                format!("WAL discontinuity, started generation {generation}"),
+            ),
+            _ => return None,
+        };
+        Some(Self {
+            kind,
+            db: db.to_string(),
+            message,
+            at_ms: now_ms(),
+            event: event.clone(),
+        })
+    }
+}
+
+/// Deliver alerts for `events` until the manager is dropped.
+pub(crate) fn spawn(
+    db: String,
+    config: AlertConfig,
+    mut events: broadcast::Receiver<ReplicationEvent>,
+) {
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    tracing::warn!(missed, "alerts: missed replication events");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            if let Some(alert) = Alert::from_event(&db, &event, config.consecutive_failures) {
+                deliver(&config, &alert).await;
+            }
+        }
+    });
+}
+
+/// Send `alert` to every target, logging failures.
+pub(crate) async fn deliver(config: &AlertConfig, alert: &Alert) {
+    for target in &config.targets {
+        let result = tokio::time::timeout(config.timeout, send(target, alert))
+            .await
+            .unwrap_or_else(|_| Err(Error::Other("alert delivery timed out".into())));
+        if let Err(e) = result {
+            tracing::warn!(error = %e, kind = alert.kind.as_str(), "alert delivery failed");
+        }
+    }
+}
+
+async fn send(target: &AlertTarget, alert: &Alert) -> Result<()> {
+    let payload =
+        serde_json::to_vec(alert).map_err(|e| Error::Other(format!("alert serialize: {e}")))?;
+    match target {
+        #[cfg(feature = "webhook")]
+        AlertTarget::Webhook { url, headers } => {
+            let mut request = reqwest::Client::new()
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(payload);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            request
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| Error::Other(format!("webhook: {e}")))?;
+        }
+        AlertTarget::Command { program, args } => {
+            let mut child = tokio::process::Command::new(program)
+                .args(args)
+                .env("WALOY_ALERT", alert.kind.as_str())
+                .env("WALOY_DB", &alert.db)
+                .env("WALOY_MESSAGE", &alert.message)
+                .stdin(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn()?;
+            if let Some(mut stdin) = child.stdin.take() {
+                // The command may ignore its input and exit early.
+                let _ = stdin.write_all(&payload).await;
+            }
+            let status = child.wait().await?;
+            if !status.success() {
+                return Err(Error::Other(format!("alert command {program}: {status}")));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sync_failed(consecutive_errors: u32) -> ReplicationEvent {
+        ReplicationEvent::SyncFailed {
+            kind: "s3",
+            error: "s3: timeout".into(),
+            consecutive_errors,
+        }
+    }
+
+    #[test]
+    fn sync_failures_alert_once_at_threshold() {
+        assert!(Alert::from_event("a.db", &sync_failed(2), 3).is_none());
+        let alert = Alert::from_event("a.db", &sync_failed(3), 3).unwrap();
+        assert_eq!(alert.kind, AlertKind::SyncFailing);
+        assert_eq!(alert.db, "a.db");
+        assert!(alert.message.contains("3 WAL syncs failed"));
+        assert!(Alert::from_event("a.db", &sync_failed(4), 3).is_none());
+    }
+
+    #[test]
+    fn only_recovery_generations_alert() {
+        let started = |reason| ReplicationEvent::GenerationStarted {
+            generation: "g".into(),
+            reason,
+        };
+        assert!(Alert::from_event("a.db", &started(GenerationReason::Checkpoint), 3).is_none());
+        assert!(Alert::from_event("a.db", &started(GenerationReason::Schedule), 3).is_none());
+        let alert = Alert::from_event("a.db", &started(GenerationReason::Recovery), 3).unwrap();
+        assert_eq!(alert.kind, AlertKind::Recovery);
+    }
+
+    #[test]
+    fn snapshot_failure_alerts() {
+        let event = ReplicationEvent::SnapshotFailed {
+            generation: "g".into(),
+            kind: "io",
+            error: "io: disk full".into(),
+        };
+        let alert = Alert::from_event("a.db", &event, 3).unwrap();
+        assert_eq!(alert.kind, AlertKind::SnapshotFailed);
+        let json = serde_json::to_value(&alert).unwrap();
+        assert_eq!(json["kind"], "snapshot_failed");
+        assert_eq!(json["event"]["type"], "snapshot_failed");
+    }
+
+    #[tokio::test]
+    async fn command_receives_alert() {
+        let tmp = tempfile::tempdir().unwrap();
+        let out = tmp.path().join("alert.json");
+        let config = AlertConfig {
+            targets: vec![AlertTarget::Command {
+                program: "sh".into(),
+                args: vec![
+                    "-c".into(),
+                    format!(
+                        "cat > {} && test \"$WALOY_ALERT\" = sync_failing",
+                        out.display()
+                    ),
+                ],
+            }],
+            ..Default::default()
+        };
+        let alert = Alert::from_event("a.db", &sync_failed(3), 3).unwrap();
+        send(&config.targets[0], &alert).await.unwrap();
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&out).unwrap()).unwrap();
+        assert_eq!(json["db"], "a.db");
+        assert_eq!(json["event"]["consecutive_errors"], 3);
+    }
+
+    #[cfg(feature = "webhook")]
+    #[tokio::test]
+    async fn webhook_posts_alert() {
+        use axum::http::HeaderMap;
+        use axum::routing::post;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let app = axum::Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: String| async move {
+                let token = headers["x-token"].to_str().unwrap().to_string();
+                tx.send((token, body)).unwrap();
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let target = AlertTarget::Webhook {
+            url: format!("http://{addr}/hook"),
+            headers: vec![("x-token".into(), "secret".into())],
+        };
+        let alert = Alert::from_event("a.db", &sync_failed(3), 3).unwrap();
+        send(&target, &alert).await.unwrap();
+        let (token, body) = rx.recv().await.unwrap();
+        assert_eq!(token, "secret");
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["kind"], "sync_failing");
+    }
+
+    #[tokio::test]
+    async fn failing_command_is_an_error() {
+        let target = AlertTarget::Command {
+            program: "false".into(),
+            args: Vec::new(),
+        };
+        let alert = Alert::from_event("a.db", &sync_failed(3), 3).unwrap();
+        assert!(send(&target, &alert).await.is_err());
+    }
+}
@@ -1,242 +1,1593 @@
-use clap::{Parser, Subcommand};
-use waloy::{BackupManager, S3Config};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use waloy::{
+    BackupConfig, BackupManager, CompressionAlgorithm, GenerationInfo, ReplicaSet,
+    ReplicationEvent, S3Config,
+};
 
 #[derive(Parser)]
 #[command(name = "waloy", about = "CLI for waloy SQLite backup management")]
 struct Cli {
+    /// TOML or YAML file describing the databases and their replicas; the S3
+    /// flags are ignored when it is given
+    #[arg(long, env = "WALOY_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Replica URL such as `s3://bucket/prefix?endpoint=...&region=...` or
+    /// `file:///var/backups`; replaces the other S3 flags except the keys
+    #[arg(long, env = "WALOY_REPLICA")]
+    replica: Option<String>,
+
     /// S3 endpoint URL
     #[arg(long, env = "WALOY_S3_ENDPOINT")]
-    endpoint: String,
+    endpoint: Option<String>,
 
     /// S3 region
     #[arg(long, env = "WALOY_S3_REGION")]
-    region: String,
+    region: Option<String>,
 
     /// S3 bucket name
     #[arg(long, env = "WALOY_S3_BUCKET")]
-    bucket: String,
+    bucket: Option<String>,
 
     /// S3 access key
     #[arg(long, env = "WALOY_S3_ACCESS_KEY")]
-    access_key: String,
+    access_key: Option<String>,
 
     /// S3 secret key
     #[arg(long, env = "WALOY_S3_SECRET_KEY")]
-    secret_key: String,
+    secret_key: Option<String>,
 
     /// S3 key prefix
     #[arg(long, env = "WALOY_S3_PREFIX", default_value = "")]
     prefix: String,
 
+    /// Passphrase to encrypt uploads and decrypt backups with; overrides
+    /// `encryption_key` in `--config`
+    #[arg(long, env = "WALOY_ENCRYPTION_KEY", hide_env_values = true)]
+    encryption_key: Option<String>,
+
+    /// File holding the encryption passphrase, e.g. a mounted secret
+    #[arg(
+        long,
+        env = "WALOY_ENCRYPTION_KEY_FILE",
+        conflicts_with = "encryption_key"
+    )]
+    encryption_key_file: Option<PathBuf>,
+
+    /// Output format; `json` prints one JSON document per command, or one
+    /// JSON object per line for `replicate`
+    #[arg(long, env = "WALOY_OUTPUT", value_enum, default_value_t, global = true)]
+    output: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Restore a database from S3
     Restore {
         /// Target path for the restored database
-        #[arg(short, long)]
-        output: String,
+        #[arg(short = 'o', long)]
+        target: String,
 
-        /// Optional: restore to a specific point in time (milliseconds since epoch)
-        #[arg(long)]
+        /// Optional: restore to a specific point in time, in milliseconds
+        /// since epoch or RFC 3339
+        #[arg(long, value_parser = parse_time)]
         timestamp: Option<u64>,
+
+        /// Database from `--config` whose replica to restore
+        #[arg(long)]
+        db: Option<String>,
     },
     /// List all generations in S3
-    Generations,
+    Generations {
+        /// Database from `--config` whose replica to list
+        #[arg(long)]
+        db: Option<String>,
+    },
     /// Inspect a specific generation or the latest
     Inspect {
         /// Generation ID to inspect (defaults to latest)
         #[arg(short, long)]
         generation: Option<String>,
+
+        /// Database from `--config` whose replica to inspect
+        #[arg(long)]
+        db: Option<String>,
+    },
+    /// Restore a backup into a temporary directory and check that it is intact
+    Verify {
+        /// Generation ID to verify (defaults to latest)
+        #[arg(short, long)]
+        generation: Option<String>,
+
+        /// Verify the backup as of this time, in milliseconds since epoch or
+        /// RFC 3339
+        #[arg(long, value_parser = parse_time)]
+        at: Option<u64>,
+
+        /// Query whose first column must be true (non-zero), such as
+        /// `SELECT count(*) > 0 FROM users`; may be repeated
+        #[arg(long = "assert", value_name = "SQL")]
+        assertions: Vec<String>,
+
+        /// Database from `--config` whose replica to verify
+        #[arg(long)]
+        db: Option<String>,
+    },
+    /// Delete old generations from the replica
+    Prune {
+        /// Delete generations created longer ago than this
+        #[arg(long, value_parser = waloy::parse_duration)]
+        older_than: Duration,
+
+        /// Always keep this many of the newest generations
+        #[arg(long, default_value_t = 0)]
+        keep_last: usize,
+
+        /// Only list the generations that would be deleted
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Prune even though the replica has no `latest` marker, so the
+        /// generation `replicate` is writing to can't be recognized
+        #[arg(long)]
+        force: bool,
+
+        /// Database from `--config` whose replica to prune
+        #[arg(long)]
+        db: Option<String>,
+    },
+    /// Merge the WAL segments of a generation in the replica
+    Compact {
+        /// Generation ID to compact
+        #[arg(short, long)]
+        generation: String,
+
+        /// Upper bound on the size of compacted segments, in bytes
+        #[arg(long)]
+        max_segment_size: Option<usize>,
+
+        /// Compact the latest generation even though `replicate` may still
+        /// be writing to it
+        #[arg(long)]
+        force: bool,
+
+        /// Database from `--config` whose replica to compact
+        #[arg(long)]
+        db: Option<String>,
+    },
+    /// Upload a snapshot of a database, starting a new generation
+    Snapshot {
+        /// Database to snapshot; must not be replicated by a running
+        /// `replicate` at the same time
+        #[arg(long)]
+        db: Option<String>,
+    },
+    /// Decode replicated WAL segments and print their frame headers
+    WalDump {
+        /// Generation ID to dump (defaults to latest)
+        #[arg(short, long)]
+        generation: Option<String>,
+
+        /// Only print the frames of this WAL segment
+        #[arg(short, long)]
+        segment: Option<u32>,
+
+        /// Database from `--config` whose replica to read
+        #[arg(long)]
+        db: Option<String>,
     },
+    /// Copy all backups from one replica to another
+    Copy(CopyArgs),
+    /// Continuously replicate databases until SIGTERM or SIGINT
+    Replicate(ReplicateArgs),
+}
+
+#[derive(Args)]
+struct CopyArgs {
+    /// Replica URL to copy from, decrypted with `--encryption-key` when
+    /// re-encoding; without credentials in it, the S3 key flags are used
+    #[arg(long)]
+    from: String,
+
+    /// Replica URL to copy to
+    #[arg(long)]
+    to: String,
+
+    /// Re-encode the backups with this compression
+    #[arg(long, value_enum)]
+    compression: Option<CompressionArg>,
+
+    /// Re-encode the backups, encrypting them with this passphrase
+    #[arg(long, env = "WALOY_TO_ENCRYPTION_KEY", hide_env_values = true)]
+    to_encryption_key: Option<String>,
+
+    /// File holding the passphrase for `--to-encryption-key`
+    #[arg(
+        long,
+        env = "WALOY_TO_ENCRYPTION_KEY_FILE",
+        conflicts_with = "to_encryption_key"
+    )]
+    to_encryption_key_file: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CompressionArg {
+    None,
+    Lz4,
+    Zstd,
+}
+
+impl From<CompressionArg> for CompressionAlgorithm {
+    fn from(arg: CompressionArg) -> Self {
+        match arg {
+            CompressionArg::None => Self::None,
+            CompressionArg::Lz4 => Self::Lz4,
+            CompressionArg::Zstd => Self::Zstd,
+        }
+    }
 }
 
-fn s3_config(cli: &Cli) -> S3Config {
-    S3Config {
-        endpoint: cli.endpoint.clone(),
-        region: cli.region.clone(),
-        bucket: cli.bucket.clone(),
-        access_key: cli.access_key.clone(),
-        secret_key: cli.secret_key.clone(),
+#[derive(Args)]
+struct ReplicateArgs {
+    /// Database to replicate; repeat for several. With more than one, each is
+    /// stored under `{prefix}/{file stem}`. With `--config`, replicates only
+    /// these of the configured databases
+    #[arg(long = "db")]
+    dbs: Vec<String>,
+
+    /// How often to upload new WAL frames [default: 1s]
+    #[arg(long, value_parser = waloy::parse_duration)]
+    sync_interval: Option<Duration>,
+
+    /// Checkpoint and start a new generation at this interval
+    #[arg(long, value_parser = waloy::parse_duration)]
+    snapshot_interval: Option<Duration>,
+
+    /// Delete generations older than this
+    #[arg(long, value_parser = waloy::parse_duration)]
+    retention: Option<Duration>,
+
+    /// How often to apply `--retention`
+    #[arg(long, default_value = "1h", value_parser = waloy::parse_duration)]
+    retention_check_interval: Duration,
+
+    /// Compact the current generation's WAL segments at this interval
+    #[arg(long, value_parser = waloy::parse_duration)]
+    compact_interval: Option<Duration>,
+
+    /// Upper bound on the size of compacted segments, in bytes
+    #[arg(long)]
+    compact_max_segment_size: Option<usize>,
+
+    /// Restore a database from S3 first if its file does not exist
+    #[arg(long)]
+    auto_restore: bool,
+
+    /// Run this shell command once replication has started, forward SIGTERM
+    /// and SIGINT to it, and exit with its status after a final sync
+    #[arg(long)]
+    exec: Option<String>,
+}
+
+fn s3_config(cli: &Cli) -> anyhow::Result<S3Config> {
+    if let Some(url) = &cli.replica {
+        return replica_url_config(cli, url);
+    }
+    let required = |value: &Option<String>, flag: &str, env: &str| {
+        value.clone().ok_or_else(|| {
+            anyhow::anyhow!("--{flag} (or {env}) is required without --replica or --config")
+        })
+    };
+    Ok(S3Config {
+        endpoint: required(&cli.endpoint, "endpoint", "WALOY_S3_ENDPOINT")?,
+        region: required(&cli.region, "region", "WALOY_S3_REGION")?,
+        bucket: required(&cli.bucket, "bucket", "WALOY_S3_BUCKET")?,
+        access_key: required(&cli.access_key, "access-key", "WALOY_S3_ACCESS_KEY")?,
+        secret_key: required(&cli.secret_key, "secret-key", "WALOY_S3_SECRET_KEY")?,
         prefix: cli.prefix.clone(),
+    })
+}
+
+/// The S3 config of a replica URL, taking the keys from the flags unless
+/// the URL has them.
+fn replica_url_config(cli: &Cli, url: &str) -> anyhow::Result<S3Config> {
+    let mut s3 = S3Config::from_url(url)?;
+    if s3.access_key.is_empty() {
+        s3.access_key = cli.access_key.clone().unwrap_or_default();
     }
+    if s3.secret_key.is_empty() {
+        s3.secret_key = cli.secret_key.clone().unwrap_or_default();
+    }
+    Ok(s3)
 }
 
-// S3 helper for CLI commands that need direct bucket access
-struct CliS3 {
-    bucket: Box<s3::Bucket>,
-    prefix: String,
+/// The passphrase from `--encryption-key` or `--encryption-key-file`.
+fn encryption_key(cli: &Cli) -> anyhow::Result<Option<String>> {
+    read_key(&cli.encryption_key, &cli.encryption_key_file)
 }
 
-fn create_cli_s3(config: &S3Config) -> waloy::Result<CliS3> {
-    use s3::creds::Credentials;
-    use s3::{Bucket, Region};
+/// A passphrase given directly or, trimmed of its final newline, in a file.
+fn read_key(key: &Option<String>, file: &Option<PathBuf>) -> anyhow::Result<Option<String>> {
+    if let Some(path) = file {
+        let key = std::fs::read_to_string(path).map_err(|e| {
+            anyhow::anyhow!("cannot read encryption key file {}: {e}", path.display())
+        })?;
+        let key = key.trim_end_matches(['\r', '\n']);
+        if key.is_empty() {
+            anyhow::bail!("encryption key file {} is empty", path.display());
+        }
+        return Ok(Some(key.to_string()));
+    }
+    Ok(key.clone())
+}
 
-    let region = Region::Custom {
-        region: config.region.clone(),
-        endpoint: config.endpoint.clone(),
+/// Set the encryption key given on the command line, if any, on `configs`.
+fn apply_encryption_key(cli: &Cli, configs: &mut [BackupConfig]) -> anyhow::Result<()> {
+    if let Some(key) = encryption_key(cli)? {
+        for config in configs {
+            config.encryption_key = Some(key.clone());
+        }
+    }
+    Ok(())
+}
+
+/// The config of one database: from `--config`, where `db` picks among
+/// several, or else from the S3 flags.
+fn database_config(cli: &Cli, db: Option<&str>) -> anyhow::Result<BackupConfig> {
+    let mut config = select_database(cli, db)?;
+    apply_encryption_key(cli, std::slice::from_mut(&mut config))?;
+    Ok(config)
+}
+
+fn select_database(cli: &Cli, db: Option<&str>) -> anyhow::Result<BackupConfig> {
+    let Some(path) = &cli.config else {
+        return Ok(BackupConfig {
+            db_path: db.unwrap_or_default().to_string(),
+            s3: s3_config(cli)?,
+            ..Default::default()
+        });
     };
-    let credentials = Credentials::new(
-        Some(&config.access_key),
-        Some(&config.secret_key),
-        None,
-        None,
-        None,
-    )
-    .map_err(|e| waloy::Error::S3(e.to_string()))?;
+    let mut configs = BackupConfig::all_from_file(path)?;
+    match db {
+        Some(db) => configs
+            .into_iter()
+            .find(|c| c.db_path == db)
+            .ok_or_else(|| anyhow::anyhow!("{db} is not configured in {}", path.display())),
+        None if configs.len() == 1 => Ok(configs.remove(0)),
+        None => anyhow::bail!(
+            "{} describes {} databases, choose one with --db",
+            path.display(),
+            configs.len()
+        ),
+    }
+}
 
-    let bucket = Bucket::new(&config.bucket, region, credentials)
-        .map_err(|e| waloy::Error::S3(e.to_string()))?
-        .with_path_style();
+/// Backup configs for `replicate` from `--config`. `--db` selects databases
+/// and the other flags, when given, override the file.
+fn file_replicate_configs(path: &Path, args: &ReplicateArgs) -> anyhow::Result<Vec<BackupConfig>> {
+    let mut configs = BackupConfig::all_from_file(path)?;
+    if let Some(db) = args
+        .dbs
+        .iter()
+        .find(|db| !configs.iter().any(|c| &c.db_path == *db))
+    {
+        anyhow::bail!("{db} is not configured in {}", path.display());
+    }
+    if !args.dbs.is_empty() {
+        configs.retain(|c| args.dbs.contains(&c.db_path));
+    }
+    for config in &mut configs {
+        if let Some(interval) = args.sync_interval {
+            config.sync_interval = interval;
+        }
+        if args.snapshot_interval.is_some() {
+            config.snapshot_interval = args.snapshot_interval;
+        }
+        if args.retention.is_some() {
+            config.retention_duration = args.retention;
+        }
+        config.auto_restore |= args.auto_restore;
+    }
+    Ok(configs)
+}
 
-    Ok(CliS3 {
-        bucket,
-        prefix: config.prefix.clone(),
-    })
+/// Backup configs for `replicate`. A single database uses the prefix as
+/// given, so `restore` with the same flags finds it.
+fn replicate_configs(s3: &S3Config, args: &ReplicateArgs) -> anyhow::Result<Vec<BackupConfig>> {
+    if args.dbs.is_empty() {
+        anyhow::bail!("--db is required without --config");
+    }
+    let mut configs: Vec<BackupConfig> = Vec::new();
+    for db in &args.dbs {
+        let mut s3 = s3.clone();
+        if args.dbs.len() > 1 {
+            let stem = Path::new(db)
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .ok_or_else(|| anyhow::anyhow!("invalid database path: {db}"))?;
+            s3.prefix = if s3.prefix.is_empty() {
+                stem.to_string()
+            } else {
+                format!("{}/{stem}", s3.prefix)
+            };
+            if configs.iter().any(|c| c.s3.prefix == s3.prefix) {
+                anyhow::bail!(
+                    "databases with the same file name would share prefix {}",
+                    s3.prefix
+                );
+            }
+        }
+        let mut config = BackupConfig {
+            db_path: db.clone(),
+            s3,
+            snapshot_interval: args.snapshot_interval,
+            retention_duration: args.retention,
+            auto_restore: args.auto_restore,
+            ..Default::default()
+        };
+        if let Some(interval) = args.sync_interval {
+            config.sync_interval = interval;
+        }
+        configs.push(config);
+    }
+    Ok(configs)
 }
 
-impl CliS3 {
-    fn full_key(&self, key: &str) -> String {
-        if self.prefix.is_empty() {
-            key.to_string()
-        } else {
-            format!("{}/{}", self.prefix, key)
-        }
-    }
-
-    async fn get_object(&self, key: &str) -> waloy::Result<Vec<u8>> {
-        let full_key = self.full_key(key);
-        let response = self
-            .bucket
-            .get_object(&full_key)
-            .await
-            .map_err(|e| waloy::Error::S3(e.to_string()))?;
-        Ok(response.to_vec())
-    }
-
-    async fn list_keys(&self, prefix: &str) -> waloy::Result<Vec<String>> {
-        let full_prefix = self.full_key(prefix);
-        let results = self
-            .bucket
-            .list(full_prefix.clone(), None)
-            .await
-            .map_err(|e| waloy::Error::S3(e.to_string()))?;
-
-        let mut keys = Vec::new();
-        for page in &results {
-            for obj in &page.contents {
-                let rel = if self.prefix.is_empty() {
-                    obj.key.clone()
-                } else {
-                    obj.key
-                        .strip_prefix(&format!("{}/", self.prefix))
-                        .unwrap_or(&obj.key)
-                        .to_string()
-                };
-                keys.push(rel);
+/// Completes on SIGINT or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+                return;
             }
+            Err(e) => eprintln!("cannot listen for SIGTERM: {e}"),
         }
-        keys.sort();
-        Ok(keys)
     }
+    let _ = tokio::signal::ctrl_c().await;
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
-    let s3 = s3_config(&cli);
+/// Print one line of JSON with `db` added to `value`'s fields.
+fn print_json_line(db: &str, value: impl Serialize) {
+    let mut value = serde_json::to_value(value).unwrap_or_default();
+    if let Some(fields) = value.as_object_mut() {
+        fields.insert("db".into(), db.into());
+    }
+    println!("{value}");
+}
 
-    match cli.command {
-        Commands::Restore { output, timestamp } => {
-            if let Some(ts) = timestamp {
-                println!("Restoring to point-in-time: {ts}ms");
-                BackupManager::restore_to_time(&s3, &output, ts).await?;
+/// Report replication activity of one database on stdout and stderr, or as
+/// JSON lines on stdout.
+fn print_events(
+    db: String,
+    mut events: tokio::sync::broadcast::Receiver<ReplicationEvent>,
+    format: OutputFormat,
+) {
+    tokio::spawn(async move {
+        use tokio::sync::broadcast::error::RecvError;
+        loop {
+            let event = events.recv().await;
+            if format == OutputFormat::Json {
+                match event {
+                    Ok(event) => print_json_line(&db, event),
+                    Err(RecvError::Lagged(missed)) => print_json_line(
+                        &db,
+                        serde_json::json!({ "type": "events_missed", "missed": missed }),
+                    ),
+                    Err(RecvError::Closed) => return,
+                }
+                continue;
+            }
+            match event {
+                Ok(ReplicationEvent::GenerationStarted { generation, reason }) => {
+                    println!("{db}: new generation {generation} ({reason:?})");
+                }
+                Ok(ReplicationEvent::SyncFailed { error, .. }) => {
+                    eprintln!("{db}: WAL sync failed: {error}");
+                }
+                Ok(ReplicationEvent::SnapshotFailed { error, .. }) => {
+                    eprintln!("{db}: snapshot failed: {error}");
+                }
+                Ok(ReplicationEvent::RetentionDeleted { generations }) => {
+                    println!("{db}: retention deleted {} generations", generations.len());
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return,
+            }
+        }
+    });
+}
+
+/// Apply retention (if `retain`) and compaction to every replica on their
+/// intervals.
+async fn maintain(set: &ReplicaSet, args: &ReplicateArgs, retain: bool, format: OutputFormat) {
+    let start = tokio::time::Instant::now();
+    let mut retention = retain.then(|| {
+        tokio::time::interval_at(
+            start + args.retention_check_interval,
+            args.retention_check_interval,
+        )
+    });
+    let mut compaction = args
+        .compact_interval
+        .map(|i| tokio::time::interval_at(start + i, i));
+    loop {
+        let compact = tokio::select! {
+            _ = tick(&mut retention) => false,
+            _ = tick(&mut compaction) => true,
+        };
+        for db in set.db_paths().await {
+            let Some(mgr) = set.get(&db).await else {
+                continue;
+            };
+            let mut mgr = mgr.lock().await;
+            let result = if compact {
+                mgr.compact(args.compact_max_segment_size).await.map(|_| ())
             } else {
-                println!("Restoring latest backup...");
-                BackupManager::restore(&s3, &output).await?;
+                mgr.enforce_retention().await.map(|_| ())
+            };
+            if let Err(e) = result {
+                let task = if compact { "compaction" } else { "retention" };
+                match format {
+                    OutputFormat::Text => eprintln!("{db}: {task} failed: {e}"),
+                    OutputFormat::Json => print_json_line(
+                        &db,
+                        serde_json::json!({
+                            "type": "maintenance_failed",
+                            "task": task,
+                            "kind": e.kind(),
+                            "error": e.to_string(),
+                        }),
+                    ),
+                }
             }
-            println!("Restored to: {output}");
         }
-        Commands::Generations => {
-            let client = create_cli_s3(&s3)?;
-            let keys = client.list_keys("").await?;
+    }
+}
+
+/// Wait for the next tick of an optional interval; never completes if unset.
+async fn tick(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(i) => {
+            i.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
 
-            let mut generations: Vec<String> = keys
-                .iter()
-                .filter(|k| k.ends_with("/manifest.json"))
-                .map(|k| k.trim_end_matches("/manifest.json").to_string())
-                .collect();
-            generations.sort();
+/// Wait for the child to exit, forwarding SIGTERM and SIGINT to it.
+async fn supervise(mut child: tokio::process::Child) -> std::io::Result<std::process::ExitStatus> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        let mut term = signal(SignalKind::terminate())?;
+        let mut int = signal(SignalKind::interrupt())?;
+        let forward = |child: &tokio::process::Child, sig| {
+            if let Some(pid) = child.id() {
+                // SAFETY: kill(2) only sends a signal; an exited child is harmless.
+                unsafe { libc::kill(pid as libc::pid_t, sig) };
+            }
+        };
+        loop {
+            tokio::select! {
+                status = child.wait() => return status,
+                _ = term.recv() => forward(&child, libc::SIGTERM),
+                _ = int.recv() => forward(&child, libc::SIGINT),
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    child.wait().await
+}
 
-            let latest = client
-                .get_object("latest")
-                .await
-                .ok()
-                .and_then(|b| String::from_utf8(b).ok());
+/// The exit code to pass on for a child's exit status; 128 plus the signal
+/// number if it was killed by a signal, like a shell.
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    #[cfg(unix)]
+    if let Some(sig) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return 128 + sig;
+    }
+    status.code().unwrap_or(1)
+}
 
-            println!("Generations ({}):", generations.len());
-            for generation in &generations {
-                let marker = if latest.as_deref() == Some(generation) {
-                    " (latest)"
-                } else {
-                    ""
+/// Replicate until a signal arrives or, with `--exec`, the child exits.
+/// Returns the exit code for the process.
+async fn replicate(
+    configs: Vec<BackupConfig>,
+    args: &ReplicateArgs,
+    format: OutputFormat,
+) -> anyhow::Result<i32> {
+    let retain = configs.iter().any(|c| c.retention_duration.is_some());
+    let set = ReplicaSet::new(configs.len());
+    for config in configs {
+        let db = config.db_path.clone();
+        let target = config.s3.url();
+        set.add(config).await?;
+        if let Some(mgr) = set.get(&db).await {
+            print_events(db.clone(), mgr.lock().await.subscribe(), format);
+        }
+        match format {
+            OutputFormat::Text => println!("Replicating {db} to {target}"),
+            OutputFormat::Json => print_json_line(
+                &db,
+                serde_json::json!({ "type": "replicating", "replica": target }),
+            ),
+        }
+    }
+
+    let child = match &args.exec {
+        Some(command) => {
+            let spawned = tokio::process::Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .spawn();
+            match spawned {
+                Ok(child) => Some(child),
+                Err(e) => {
+                    set.shutdown().await?;
+                    anyhow::bail!("cannot run {command:?}: {e}");
+                }
+            }
+        }
+        None => None,
+    };
+
+    let mut exit = None;
+    let stopped = async {
+        match child {
+            Some(child) => exit = Some(supervise(child).await),
+            None => shutdown_signal().await,
+        }
+    };
+    tokio::select! {
+        result = set.run(stopped) => result?,
+        _ = maintain(&set, args, retain, format) => unreachable!("maintenance runs until shutdown"),
+    }
+    let code = match exit {
+        Some(status) => exit_code(status?),
+        None => 0,
+    };
+    match format {
+        OutputFormat::Text => println!("Shut down after final sync"),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::json!({ "type": "shutdown", "exit_code": code })
+        ),
+    }
+    Ok(code)
+}
+
+/// Format milliseconds since the Unix epoch as an RFC 3339 UTC timestamp.
+fn format_time(ms: u64) -> String {
+    let secs = ms / 1000;
+    let (days, rem) = (secs / 86400, secs % 86400);
+    // Civil date from days since 1970-01-01, after Howard Hinnant's algorithm.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        ms % 1000
+    )
+}
+
+/// Parse a `--at` time: milliseconds since the Unix epoch, or an RFC 3339
+/// timestamp such as `2026-10-18T12:00:00Z` or `2026-10-18T14:00:00+02:00`.
+fn parse_time(s: &str) -> Result<u64, String> {
+    if let Ok(ms) = s.parse() {
+        return Ok(ms);
+    }
+    let invalid = || format!("invalid time {s:?}: expected milliseconds or RFC 3339");
+    let (date, time) = s.split_once(['T', 't', ' ']).ok_or_else(invalid)?;
+    let (time, offset_secs) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+        (time, 0)
+    } else {
+        let sign_at = time.rfind(['+', '-']).ok_or_else(invalid)?;
+        let (time, offset) = time.split_at(sign_at);
+        let (hours, minutes) = offset[1..].split_once(':').ok_or_else(invalid)?;
+        let hours: i64 = hours.parse().map_err(|_| invalid())?;
+        let minutes: i64 = minutes.parse().map_err(|_| invalid())?;
+        let secs = hours * 3600 + minutes * 60;
+        (time, if offset.starts_with('-') { -secs } else { secs })
+    };
+    let numbers = |part: &str, sep: char| -> Result<Vec<i64>, String> {
+        part.split(sep)
+            .map(|n| n.parse().map_err(|_| invalid()))
+            .collect()
+    };
+    let [year, month, day] = numbers(date, '-')?[..] else {
+        return Err(invalid());
+    };
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let [hour, minute, second] = numbers(time, ':')?[..] else {
+        return Err(invalid());
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 {
+        return Err(invalid());
+    }
+    let millis: i64 = format!("{fraction:0<3}")[..3]
+        .parse()
+        .map_err(|_| invalid())?;
+
+    // Days since 1970-01-01, the inverse of `format_time`.
+    let y = year - i64::from(month <= 2);
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let days = era * 146_097 + yoe * 365 + yoe / 4 - yoe / 100 + doy - 719_468;
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second - offset_secs;
+    u64::try_from(secs * 1000 + millis).map_err(|_| invalid())
+}
+
+/// Format a byte count with a binary unit.
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+fn print_json(value: &impl Serialize) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// A generation in `generations` and `inspect` JSON output.
+#[derive(Serialize)]
+struct GenerationReport {
+    latest: bool,
+    /// Earliest and latest point a restore of this generation reaches.
+    restorable_from_ms: Option<u64>,
+    restorable_to_ms: Option<u64>,
+    #[serde(flatten)]
+    info: GenerationInfo,
+}
+
+impl GenerationReport {
+    fn new(info: GenerationInfo, latest: Option<&str>) -> Self {
+        let manifest = info.manifest.as_ref();
+        Self {
+            latest: latest == Some(info.generation.as_str()),
+            restorable_from_ms: manifest.map(|m| m.snapshot_timestamp_ms),
+            restorable_to_ms: manifest.map(|m| {
+                m.segments
+                    .last()
+                    .map_or(m.snapshot_timestamp_ms, |s| s.timestamp_ms)
+            }),
+            info,
+        }
+    }
+}
+
+/// `inspect` JSON output: the generation and each WAL segment with its
+/// stored object.
+#[derive(Serialize)]
+struct InspectReport {
+    #[serde(flatten)]
+    generation: GenerationReport,
+    segments: Vec<SegmentReport>,
+}
+
+#[derive(Serialize)]
+struct SegmentReport {
+    #[serde(flatten)]
+    meta: waloy::SegmentMeta,
+    key: String,
+    /// Size of the stored object; `None` if it is missing.
+    stored_bytes: Option<u64>,
+}
+
+impl InspectReport {
+    fn new(generation: GenerationReport) -> Self {
+        let info = &generation.info;
+        let segments = info
+            .manifest
+            .iter()
+            .flat_map(|m| &m.segments)
+            .map(|meta| {
+                let key = format!("{}/wal/{:08}", info.generation, meta.index);
+                let stored_bytes = info.objects.iter().find(|o| o.key == key).map(|o| o.size);
+                SegmentReport {
+                    meta: meta.clone(),
+                    key,
+                    stored_bytes,
+                }
+            })
+            .collect();
+        Self {
+            generation,
+            segments,
+        }
+    }
+}
+
+/// The result of `restore` in JSON output.
+#[derive(Serialize)]
+struct RestoreReport {
+    target: String,
+    database_bytes: u64,
+    duration_ms: u64,
+    #[serde(flatten)]
+    plan: waloy::RestorePlan,
+}
+
+/// The result of `prune` in JSON output.
+#[derive(Serialize)]
+struct PruneReport {
+    dry_run: bool,
+    #[serde(flatten)]
+    result: waloy::PruneResult,
+}
+
+/// The result of `compact` in JSON output.
+#[derive(Serialize)]
+struct CompactReport {
+    generation: String,
+    #[serde(flatten)]
+    result: waloy::CompactionResult,
+}
+
+/// The result of `snapshot` in JSON output.
+#[derive(Serialize)]
+struct SnapshotReport {
+    generation: String,
+    duration_ms: u64,
+}
+
+/// `wal-dump` JSON output.
+#[derive(Serialize)]
+struct WalDumpReport {
+    generation: String,
+    header: waloy::WalHeader,
+    segments: Vec<SegmentDump>,
+    /// Bytes after the last complete frame of the WAL.
+    trailing_bytes: u64,
+}
+
+#[derive(Serialize)]
+struct SegmentDump {
+    #[serde(flatten)]
+    meta: waloy::SegmentMeta,
+    /// Where the segment starts in the WAL replayed on restore.
+    wal_offset: u64,
+    /// Frames whose header starts in this segment.
+    frames: Vec<waloy::WalFrame>,
+}
+
+impl WalDumpReport {
+    /// Split the frames of the WAL formed by `segments` by the segment they
+    /// start in, keeping only segment `only` if given.
+    fn new(
+        generation: String,
+        segments: Vec<(waloy::SegmentMeta, Vec<u8>)>,
+        only: Option<u32>,
+    ) -> anyhow::Result<Self> {
+        let wal = segments.iter().flat_map(|(_, data)| data).copied();
+        let wal = waloy::WalFile::parse(&wal.collect::<Vec<u8>>())?;
+        let mut frames = wal.frames.into_iter().peekable();
+        let mut wal_offset = 0;
+        let mut dumps = Vec::new();
+        for (meta, data) in segments {
+            let end = wal_offset + data.len() as u64;
+            let mut dump = SegmentDump {
+                meta,
+                wal_offset,
+                frames: Vec::new(),
+            };
+            while let Some(frame) = frames.next_if(|f| f.offset < end) {
+                dump.frames.push(frame);
+            }
+            if only.is_none_or(|index| index == dump.meta.index) {
+                dumps.push(dump);
+            }
+            wal_offset = end;
+        }
+        Ok(Self {
+            generation,
+            header: wal.header,
+            segments: dumps,
+            trailing_bytes: wal.trailing_bytes,
+        })
+    }
+}
+
+/// The result of `copy` in JSON output.
+#[derive(Serialize)]
+struct CopyReport {
+    from: String,
+    to: String,
+    reencoded: bool,
+    duration_ms: u64,
+    #[serde(flatten)]
+    result: waloy::CopyResult,
+}
+
+/// Copy the backups of `--from` to `--to`, re-encoding them when a
+/// compression or destination key is given.
+async fn copy(cli: &Cli, args: &CopyArgs) -> anyhow::Result<CopyReport> {
+    let mut from = BackupConfig {
+        s3: replica_url_config(cli, &args.from)?,
+        ..Default::default()
+    };
+    apply_encryption_key(cli, std::slice::from_mut(&mut from))?;
+    let to_key = read_key(&args.to_encryption_key, &args.to_encryption_key_file)?;
+    let reencode = args.compression.is_some() || to_key.is_some();
+    let to = BackupConfig {
+        s3: replica_url_config(cli, &args.to)?,
+        compression: args.compression.map(Into::into).unwrap_or_default(),
+        encryption_key: to_key,
+        ..Default::default()
+    };
+
+    let started = std::time::Instant::now();
+    let result = BackupManager::copy_replica(&from, &to, reencode).await?;
+    Ok(CopyReport {
+        from: from.s3.url(),
+        to: to.s3.url(),
+        reencoded: reencode,
+        duration_ms: started.elapsed().as_millis() as u64,
+        result,
+    })
+}
+
+/// The result of `verify` in JSON output.
+#[derive(Serialize)]
+struct VerifyReport {
+    passed: bool,
+    database_bytes: u64,
+    restore_ms: u64,
+    check_ms: u64,
+    /// Rows of `PRAGMA integrity_check`; `["ok"]` for an intact database.
+    integrity: Vec<String>,
+    assertions: Vec<AssertionReport>,
+    #[serde(flatten)]
+    plan: waloy::RestorePlan,
+}
+
+#[derive(Serialize)]
+struct AssertionReport {
+    sql: String,
+    passed: bool,
+    /// First column of the first row the query returned.
+    value: Option<serde_json::Value>,
+    error: Option<String>,
+}
+
+impl AssertionReport {
+    fn run(conn: &rusqlite::Connection, sql: &str) -> Self {
+        use rusqlite::types::Value;
+
+        let value = conn.query_row(sql, [], |row| row.get::<_, Value>(0));
+        let (passed, value, error) = match value {
+            Ok(value) => {
+                let passed = match value {
+                    Value::Integer(i) => i != 0,
+                    Value::Real(f) => f != 0.0,
+                    _ => false,
+                };
+                let json = match value {
+                    Value::Null => serde_json::Value::Null,
+                    Value::Integer(i) => i.into(),
+                    Value::Real(f) => f.into(),
+                    Value::Text(t) => t.into(),
+                    Value::Blob(b) => format!("<{} byte blob>", b.len()).into(),
                 };
-                let manifest_key = format!("{generation}/manifest.json");
-                if let Ok(data) = client.get_object(&manifest_key).await
-                    && let Ok(m) = serde_json::from_slice::<waloy::GenerationManifest>(&data)
-                {
+                (passed, Some(json), None)
+            }
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                (false, None, Some("query returned no rows".into()))
+            }
+            Err(e) => (false, None, Some(e.to_string())),
+        };
+        Self {
+            sql: sql.to_string(),
+            passed,
+            value,
+            error,
+        }
+    }
+}
+
+/// Restore the backup into a temporary directory, then run `PRAGMA
+/// integrity_check` and the assertions against it.
+async fn verify(
+    config: &BackupConfig,
+    generation: Option<&str>,
+    at: Option<u64>,
+    assertions: &[String],
+) -> anyhow::Result<VerifyReport> {
+    let plan = match generation {
+        Some(generation) => BackupManager::generation_restore_plan(config, generation, at).await?,
+        None => BackupManager::restore_plan(config, at).await?,
+    };
+    let dir = tempfile::tempdir()?;
+    let target = dir.path().join("verify.db");
+    let target = target
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("temporary path is not UTF-8"))?;
+
+    let started = std::time::Instant::now();
+    BackupManager::restore_generation_with_config(config, &plan.generation, target, at).await?;
+    let restore_ms = started.elapsed().as_millis() as u64;
+
+    let started = std::time::Instant::now();
+    let conn = rusqlite::Connection::open(target)?;
+    let integrity = conn
+        .prepare("PRAGMA integrity_check")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+    let assertions: Vec<AssertionReport> = assertions
+        .iter()
+        .map(|sql| AssertionReport::run(&conn, sql))
+        .collect();
+    drop(conn);
+
+    Ok(VerifyReport {
+        passed: integrity == ["ok"] && assertions.iter().all(|a| a.passed),
+        database_bytes: std::fs::metadata(target)?.len(),
+        restore_ms,
+        check_ms: started.elapsed().as_millis() as u64,
+        integrity,
+        assertions,
+        plan,
+    })
+}
+
+/// Run the command and return the exit code for the process.
+async fn run(cli: &Cli) -> anyhow::Result<i32> {
+    let format = cli.output;
+    match &cli.command {
+        Commands::Restore {
+            target,
+            timestamp,
+            db,
+        } => {
+            let config = database_config(cli, db.as_deref())?;
+            let started = std::time::Instant::now();
+            if format == OutputFormat::Text {
+                match timestamp {
+                    Some(ts) => println!("Restoring to point-in-time: {}", format_time(*ts)),
+                    None => println!("Restoring latest backup..."),
+                }
+            }
+            let plan = BackupManager::restore_plan(&config, *timestamp).await?;
+            match *timestamp {
+                Some(ts) => BackupManager::restore_to_time_with_config(&config, target, ts).await?,
+                None => BackupManager::restore_with_config(&config, target).await?,
+            }
+            let report = RestoreReport {
+                target: target.clone(),
+                database_bytes: std::fs::metadata(target)?.len(),
+                duration_ms: started.elapsed().as_millis() as u64,
+                plan,
+            };
+            match format {
+                OutputFormat::Text => {
+                    println!("Restored to: {}", report.target);
                     println!(
-                        "  {generation}{marker}  created={}  segments={}",
-                        m.created_at_ms,
-                        m.segments.len()
+                        "  generation {} as of {}, {} WAL segments, {}, took {:.1}s",
+                        report.plan.generation,
+                        format_time(report.plan.restores_to_ms),
+                        report.plan.segments.len(),
+                        format_bytes(report.database_bytes),
+                        report.duration_ms as f64 / 1000.0
                     );
-                    continue;
                 }
-                println!("  {generation}{marker}");
+                OutputFormat::Json => print_json(&report)?,
+            }
+        }
+        Commands::Generations { db } => {
+            let config = database_config(cli, db.as_deref())?;
+            let latest = BackupManager::latest_generation_id(&config).await.ok();
+            let mut generations = Vec::new();
+            for generation in BackupManager::list_generation_ids(&config).await? {
+                let info = BackupManager::generation_info(&config, &generation).await?;
+                generations.push(GenerationReport::new(info, latest.as_deref()));
+            }
+            if format == OutputFormat::Json {
+                print_json(&generations)?;
+                return Ok(0);
+            }
+
+            println!("Generations ({}):", generations.len());
+            for report in &generations {
+                let marker = if report.latest { " (latest)" } else { "" };
+                let info = &report.info;
+                let size = format_bytes(info.total_bytes);
+                match &info.manifest {
+                    Some(m) => println!(
+                        "  {}{marker}  created={}  segments={}  size={size}",
+                        info.generation,
+                        format_time(m.created_at_ms),
+                        m.segments.len()
+                    ),
+                    None => println!("  {}{marker}  size={size}", info.generation),
+                }
+            }
+        }
+        Commands::Verify {
+            generation,
+            at,
+            assertions,
+            db,
+        } => {
+            let config = database_config(cli, db.as_deref())?;
+            let report = verify(&config, generation.as_deref(), *at, assertions).await?;
+            let code = if report.passed { 0 } else { 2 };
+            if format == OutputFormat::Json {
+                print_json(&report)?;
+                return Ok(code);
+            }
+
+            println!(
+                "Generation {} as of {}, {} WAL segments",
+                report.plan.generation,
+                format_time(report.plan.restores_to_ms),
+                report.plan.segments.len()
+            );
+            println!(
+                "Restored {} in {:.1}s",
+                format_bytes(report.database_bytes),
+                report.restore_ms as f64 / 1000.0
+            );
+            match &report.integrity[..] {
+                [ok] if ok == "ok" => println!("Integrity check: ok"),
+                problems => {
+                    println!("Integrity check: FAILED");
+                    for problem in problems {
+                        println!("  {problem}");
+                    }
+                }
             }
+            for assertion in &report.assertions {
+                let status = if assertion.passed { "ok" } else { "FAILED" };
+                let detail = match (&assertion.value, &assertion.error) {
+                    (_, Some(error)) => format!(" ({error})"),
+                    (Some(value), None) => format!(" = {value}"),
+                    (None, None) => String::new(),
+                };
+                println!("Assertion {status}: {}{detail}", assertion.sql);
+            }
+            let outcome = if report.passed { "passed" } else { "FAILED" };
+            println!(
+                "Verification {outcome} in {:.1}s",
+                (report.restore_ms + report.check_ms) as f64 / 1000.0
+            );
+            return Ok(code);
         }
-        Commands::Inspect { generation } => {
-            let client = create_cli_s3(&s3)?;
+        Commands::Prune {
+            older_than,
+            keep_last,
+            dry_run,
+            force,
+            db,
+        } => {
+            let config = database_config(cli, db.as_deref())?;
+            let result =
+                BackupManager::prune(&config, *older_than, *keep_last, *dry_run, *force).await?;
+            let report = PruneReport {
+                dry_run: *dry_run,
+                result,
+            };
+            if format == OutputFormat::Json {
+                print_json(&report)?;
+                return Ok(0);
+            }
 
+            let verb = if report.dry_run {
+                "Would delete"
+            } else {
+                "Deleted"
+            };
+            let result = &report.result;
+            println!(
+                "{verb} {} generations ({} objects, {}), {} retained",
+                result.deleted.len(),
+                result.objects,
+                format_bytes(result.bytes),
+                result.retained.len()
+            );
+            for generation in &result.deleted {
+                println!("  {generation}");
+            }
+        }
+        Commands::Compact {
+            generation,
+            max_segment_size,
+            force,
+            db,
+        } => {
+            let config = database_config(cli, db.as_deref())?;
+            let latest = BackupManager::latest_generation_id(&config).await.ok();
+            if !force && latest.as_deref() == Some(generation.as_str()) {
+                anyhow::bail!(
+                    "generation {generation} is the latest, which `replicate` may still be \
+                     writing to; pass --force if it is not running"
+                );
+            }
+            let result =
+                BackupManager::compact_generation(&config, generation, *max_segment_size).await?;
+            let report = CompactReport {
+                generation: generation.clone(),
+                result,
+            };
+            match format {
+                OutputFormat::Text => println!(
+                    "Compacted generation {}: {} segments -> {}",
+                    report.generation, result.segments_before, result.segments_after
+                ),
+                OutputFormat::Json => print_json(&report)?,
+            }
+        }
+        Commands::Snapshot { db } => {
+            let config = database_config(cli, db.as_deref())?;
+            if config.db_path.is_empty() {
+                anyhow::bail!("--db is required");
+            }
+            let started = std::time::Instant::now();
+            let mut manager = BackupManager::new(config).await?;
+            let generation = manager.generation().to_string();
+            manager.shutdown().await?;
+            let report = SnapshotReport {
+                generation,
+                duration_ms: started.elapsed().as_millis() as u64,
+            };
+            match format {
+                OutputFormat::Text => println!(
+                    "Snapshot uploaded as generation {} in {:.1}s",
+                    report.generation,
+                    report.duration_ms as f64 / 1000.0
+                ),
+                OutputFormat::Json => print_json(&report)?,
+            }
+        }
+        Commands::WalDump {
+            generation,
+            segment,
+            db,
+        } => {
+            let config = database_config(cli, db.as_deref())?;
             let gen_id = match generation {
-                Some(g) => g,
-                None => {
-                    let bytes = client.get_object("latest").await?;
-                    String::from_utf8(bytes).map_err(|e| waloy::Error::Other(e.to_string()))?
+                Some(generation) => generation.clone(),
+                None => BackupManager::latest_generation_id(&config).await?,
+            };
+            let segments = BackupManager::download_wal_segments(&config, &gen_id, *segment).await?;
+            if segments.is_empty() {
+                anyhow::bail!("generation {gen_id} has no WAL segments");
+            }
+            let report = WalDumpReport::new(gen_id, segments, *segment)?;
+            if format == OutputFormat::Json {
+                print_json(&report)?;
+                return Ok(0);
+            }
+
+            let header = &report.header;
+            let endianness = if header.big_endian_checksums() {
+                "big"
+            } else {
+                "little"
+            };
+            println!("Generation: {}", report.generation);
+            println!(
+                "WAL header: magic={:#010x} ({endianness}-endian checksums) version={} \
+                 page_size={} checkpoint_seq={} salt={:08x}-{:08x} checksum={}",
+                header.magic,
+                header.format_version,
+                header.page_size,
+                header.checkpoint_seq,
+                header.salt[0],
+                header.salt[1],
+                if header.checksum_valid { "ok" } else { "BAD" }
+            );
+            for dump in &report.segments {
+                println!(
+                    "Segment [{:08}] wal_offset={} size={} timestamp={} frames={}",
+                    dump.meta.index,
+                    dump.wal_offset,
+                    dump.meta.size,
+                    format_time(dump.meta.timestamp_ms),
+                    dump.frames.len()
+                );
+                for frame in &dump.frames {
+                    let status = match (frame.salt_valid, frame.checksum_valid) {
+                        (true, true) => "ok",
+                        (false, _) => "BAD salt",
+                        (true, false) => "BAD checksum",
+                    };
+                    println!(
+                        "  frame {} offset={} page={} commit={} salt={:08x}-{:08x} \
+                         checksum={:08x}-{:08x} {status}",
+                        frame.frame,
+                        frame.offset,
+                        frame.page_number,
+                        frame.commit_size,
+                        frame.salt[0],
+                        frame.salt[1],
+                        frame.checksum[0],
+                        frame.checksum[1]
+                    );
                 }
+            }
+            let frames = report.segments.iter().flat_map(|d| &d.frames);
+            let (count, commits, invalid) = frames.fold((0, 0, 0), |(n, c, i), f| {
+                (
+                    n + 1,
+                    c + usize::from(f.commit_size > 0),
+                    i + usize::from(!f.is_valid()),
+                )
+            });
+            println!("Frames: {count} ({commits} commits, {invalid} invalid)");
+            if report.trailing_bytes > 0 {
+                println!(
+                    "{} bytes after the last complete frame",
+                    report.trailing_bytes
+                );
+            }
+        }
+        Commands::Copy(args) => {
+            let report = copy(cli, args).await?;
+            if format == OutputFormat::Json {
+                print_json(&report)?;
+                return Ok(0);
+            }
+            let result = &report.result;
+            println!("Copied {} to {}", report.from, report.to);
+            println!(
+                "  {} generations, {} objects written ({}{}), {} already present, took {:.1}s",
+                result.generations,
+                result.objects_copied,
+                format_bytes(result.bytes_copied),
+                if report.reencoded { ", re-encoded" } else { "" },
+                result.objects_skipped,
+                report.duration_ms as f64 / 1000.0
+            );
+        }
+        Commands::Replicate(args) => {
+            let mut configs = match &cli.config {
+                Some(path) => file_replicate_configs(path, args)?,
+                None => replicate_configs(&s3_config(cli)?, args)?,
             };
+            apply_encryption_key(cli, &mut configs)?;
+            return replicate(configs, args, format).await;
+        }
+        Commands::Inspect { generation, db } => {
+            let config = database_config(cli, db.as_deref())?;
+            let latest = BackupManager::latest_generation_id(&config).await?;
+            let gen_id = generation.clone().unwrap_or_else(|| latest.clone());
+            let info = BackupManager::generation_info(&config, &gen_id).await?;
+            let report = InspectReport::new(GenerationReport::new(info, Some(&latest)));
+            if format == OutputFormat::Json {
+                print_json(&report)?;
+                return Ok(0);
+            }
 
+            let info = &report.generation.info;
             println!("Generation: {gen_id}");
-
-            let manifest_key = format!("{gen_id}/manifest.json");
-            match client.get_object(&manifest_key).await {
-                Ok(data) => {
-                    let m: waloy::GenerationManifest = serde_json::from_slice(&data)
-                        .map_err(|e| waloy::Error::Other(e.to_string()))?;
-                    println!("Created: {}ms", m.created_at_ms);
-                    println!("Snapshot timestamp: {}ms", m.snapshot_timestamp_ms);
+            let encoding = match (info.encrypted, info.compression) {
+                (Some(encrypted), Some(compression)) => format!(
+                    ", compression {compression}{}",
+                    if encrypted { ", encrypted" } else { "" }
+                ),
+                _ => String::new(),
+            };
+            println!(
+                "Stored: {} in {} objects{encoding}",
+                format_bytes(info.total_bytes),
+                info.objects.len()
+            );
+            match &info.manifest {
+                Some(m) => {
+                    println!("Created: {}", format_time(m.created_at_ms));
+                    println!(
+                        "Snapshot timestamp: {}",
+                        format_time(m.snapshot_timestamp_ms)
+                    );
+                    if let Some(base) = &m.base_generation {
+                        println!("Delta snapshot on top of: {base}");
+                    }
                     println!("WAL segments: {}", m.segments.len());
-                    for seg in &m.segments {
+                    for seg in &report.segments {
+                        let stored = seg.stored_bytes.map_or("missing".into(), format_bytes);
                         println!(
-                            "  [{:08}] offset={} size={} timestamp={}ms",
-                            seg.index, seg.offset, seg.size, seg.timestamp_ms
+                            "  [{:08}] offset={} size={} stored={stored} timestamp={}",
+                            seg.meta.index,
+                            seg.meta.offset,
+                            seg.meta.size,
+                            format_time(seg.meta.timestamp_ms)
                         );
                     }
                 }
-                Err(_) => {
+                None => {
                     println!("No manifest found (legacy generation without manifest)");
-                    let prefix = format!("{gen_id}/");
-                    let keys = client.list_keys(&prefix).await?;
-                    for key in &keys {
-                        println!("  {key}");
+                    for object in &info.objects {
+                        println!("  {}  {}", object.key, format_bytes(object.size));
                     }
                 }
             }
         }
     }
+    Ok(0)
+}
 
-    Ok(())
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let code = match run(&cli).await {
+        Ok(code) => code,
+        Err(e) => {
+            match cli.output {
+                OutputFormat::Text => eprintln!("Error: {e:?}"),
+                OutputFormat::Json => {
+                    eprintln!("{}", serde_json::json!({ "error": format!("{e:#}") }))
+                }
+            }
+            1
+        }
+    };
+    if code != 0 {
+        std::process::exit(code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replicate_args(dbs: &[&str]) -> ReplicateArgs {
+        let cli = Cli::parse_from(
+            [
+                "waloy",
+                "--endpoint=e",
+                "--region=r",
+                "--bucket=b",
+                "--access-key=a",
+                "--secret-key=s",
+                "--prefix=backups",
+                "replicate",
+            ]
+            .into_iter()
+            .chain(dbs.iter().flat_map(|db| ["--db", db])),
+        );
+        match cli.command {
+            Commands::Replicate(args) => args,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn formats_times_and_sizes() {
+        assert_eq!(format_time(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(format_time(951_782_400_123), "2000-02-29T00:00:00.123Z");
+        assert_eq!(format_time(1_792_324_799_999), "2026-10-18T11:59:59.999Z");
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 << 30), "3.0 GiB");
+    }
+
+    #[test]
+    fn parses_times() {
+        assert_eq!(parse_time("1792324799999"), Ok(1_792_324_799_999));
+        assert_eq!(parse_time("2000-02-29T00:00:00.123Z"), Ok(951_782_400_123));
+        assert_eq!(
+            parse_time("2026-10-18T11:59:59.999Z"),
+            Ok(1_792_324_799_999)
+        );
+        assert_eq!(
+            parse_time("2026-10-18T13:59:59.999+02:00"),
+            Ok(1_792_324_799_999)
+        );
+        assert_eq!(parse_time("1970-01-01 00:00:01-00:30"), Ok(1_801_000));
+        assert!(parse_time("yesterday").is_err());
+        assert!(parse_time("2026-13-01T00:00:00Z").is_err());
+        assert!(parse_time("1969-12-31T23:59:59Z").is_err());
+    }
+
+    #[test]
+    fn single_database_keeps_prefix() {
+        let s3 = S3Config {
+            prefix: "backups".into(),
+            ..BackupConfig::default().s3
+        };
+        let configs = replicate_configs(&s3, &replicate_args(&["/data/app.db"])).unwrap();
+        assert_eq!(configs[0].s3.prefix, "backups");
+        assert_eq!(configs[0].sync_interval, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn several_databases_get_own_prefixes() {
+        let s3 = S3Config {
+            prefix: "backups".into(),
+            ..BackupConfig::default().s3
+        };
+        let args = replicate_args(&["/data/a.db", "/data/b.db"]);
+        let configs = replicate_configs(&s3, &args).unwrap();
+        assert_eq!(configs[0].s3.prefix, "backups/a");
+        assert_eq!(configs[1].s3.prefix, "backups/b");
+
+        let args = replicate_args(&["/data/a.db", "/other/a.db"]);
+        assert!(replicate_configs(&s3, &args).is_err());
+    }
+
+    #[test]
+    fn flags_override_config_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("waloy.yaml");
+        std::fs::write(
+            &path,
+            "defaults:
+  snapshot_interval: 6h
+  replica: { endpoint: e, region: r, bucket: b, access_key: a, secret_key: s }
+databases:
+  - { path: /data/a.db, replica: { prefix: a } }
+  - { path: /data/b.db, replica: { prefix: b } }
+",
+        )
+        .unwrap();
+        let cli = Cli::parse_from([
+            "waloy",
+            "--config",
+            path.to_str().unwrap(),
+            "replicate",
+            "--db",
+            "/data/b.db",
+            "--sync-interval",
+            "5s",
+        ]);
+        let Commands::Replicate(args) = &cli.command else {
+            unreachable!()
+        };
+        let configs = file_replicate_configs(&path, args).unwrap();
+        assert_eq!(configs.len(), 1);
+        assert_eq!(configs[0].s3.prefix, "b");
+        assert_eq!(configs[0].sync_interval, Duration::from_secs(5));
+        assert_eq!(
+            configs[0].snapshot_interval,
+            Some(Duration::from_secs(6 * 3600))
+        );
+
+        assert_eq!(
+            database_config(&cli, Some("/data/a.db")).unwrap().s3.prefix,
+            "a"
+        );
+        assert!(database_config(&cli, None).is_err());
+        assert!(database_config(&cli, Some("/data/c.db")).is_err());
+    }
 }
@@ -28,6 +28,15 @@ pub fn compress(data: &[u8], algo: &CompressionAlgorithm) -> Result<Vec<u8>> {
     }
 }
 
+/// Name of the algorithm `data` was compressed with: `lz4`, `zstd` or `none`.
+pub fn detect(data: &[u8]) -> &'static str {
+    match data.get(..4) {
+        Some(magic) if magic == MAGIC_LZ4 => "lz4",
+        Some(magic) if magic == MAGIC_ZSTD => "zstd",
+        _ => "none",
+    }
+}
+
 /// Decompress data, auto-detecting algorithm from magic bytes.
 /// Passes through uncompressed data unchanged.
 pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
@@ -66,6 +75,14 @@ pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn detect_from_magic() {
+        assert_eq!(detect(&[MAGIC_LZ4.as_slice(), b"data"].concat()), "lz4");
+        assert_eq!(detect(&[MAGIC_ZSTD.as_slice(), b"data"].concat()), "zstd");
+        assert_eq!(detect(b"SQLite format 3"), "none");
+        assert_eq!(detect(b"WL"), "none");
+    }
+
     #[test]
     fn compress_none_passthrough() {
         let data = b"hello world";
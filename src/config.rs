@@ -1,5 +1,37 @@
 use std::time::Duration;
 
+use crate::error::{Error, Result};
+
+/// Parse durations such as `500ms`, `30s`, `5m`, `12h` or `30d`. A bare
+/// number is taken as seconds.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| Error::Other(format!("invalid duration: {s:?}")))?;
+    let secs = match unit {
+        "ms" => return Ok(Duration::from_millis(value)),
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => {
+            return Err(Error::Other(format!(
+                "invalid duration unit in {s:?}, expected ms, s, m, h or d"
+            )));
+        }
+    };
+    value
+        .checked_mul(secs)
+        .map(Duration::from_secs)
+        .ok_or_else(|| Error::Other(format!("duration too large: {s:?}")))
+}
+
+/// Where a database is replicated to. An `endpoint` with the `file://`
+/// scheme stores objects as files under that directory instead of in a
+/// bucket; `region`, `bucket` and the keys are then unused.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct S3Config {
     pub endpoint: String,
@@ -10,6 +42,96 @@ pub struct S3Config {
     pub prefix: String,
 }
 
+/// Region of `s3://` URLs without a `region` parameter.
+const DEFAULT_REGION: &str = "us-east-1";
+
+impl S3Config {
+    /// Parse a replica URL:
+    ///
+    /// - `s3://bucket/prefix?endpoint=http://localhost:3900&region=garage`.
+    ///   `region` defaults to `us-east-1` and `endpoint` to AWS in that
+    ///   region. Credentials may be given as `access_key` and `secret_key`
+    ///   parameters; otherwise they are left empty for the caller to fill in.
+    /// - `file:///var/backups/app`, a local directory.
+    pub fn from_url(url: &str) -> Result<Self> {
+        let invalid = |reason: &str| Error::Other(format!("invalid replica URL {url:?}: {reason}"));
+        let parsed = url::Url::parse(url).map_err(|e| invalid(&e.to_string()))?;
+        match parsed.scheme() {
+            "s3" => {
+                let bucket = parsed
+                    .host_str()
+                    .filter(|b| !b.is_empty())
+                    .ok_or_else(|| invalid("missing bucket"))?;
+                let mut config = S3Config {
+                    endpoint: String::new(),
+                    region: DEFAULT_REGION.to_string(),
+                    bucket: bucket.to_string(),
+                    access_key: String::new(),
+                    secret_key: String::new(),
+                    prefix: parsed.path().trim_matches('/').to_string(),
+                };
+                for (name, value) in parsed.query_pairs() {
+                    let field = match name.as_ref() {
+                        "endpoint" => &mut config.endpoint,
+                        "region" => &mut config.region,
+                        "access_key" => &mut config.access_key,
+                        "secret_key" => &mut config.secret_key,
+                        other => return Err(invalid(&format!("unknown parameter {other:?}"))),
+                    };
+                    *field = value.into_owned();
+                }
+                if config.endpoint.is_empty() {
+                    config.endpoint = format!("https://s3.{}.amazonaws.com", config.region);
+                }
+                Ok(config)
+            }
+            "file" => {
+                if parsed.query().is_some() {
+                    return Err(invalid("file URLs take no parameters"));
+                }
+                let dir = parsed
+                    .to_file_path()
+                    .map_err(|_| invalid("not a local path"))?;
+                Ok(S3Config {
+                    endpoint: format!("file://{}", dir.display()),
+                    region: String::new(),
+                    bucket: String::new(),
+                    access_key: String::new(),
+                    secret_key: String::new(),
+                    prefix: String::new(),
+                })
+            }
+            other => Err(invalid(&format!(
+                "unsupported scheme {other:?}, expected s3 or file"
+            ))),
+        }
+    }
+
+    /// The replica as a URL, without credentials, e.g. for log messages.
+    pub fn url(&self) -> String {
+        if let Some(dir) = self.local_dir() {
+            return match self.prefix.as_str() {
+                "" => format!("file://{}", dir.display()),
+                prefix => format!("file://{}/{prefix}", dir.display()),
+            };
+        }
+        let mut url = format!("s3://{}/{}", self.bucket, self.prefix);
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        query.append_pair("endpoint", &self.endpoint);
+        query.append_pair("region", &self.region);
+        url.push('?');
+        url.push_str(&query.finish());
+        url
+    }
+
+    /// The directory of a `file://` endpoint.
+    pub(crate) fn local_dir(&self) -> Option<std::path::PathBuf> {
+        self.endpoint
+            .strip_prefix("file://")
+            .map(std::path::PathBuf::from)
+    }
+}
+
 /// Algorithm used for compressing snapshots and WAL segments before upload.
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub enum CompressionAlgorithm {
@@ -21,6 +143,142 @@ pub enum CompressionAlgorithm {
     Zstd,
 }
 
+/// Source from which snapshot images are read.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum SnapshotSource {
+    /// Read the raw database file. Relies on the pinned read transaction for
+    /// stability; pages still in the WAL are captured by WAL segments instead.
+    #[default]
+    File,
+    /// Copy the database through SQLite's online backup API. Produces a
+    /// transactionally consistent image that includes committed WAL pages and
+    /// works with any SQLite build rusqlite can open.
+    BackupApi,
+}
+
+/// Settings for syncing on filesystem notifications about the `-wal` file.
+/// While notifications work, polling slows down to `fallback_poll_interval`;
+/// `sync_interval` applies again if the watcher stops.
+#[cfg(feature = "watch")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WatchConfig {
+    /// Wait for this much quiet after a write before syncing, to batch bursts.
+    pub debounce: Duration,
+    /// Sync at most this long after the first unsynced write, even if writes continue.
+    pub max_latency: Duration,
+    /// Poll this often while watching, to catch missed notifications and
+    /// take scheduled snapshots of idle databases.
+    pub fallback_poll_interval: Duration,
+}
+
+#[cfg(feature = "watch")]
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_millis(50),
+            max_latency: Duration::from_millis(500),
+            fallback_poll_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Policy for incremental page-delta snapshots.
+///
+/// A delta snapshot uploads only the pages whose content changed since the
+/// previous generation's snapshot. A fresh full snapshot is cut when either
+/// limit below is reached.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeltaSnapshotConfig {
+    /// Maximum number of consecutive deltas stacked on top of one full snapshot.
+    pub max_chain_length: u32,
+    /// Upload a full snapshot instead when more than this percentage of pages changed.
+    pub max_changed_percent: u8,
+}
+
+impl Default for DeltaSnapshotConfig {
+    fn default() -> Self {
+        Self {
+            max_chain_length: 16,
+            max_changed_percent: 50,
+        }
+    }
+}
+
+/// Limits at which a measurement makes replication degraded or failing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Threshold<T> {
+    pub degraded: T,
+    pub failing: T,
+}
+
+/// Thresholds for [`BackupManager::health`](crate::BackupManager::health).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HealthThresholds {
+    /// Time since the last successful `sync_wal`, whether or not it uploaded data.
+    pub sync_age: Threshold<Duration>,
+    /// WAL bytes written by the application but not yet uploaded.
+    pub pending_wal_bytes: Threshold<u64>,
+    /// Failed syncs since the last successful one.
+    pub consecutive_errors: Threshold<u32>,
+    /// Time since the last snapshot. Unchecked if not set.
+    pub snapshot_age: Option<Threshold<Duration>>,
+}
+
+impl Default for HealthThresholds {
+    fn default() -> Self {
+        Self {
+            sync_age: Threshold {
+                degraded: Duration::from_secs(30),
+                failing: Duration::from_secs(300),
+            },
+            pending_wal_bytes: Threshold {
+                degraded: 16 * 1024 * 1024,
+                failing: 64 * 1024 * 1024,
+            },
+            consecutive_errors: Threshold {
+                degraded: 3,
+                failing: 10,
+            },
+            snapshot_age: None,
+        }
+    }
+}
+
+/// Where [`Alert`](crate::Alert)s are delivered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AlertTarget {
+    /// POST the alert as JSON to `url`, with extra headers such as `Authorization`.
+    #[cfg(feature = "webhook")]
+    Webhook {
+        url: String,
+        headers: Vec<(String, String)>,
+    },
+    /// Run `program` with the alert as JSON on stdin and its kind, database
+    /// and message in `WALOY_ALERT`, `WALOY_DB` and `WALOY_MESSAGE`.
+    Command { program: String, args: Vec<String> },
+}
+
+/// When and where to send alerts about replication failures.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AlertConfig {
+    /// No alerts are sent if empty.
+    pub targets: Vec<AlertTarget>,
+    /// Alert once this many syncs in a row have failed.
+    pub consecutive_failures: u32,
+    /// Give up on a delivery after this long.
+    pub timeout: Duration,
+}
+
+impl Default for AlertConfig {
+    fn default() -> Self {
+        Self {
+            targets: Vec::new(),
+            consecutive_failures: 3,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BackupConfig {
     pub db_path: String,
@@ -38,6 +296,19 @@ pub struct BackupConfig {
     pub auto_restore: bool,
     /// If set, automatically take a new snapshot at this interval.
     pub snapshot_interval: Option<Duration>,
+    /// If set, snapshots upload only pages changed since the previous generation.
+    pub delta_snapshots: Option<DeltaSnapshotConfig>,
+    /// How snapshot images are read from the database.
+    pub snapshot_source: SnapshotSource,
+    /// If set, [`BackupManager::run`](crate::BackupManager::run) syncs on
+    /// notifications about `-wal` writes, falling back to polling if the
+    /// watcher cannot be started.
+    #[cfg(feature = "watch")]
+    pub watch: Option<WatchConfig>,
+    /// Thresholds used by [`BackupManager::health`](crate::BackupManager::health).
+    pub health: HealthThresholds,
+    /// Alerts on repeated sync failures, failed snapshots and recoveries.
+    pub alerts: AlertConfig,
 }
 
 impl Default for BackupConfig {
@@ -60,6 +331,12 @@ impl Default for BackupConfig {
             encryption_key: None,
             auto_restore: false,
             snapshot_interval: None,
+            delta_snapshots: None,
+            snapshot_source: SnapshotSource::default(),
+            #[cfg(feature = "watch")]
+            watch: None,
+            health: HealthThresholds::default(),
+            alerts: AlertConfig::default(),
         }
     }
 }
@@ -77,11 +354,69 @@ mod tests {
         assert_eq!(cfg.compression, CompressionAlgorithm::None);
         assert!(!cfg.auto_restore);
         assert!(cfg.snapshot_interval.is_none());
+        assert!(cfg.delta_snapshots.is_none());
+        assert_eq!(cfg.snapshot_source, SnapshotSource::File);
         assert!(cfg.db_path.is_empty());
         assert!(cfg.s3.endpoint.is_empty());
         assert!(cfg.s3.prefix.is_empty());
         #[cfg(feature = "encryption")]
         assert!(cfg.encryption_key.is_none());
+        #[cfg(feature = "watch")]
+        assert!(cfg.watch.is_none());
+        assert!(cfg.alerts.targets.is_empty());
+        assert_eq!(cfg.alerts.consecutive_failures, 3);
+    }
+
+    #[test]
+    fn s3_config_from_url() {
+        let cfg = S3Config::from_url(
+            "s3://backups/app/prod?endpoint=http://localhost:3900&region=garage",
+        )
+        .unwrap();
+        assert_eq!(cfg.endpoint, "http://localhost:3900");
+        assert_eq!(cfg.region, "garage");
+        assert_eq!(cfg.bucket, "backups");
+        assert_eq!(cfg.prefix, "app/prod");
+        assert_eq!(cfg.access_key, "");
+        assert_eq!(S3Config::from_url(&cfg.url()).unwrap(), cfg);
+
+        let cfg = S3Config::from_url("s3://Backups?access_key=ak&secret_key=s%2Fk").unwrap();
+        assert_eq!(cfg.bucket, "Backups");
+        assert_eq!(cfg.prefix, "");
+        assert_eq!(cfg.endpoint, "https://s3.us-east-1.amazonaws.com");
+        assert_eq!(cfg.secret_key, "s/k");
+
+        assert!(S3Config::from_url("s3:///prefix").is_err());
+        assert!(S3Config::from_url("s3://b?endpont=x").is_err());
+        assert!(S3Config::from_url("gs://b").is_err());
+    }
+
+    #[test]
+    fn s3_config_from_file_url() {
+        let cfg = S3Config::from_url("file:///var/backups/app").unwrap();
+        assert_eq!(cfg.endpoint, "file:///var/backups/app");
+        assert_eq!(
+            cfg.local_dir(),
+            Some(std::path::PathBuf::from("/var/backups/app"))
+        );
+        assert_eq!(cfg.url(), "file:///var/backups/app");
+        assert!(S3Config::from_url("file://host/backups").is_err());
+        assert!(BackupConfig::default().s3.local_dir().is_none());
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(
+            parse_duration("30d").unwrap(),
+            Duration::from_secs(30 * 86400)
+        );
+        assert!(parse_duration("1w").is_err());
+        assert!(parse_duration("h").is_err());
+        let err = parse_duration("18446744073709551615d").unwrap_err();
+        assert!(err.to_string().contains("duration too large"), "{err}");
     }
 
     #[test]
@@ -89,6 +424,21 @@ mod tests {
         assert_eq!(CompressionAlgorithm::default(), CompressionAlgorithm::None);
     }
 
+    #[test]
+    fn delta_snapshot_config_defaults() {
+        let cfg = DeltaSnapshotConfig::default();
+        assert_eq!(cfg.max_chain_length, 16);
+        assert_eq!(cfg.max_changed_percent, 50);
+    }
+
+    #[cfg(feature = "watch")]
+    #[test]
+    fn watch_config_defaults() {
+        let cfg = WatchConfig::default();
+        assert!(cfg.debounce < cfg.max_latency);
+        assert!(cfg.max_latency < cfg.fallback_poll_interval);
+    }
+
     #[test]
     fn s3_config_clone_and_debug() {
         let cfg = S3Config {
@@ -0,0 +1,571 @@
+//! Loading [`BackupConfig`]s from TOML or YAML files.
+//!
+//! ```toml
+//! [defaults]
+//! sync_interval = "1s"
+//! retention = "30d"
+//!
+//! [defaults.replica]
+//! endpoint = "http://localhost:3900"
+//! region = "garage"
+//! bucket = "backups"
+//! access_key = "${WALOY_S3_ACCESS_KEY}"
+//! secret_key = "${WALOY_S3_SECRET_KEY}"
+//!
+//! [[databases]]
+//! path = "/data/app.db"
+//! replica = { prefix = "app" }
+//! ```
+//!
+//! `${NAME}` in any string is replaced by the environment variable `NAME`,
+//! and `${NAME:-fallback}` falls back when it is unset; `$${` is a literal `${`.
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::config::{
+    BackupConfig, CompressionAlgorithm, DeltaSnapshotConfig, S3Config, SnapshotSource,
+    parse_duration,
+};
+use crate::error::{Error, Result};
+
+/// Syntax of a configuration file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// The format for a file name ending in `.toml`, `.yaml` or `.yml`.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Ok(Self::Toml),
+            Some("yaml" | "yml") => Ok(Self::Yaml),
+            _ => Err(Error::Other(format!(
+                "unknown config file format: {} (expected .toml, .yaml or .yml)",
+                path.display()
+            ))),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileSchema {
+    #[serde(default)]
+    defaults: DatabaseSection,
+    #[serde(default)]
+    databases: Vec<DatabaseSection>,
+}
+
+/// Settings of one database; every field falls back to `[defaults]`.
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DatabaseSection {
+    path: Option<String>,
+    replica: Option<ReplicaSection>,
+    sync_interval: Option<String>,
+    checkpoint_threshold_bytes: Option<u64>,
+    retention: Option<String>,
+    snapshot_interval: Option<String>,
+    compression: Option<String>,
+    encryption_key: Option<String>,
+    auto_restore: Option<bool>,
+    snapshot_source: Option<String>,
+    delta_snapshots: Option<DeltaSection>,
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReplicaSection {
+    /// A replica URL; the other fields override its parts.
+    url: Option<String>,
+    endpoint: Option<String>,
+    region: Option<String>,
+    bucket: Option<String>,
+    access_key: Option<String>,
+    secret_key: Option<String>,
+    prefix: Option<String>,
+}
+
+impl ReplicaSection {
+    /// This section with the fields it leaves unset taken from its `url`.
+    fn with_url(&self) -> Result<Self> {
+        let Some(url) = &self.url else {
+            return Ok(self.clone());
+        };
+        let parsed = S3Config::from_url(url)?;
+        let local = parsed.local_dir().is_some();
+        // Parts the URL leaves empty stay unset, except the ones a local
+        // directory has no use for.
+        let part = |value: &Option<String>, from_url: String| {
+            value
+                .clone()
+                .or_else(|| (local || !from_url.is_empty()).then_some(from_url))
+        };
+        Ok(Self {
+            url: None,
+            endpoint: part(&self.endpoint, parsed.endpoint),
+            region: part(&self.region, parsed.region),
+            bucket: part(&self.bucket, parsed.bucket),
+            access_key: part(&self.access_key, parsed.access_key),
+            secret_key: part(&self.secret_key, parsed.secret_key),
+            prefix: self
+                .prefix
+                .clone()
+                .or_else(|| Some(parsed.prefix).filter(|p| !p.is_empty())),
+        })
+    }
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DeltaSection {
+    max_chain_length: Option<u32>,
+    max_changed_percent: Option<u8>,
+}
+
+impl BackupConfig {
+    /// Load the single database described by a TOML or YAML file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut configs = Self::all_from_file(path)?;
+        if configs.len() != 1 {
+            return Err(Error::Other(format!(
+                "{} describes {} databases, expected one",
+                path.display(),
+                configs.len()
+            )));
+        }
+        Ok(configs.remove(0))
+    }
+
+    /// Load every database described by a TOML or YAML file.
+    pub fn all_from_file(path: impl AsRef<Path>) -> Result<Vec<Self>> {
+        let path = path.as_ref();
+        let format = ConfigFormat::from_path(path)?;
+        let text = std::fs::read_to_string(path)?;
+        Self::all_from_str(&text, format)
+            .map_err(|e| Error::Other(format!("{}: {e}", path.display())))
+    }
+
+    /// Parse every database described by a configuration in `format`.
+    pub fn all_from_str(text: &str, format: ConfigFormat) -> Result<Vec<Self>> {
+        let mut value: serde_json::Value = match format {
+            ConfigFormat::Toml => toml::from_str(text).map_err(|e| Error::Other(e.to_string()))?,
+            ConfigFormat::Yaml => {
+                serde_yaml::from_str(text).map_err(|e| Error::Other(e.to_string()))?
+            }
+        };
+        expand_env(&mut value, &|name| std::env::var(name).ok())?;
+        let schema: FileSchema =
+            serde_json::from_value(value).map_err(|e| Error::Other(e.to_string()))?;
+        if schema.databases.is_empty() {
+            return Err(Error::Other("no [[databases]] configured".into()));
+        }
+
+        let mut configs: Vec<BackupConfig> = Vec::new();
+        for db in &schema.databases {
+            let config = resolve(&schema.defaults, db)?;
+            if configs.iter().any(|c| c.db_path == config.db_path) {
+                return Err(Error::Other(format!(
+                    "database {} is configured twice",
+                    config.db_path
+                )));
+            }
+            if let Some(other) = configs.iter().find(|c| same_replica(&c.s3, &config.s3)) {
+                return Err(Error::Other(format!(
+                    "databases {} and {} share replica prefix {:?}",
+                    other.db_path, config.db_path, config.s3.prefix
+                )));
+            }
+            configs.push(config);
+        }
+        Ok(configs)
+    }
+}
+
+fn same_replica(a: &S3Config, b: &S3Config) -> bool {
+    a.endpoint == b.endpoint && a.bucket == b.bucket && a.prefix == b.prefix
+}
+
+/// Build the config of `db`, falling back to `defaults` field by field.
+fn resolve(defaults: &DatabaseSection, db: &DatabaseSection) -> Result<BackupConfig> {
+    let path = db
+        .path
+        .clone()
+        .ok_or_else(|| Error::Other("database without a path".into()))?;
+    let context = |e: Error| Error::Other(format!("database {path}: {e}"));
+
+    let empty = ReplicaSection::default();
+    let replica = db
+        .replica
+        .as_ref()
+        .unwrap_or(&empty)
+        .with_url()
+        .map_err(context)?;
+    let default_replica = defaults
+        .replica
+        .as_ref()
+        .unwrap_or(&empty)
+        .with_url()
+        .map_err(context)?;
+    let field = |name: &str, value: &Option<String>, default: &Option<String>| {
+        value
+            .clone()
+            .or_else(|| default.clone())
+            .ok_or_else(|| Error::Other(format!("replica.{name} is missing")))
+    };
+    let s3 = S3Config {
+        endpoint: field("endpoint", &replica.endpoint, &default_replica.endpoint)
+            .map_err(context)?,
+        region: field("region", &replica.region, &default_replica.region).map_err(context)?,
+        bucket: field("bucket", &replica.bucket, &default_replica.bucket).map_err(context)?,
+        access_key: field(
+            "access_key",
+            &replica.access_key,
+            &default_replica.access_key,
+        )
+        .map_err(context)?,
+        secret_key: field(
+            "secret_key",
+            &replica.secret_key,
+            &default_replica.secret_key,
+        )
+        .map_err(context)?,
+        prefix: replica
+            .prefix
+            .clone()
+            .or_else(|| default_replica.prefix.clone())
+            .unwrap_or_default(),
+    };
+
+    let duration = |value: &Option<String>, default: &Option<String>| {
+        value
+            .as_ref()
+            .or(default.as_ref())
+            .map(|s| parse_duration(s))
+            .transpose()
+    };
+    let mut config = BackupConfig {
+        db_path: path.clone(),
+        s3,
+        ..Default::default()
+    };
+    if let Some(interval) = duration(&db.sync_interval, &defaults.sync_interval).map_err(context)? {
+        config.sync_interval = interval;
+    }
+    if let Some(bytes) = db
+        .checkpoint_threshold_bytes
+        .or(defaults.checkpoint_threshold_bytes)
+    {
+        config.checkpoint_threshold_bytes = bytes;
+    }
+    config.retention_duration = duration(&db.retention, &defaults.retention).map_err(context)?;
+    config.snapshot_interval =
+        duration(&db.snapshot_interval, &defaults.snapshot_interval).map_err(context)?;
+    if let Some(name) = db.compression.as_ref().or(defaults.compression.as_ref()) {
+        config.compression = compression(name).map_err(context)?;
+    }
+    if let Some(key) = db
+        .encryption_key
+        .as_ref()
+        .or(defaults.encryption_key.as_ref())
+    {
+        #[cfg(feature = "encryption")]
+        {
+            config.encryption_key = Some(key.clone());
+        }
+        #[cfg(not(feature = "encryption"))]
+        {
+            let _ = key;
+            return Err(context(Error::Other(
+                "encryption_key requires the encryption feature".into(),
+            )));
+        }
+    }
+    config.auto_restore = db.auto_restore.or(defaults.auto_restore).unwrap_or(false);
+    if let Some(source) = db
+        .snapshot_source
+        .as_ref()
+        .or(defaults.snapshot_source.as_ref())
+    {
+        config.snapshot_source = match source.as_str() {
+            "file" => SnapshotSource::File,
+            "backup_api" => SnapshotSource::BackupApi,
+            other => {
+                return Err(context(Error::Other(format!(
+                    "unknown snapshot_source {other:?}, expected file or backup_api"
+                ))));
+            }
+        };
+    }
+    if let Some(delta) = db
+        .delta_snapshots
+        .as_ref()
+        .or(defaults.delta_snapshots.as_ref())
+    {
+        let default = DeltaSnapshotConfig::default();
+        config.delta_snapshots = Some(DeltaSnapshotConfig {
+            max_chain_length: delta.max_chain_length.unwrap_or(default.max_chain_length),
+            max_changed_percent: delta
+                .max_changed_percent
+                .unwrap_or(default.max_changed_percent),
+        });
+    }
+    Ok(config)
+}
+
+fn compression(name: &str) -> Result<CompressionAlgorithm> {
+    match name {
+        "none" => Ok(CompressionAlgorithm::None),
+        #[cfg(feature = "compression-lz4")]
+        "lz4" => Ok(CompressionAlgorithm::Lz4),
+        #[cfg(feature = "compression-zstd")]
+        "zstd" => Ok(CompressionAlgorithm::Zstd),
+        #[cfg(not(feature = "compression-lz4"))]
+        "lz4" => Err(Error::Other(
+            "compression \"lz4\" requires the compression-lz4 feature".into(),
+        )),
+        #[cfg(not(feature = "compression-zstd"))]
+        "zstd" => Err(Error::Other(
+            "compression \"zstd\" requires the compression-zstd feature".into(),
+        )),
+        other => Err(Error::Other(format!(
+            "unknown compression {other:?}, expected none, lz4 or zstd"
+        ))),
+    }
+}
+
+/// Replace `${NAME}` and `${NAME:-fallback}` in every string of `value`.
+fn expand_env(
+    value: &mut serde_json::Value,
+    lookup: &dyn Fn(&str) -> Option<String>,
+) -> Result<()> {
+    match value {
+        serde_json::Value::String(s) => *s = expand_str(s, lookup)?,
+        serde_json::Value::Array(items) => {
+            for item in items {
+                expand_env(item, lookup)?;
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for item in map.values_mut() {
+                expand_env(item, lookup)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn expand_str(s: &str, lookup: &dyn Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start..];
+        if let Some(literal) = after.strip_prefix("$${") {
+            out.push_str("${");
+            rest = literal;
+        } else if let Some(var) = after.strip_prefix("${") {
+            let end = var
+                .find('}')
+                .ok_or_else(|| Error::Other(format!("unterminated ${{ in {s:?}")))?;
+            let (name, fallback) = match var[..end].split_once(":-") {
+                Some((name, fallback)) => (name, Some(fallback)),
+                None => (&var[..end], None),
+            };
+            match lookup(name).or_else(|| fallback.map(str::to_string)) {
+                Some(value) => out.push_str(&value),
+                None => {
+                    return Err(Error::Other(format!(
+                        "environment variable {name} is not set"
+                    )));
+                }
+            }
+            rest = &var[end + 1..];
+        } else {
+            out.push('$');
+            rest = &after[1..];
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const TOML: &str = r#"
+[defaults]
+sync_interval = "500ms"
+retention = "30d"
+
+[defaults.replica]
+endpoint = "http://localhost:3900"
+region = "garage"
+bucket = "backups"
+access_key = "ak"
+secret_key = "sk"
+
+[[databases]]
+path = "/data/app.db"
+replica = { prefix = "app" }
+snapshot_interval = "6h"
+
+[[databases]]
+path = "/data/jobs.db"
+sync_interval = "5s"
+auto_restore = true
+replica = { prefix = "jobs", bucket = "other" }
+"#;
+
+    #[test]
+    fn databases_fall_back_to_defaults() {
+        let configs = BackupConfig::all_from_str(TOML, ConfigFormat::Toml).unwrap();
+        assert_eq!(configs.len(), 2);
+        let app = &configs[0];
+        assert_eq!(app.db_path, "/data/app.db");
+        assert_eq!(app.s3.bucket, "backups");
+        assert_eq!(app.s3.prefix, "app");
+        assert_eq!(app.sync_interval, Duration::from_millis(500));
+        assert_eq!(
+            app.retention_duration,
+            Some(Duration::from_secs(30 * 86400))
+        );
+        assert_eq!(app.snapshot_interval, Some(Duration::from_secs(6 * 3600)));
+        assert!(!app.auto_restore);
+
+        let jobs = &configs[1];
+        assert_eq!(jobs.s3.bucket, "other");
+        assert_eq!(jobs.s3.endpoint, "http://localhost:3900");
+        assert_eq!(jobs.sync_interval, Duration::from_secs(5));
+        assert!(jobs.snapshot_interval.is_none());
+        assert!(jobs.auto_restore);
+    }
+
+    #[test]
+    fn yaml_matches_toml() {
+        let yaml = r#"
+databases:
+  - path: /data/app.db
+    snapshot_source: backup_api
+    delta_snapshots: { max_chain_length: 4 }
+    replica:
+      endpoint: http://localhost:3900
+      region: garage
+      bucket: backups
+      access_key: ak
+      secret_key: sk
+"#;
+        let configs = BackupConfig::all_from_str(yaml, ConfigFormat::Yaml).unwrap();
+        assert_eq!(configs[0].snapshot_source, SnapshotSource::BackupApi);
+        let delta = configs[0].delta_snapshots.as_ref().unwrap();
+        assert_eq!(delta.max_chain_length, 4);
+        assert_eq!(delta.max_changed_percent, 50);
+        assert_eq!(configs[0].s3.prefix, "");
+    }
+
+    #[test]
+    fn missing_replica_field_names_database() {
+        let toml = r#"
+[[databases]]
+path = "/data/app.db"
+replica = { endpoint = "e", region = "r", access_key = "a", secret_key = "s" }
+"#;
+        let err = BackupConfig::all_from_str(toml, ConfigFormat::Toml).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "database /data/app.db: replica.bucket is missing"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_shared_prefixes() {
+        let typo = TOML.replace("snapshot_interval", "snapshot_intervall");
+        assert!(BackupConfig::all_from_str(&typo, ConfigFormat::Toml).is_err());
+
+        let shared = TOML.replace("prefix = \"jobs\", bucket = \"other\"", "prefix = \"app\"");
+        let err = BackupConfig::all_from_str(&shared, ConfigFormat::Toml).unwrap_err();
+        assert!(err.to_string().contains("share replica prefix"), "{err}");
+    }
+
+    #[test]
+    fn replica_urls_fill_unset_fields() {
+        let toml = r#"
+[defaults.replica]
+url = "s3://backups/base?endpoint=http://localhost:3900&region=garage"
+access_key = "ak"
+secret_key = "sk"
+
+[[databases]]
+path = "/data/app.db"
+
+[[databases]]
+path = "/data/jobs.db"
+replica = { url = "s3://other?region=eu-west-1", prefix = "jobs" }
+
+[[databases]]
+path = "/data/local.db"
+replica = { url = "file:///var/backups/local" }
+"#;
+        let configs = BackupConfig::all_from_str(toml, ConfigFormat::Toml).unwrap();
+        assert_eq!(configs[0].s3.endpoint, "http://localhost:3900");
+        assert_eq!(configs[0].s3.prefix, "base");
+        assert_eq!(configs[0].s3.access_key, "ak");
+
+        assert_eq!(configs[1].s3.bucket, "other");
+        assert_eq!(configs[1].s3.region, "eu-west-1");
+        assert_eq!(configs[1].s3.endpoint, "https://s3.eu-west-1.amazonaws.com");
+        assert_eq!(configs[1].s3.prefix, "jobs");
+        assert_eq!(configs[1].s3.secret_key, "sk");
+
+        assert_eq!(configs[2].s3.endpoint, "file:///var/backups/local");
+        assert_eq!(configs[2].s3.prefix, "base");
+    }
+
+    #[test]
+    fn expands_environment_variables() {
+        let lookup = |name: &str| (name == "KEY").then(|| "s3cr\"t".to_string());
+        assert_eq!(expand_str("${KEY}", &lookup).unwrap(), "s3cr\"t");
+        assert_eq!(expand_str("a-${MISSING:-b}-c", &lookup).unwrap(), "a-b-c");
+        assert_eq!(
+            expand_str("$${KEY} costs $5", &lookup).unwrap(),
+            "${KEY} costs $5"
+        );
+        assert!(expand_str("${MISSING}", &lookup).is_err());
+        assert!(expand_str("${KEY", &lookup).is_err());
+
+        let mut value = serde_json::json!({ "a": ["${KEY}"], "b": 1 });
+        expand_env(&mut value, &lookup).unwrap();
+        assert_eq!(value["a"][0], "s3cr\"t");
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("waloy.yml")).unwrap(),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("waloy.toml")).unwrap(),
+            ConfigFormat::Toml
+        );
+        assert!(ConfigFormat::from_path(Path::new("waloy.json")).is_err());
+    }
+
+    #[test]
+    fn from_file_requires_one_database() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("waloy.toml");
+        std::fs::write(&path, TOML).unwrap();
+        let err = BackupConfig::from_file(&path).unwrap_err();
+        assert!(err.to_string().contains("describes 2 databases"), "{err}");
+        assert_eq!(BackupConfig::all_from_file(&path).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,201 @@
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+
+/// Magic bytes for identifying a page-delta snapshot.
+const MAGIC: &[u8; 4] = b"WDS\x01";
+const HEADER_LEN: usize = 16;
+/// Offset of the page size field in the SQLite database header (bytes 16..18).
+const DB_PAGE_SIZE_OFFSET: usize = 16;
+
+pub(crate) type PageHash = [u8; 32];
+
+/// Read the page size from a SQLite database image.
+/// Returns `None` if the image is too short or the header is invalid.
+pub(crate) fn db_page_size(image: &[u8]) -> Option<usize> {
+    header_page_size(image).filter(|page_size| image.len().is_multiple_of(*page_size))
+}
+
+/// Read the page size from the first 100 bytes of a database file.
+/// Returns `None` if the header is too short or the page size is invalid.
+pub(crate) fn header_page_size(header: &[u8]) -> Option<usize> {
+    if header.len() < 100 {
+        return None;
+    }
+    let raw = u16::from_be_bytes([header[DB_PAGE_SIZE_OFFSET], header[DB_PAGE_SIZE_OFFSET + 1]]);
+    // A stored value of 1 means 65536, which does not fit in a u16.
+    let page_size = if raw == 1 { 65536 } else { raw as usize };
+    (page_size >= 512 && page_size.is_power_of_two()).then_some(page_size)
+}
+
+/// Hash every page of a database image.
+pub(crate) fn page_hashes(image: &[u8], page_size: usize) -> Vec<PageHash> {
+    image
+        .chunks(page_size)
+        .map(|page| Sha256::digest(page).into())
+        .collect()
+}
+
+/// Indices of pages in `hashes` that differ from (or are missing in) `base`.
+pub(crate) fn changed_pages(base: &[PageHash], hashes: &[PageHash]) -> Vec<u32> {
+    hashes
+        .iter()
+        .enumerate()
+        .filter(|(i, h)| base.get(*i) != Some(*h))
+        .map(|(i, _)| i as u32)
+        .collect()
+}
+
+/// Encode the given pages of `image` as a delta.
+/// Format: MAGIC (4) + page_size (4) + page_count (4) + entry_count (4)
+/// followed by `entry_count` entries of page_index (4) + page data.
+pub(crate) fn encode(image: &[u8], page_size: usize, pages: &[u32]) -> Vec<u8> {
+    let page_count = (image.len() / page_size) as u32;
+    let mut out = Vec::with_capacity(HEADER_LEN + pages.len() * (4 + page_size));
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&(page_size as u32).to_be_bytes());
+    out.extend_from_slice(&page_count.to_be_bytes());
+    out.extend_from_slice(&(pages.len() as u32).to_be_bytes());
+    for &index in pages {
+        let start = index as usize * page_size;
+        out.extend_from_slice(&index.to_be_bytes());
+        out.extend_from_slice(&image[start..start + page_size]);
+    }
+    out
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+/// Apply a delta produced by [`encode`] to a base image, returning the new image.
+pub(crate) fn apply(mut base: Vec<u8>, delta: &[u8]) -> Result<Vec<u8>> {
+    if delta.len() < HEADER_LEN || &delta[..4] != MAGIC {
+        return Err(Error::Other("delta snapshot: invalid header".into()));
+    }
+    let page_size = read_u32(delta, 4) as usize;
+    let page_count = read_u32(delta, 8) as usize;
+    let entries = read_u32(delta, 12) as usize;
+    if page_size == 0 || delta.len() != HEADER_LEN + entries * (4 + page_size) {
+        return Err(Error::Other("delta snapshot: truncated or corrupt".into()));
+    }
+
+    base.resize(page_count * page_size, 0);
+    let mut pos = HEADER_LEN;
+    for _ in 0..entries {
+        let index = read_u32(delta, pos) as usize;
+        pos += 4;
+        if index >= page_count {
+            return Err(Error::Other(format!(
+                "delta snapshot: page {index} out of range ({page_count} pages)"
+            )));
+        }
+        let start = index * page_size;
+        base[start..start + page_size].copy_from_slice(&delta[pos..pos + page_size]);
+        pos += page_size;
+    }
+    Ok(base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: usize = 512;
+
+    fn make_image(pages: &[u8]) -> Vec<u8> {
+        let mut image = Vec::new();
+        for &fill in pages {
+            image.extend(std::iter::repeat_n(fill, PAGE));
+        }
+        // Valid SQLite page size in the header of page 0.
+        image[DB_PAGE_SIZE_OFFSET..DB_PAGE_SIZE_OFFSET + 2]
+            .copy_from_slice(&(PAGE as u16).to_be_bytes());
+        image
+    }
+
+    #[test]
+    fn db_page_size_parses_header() {
+        let image = make_image(&[1, 2]);
+        assert_eq!(db_page_size(&image), Some(PAGE));
+    }
+
+    #[test]
+    fn db_page_size_rejects_short_or_misaligned() {
+        assert_eq!(db_page_size(&[0u8; 50]), None);
+        let mut image = make_image(&[1, 2]);
+        image.push(0);
+        assert_eq!(db_page_size(&image), None);
+    }
+
+    #[test]
+    fn db_page_size_65536_encoding() {
+        let mut image = vec![0u8; 65536];
+        image[DB_PAGE_SIZE_OFFSET..DB_PAGE_SIZE_OFFSET + 2].copy_from_slice(&1u16.to_be_bytes());
+        assert_eq!(db_page_size(&image), Some(65536));
+    }
+
+    #[test]
+    fn header_page_size_ignores_image_length() {
+        let mut header = vec![0u8; 100];
+        header[DB_PAGE_SIZE_OFFSET..DB_PAGE_SIZE_OFFSET + 2]
+            .copy_from_slice(&4096u16.to_be_bytes());
+        assert_eq!(header_page_size(&header), Some(4096));
+        assert_eq!(db_page_size(&header), None);
+        header[DB_PAGE_SIZE_OFFSET..DB_PAGE_SIZE_OFFSET + 2]
+            .copy_from_slice(&1000u16.to_be_bytes());
+        assert_eq!(header_page_size(&header), None);
+        assert_eq!(header_page_size(b"not a database"), None);
+    }
+
+    #[test]
+    fn changed_pages_detects_modified_and_new() {
+        let base = make_image(&[1, 2, 3]);
+        let next = make_image(&[1, 9, 3, 4]);
+        let changed = changed_pages(&page_hashes(&base, PAGE), &page_hashes(&next, PAGE));
+        assert_eq!(changed, vec![1, 3]);
+    }
+
+    #[test]
+    fn roundtrip_grow() {
+        let base = make_image(&[1, 2, 3]);
+        let next = make_image(&[1, 9, 3, 4]);
+        let changed = changed_pages(&page_hashes(&base, PAGE), &page_hashes(&next, PAGE));
+        let delta = encode(&next, PAGE, &changed);
+        assert_eq!(delta.len(), HEADER_LEN + 2 * (4 + PAGE));
+        assert_eq!(apply(base, &delta).unwrap(), next);
+    }
+
+    #[test]
+    fn roundtrip_shrink() {
+        let base = make_image(&[1, 2, 3, 4]);
+        let next = make_image(&[1, 2]);
+        let changed = changed_pages(&page_hashes(&base, PAGE), &page_hashes(&next, PAGE));
+        assert!(changed.is_empty());
+        let delta = encode(&next, PAGE, &changed);
+        assert_eq!(apply(base, &delta).unwrap(), next);
+    }
+
+    #[test]
+    fn apply_rejects_bad_magic() {
+        let err = apply(Vec::new(), b"XXXXXXXXXXXXXXXXXXXX").unwrap_err();
+        assert!(err.to_string().contains("invalid header"));
+    }
+
+    #[test]
+    fn apply_rejects_truncated() {
+        let next = make_image(&[1, 2]);
+        let mut delta = encode(&next, PAGE, &[1]);
+        delta.truncate(delta.len() - 1);
+        assert!(apply(Vec::new(), &delta).is_err());
+    }
+
+    #[test]
+    fn apply_rejects_out_of_range_page() {
+        let next = make_image(&[1, 2]);
+        let mut delta = encode(&next, PAGE, &[1]);
+        // Rewrite the entry index to point past the end.
+        delta[HEADER_LEN..HEADER_LEN + 4].copy_from_slice(&5u32.to_be_bytes());
+        assert!(apply(Vec::new(), &delta).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config::BackupConfig;
+use crate::error::Result;
+
+/// Discovers databases to replicate from a glob pattern such as
+/// `/data/tenants/*.db`.
+///
+/// The wildcards `*` and `?` are supported in the file name; the directory
+/// part is taken literally. Each discovered database is replicated under
+/// `{template.s3.prefix}/{file stem}`; of several files with the same stem,
+/// only the first in sort order is replicated.
+#[derive(Clone, Debug)]
+pub struct DirectoryDiscovery {
+    pub pattern: String,
+    /// Settings applied to every discovered database. `db_path` is ignored.
+    pub template: BackupConfig,
+    /// How often the directory is rescanned.
+    pub scan_interval: Duration,
+}
+
+impl DirectoryDiscovery {
+    pub fn new(pattern: impl Into<String>, template: BackupConfig) -> Self {
+        Self {
+            pattern: pattern.into(),
+            template,
+            scan_interval: Duration::from_secs(5),
+        }
+    }
+
+    /// Split the pattern into its directory and file-name glob.
+    fn split(&self) -> (&Path, &str) {
+        let path = Path::new(&self.pattern);
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let glob = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        (dir, glob)
+    }
+
+    /// Whether `db_path` falls under this pattern.
+    pub fn matches(&self, db_path: &str) -> bool {
+        let (dir, glob) = self.split();
+        let path = Path::new(db_path);
+        path.parent() == Some(dir)
+            && path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|name| glob_match(glob, name))
+    }
+
+    /// List the database files currently matching the pattern, sorted.
+    pub fn scan(&self) -> Result<Vec<String>> {
+        let (dir, glob) = self.split();
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let name = entry.file_name();
+            if let Some(name) = name.to_str()
+                && glob_match(glob, name)
+                && let Some(path) = dir.join(name).to_str()
+            {
+                paths.push(path.to_string());
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Backup config for a discovered database, with its derived prefix.
+    pub fn config_for(&self, db_path: &str) -> BackupConfig {
+        let stem = Path::new(db_path)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or(db_path);
+        let mut config = self.template.clone();
+        config.db_path = db_path.to_string();
+        config.s3.prefix = if self.template.s3.prefix.is_empty() {
+            stem.to_string()
+        } else {
+            format!("{}/{}", self.template.s3.prefix, stem)
+        };
+        config
+    }
+}
+
+/// Databases added and removed by one discovery scan.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DiscoveryChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Match a file name against a glob supporting `*` and `?`.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    let (mut pi, mut ni) = (0, 0);
+    // Position of the last `*` in the pattern and the name index it matched up to.
+    let mut star: Option<(usize, usize)> = None;
+
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_match_wildcards() {
+        assert!(glob_match("*.db", "tenant.db"));
+        assert!(glob_match("*.db", ".db"));
+        assert!(!glob_match("*.db", "tenant.db-wal"));
+        assert!(!glob_match("*.db", "tenant.sqlite"));
+        assert!(glob_match("tenant-?.db", "tenant-a.db"));
+        assert!(!glob_match("tenant-?.db", "tenant-ab.db"));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("a*b*c", "axxbyy"));
+        assert!(glob_match("exact.db", "exact.db"));
+    }
+
+    #[test]
+    fn config_for_derives_prefix() {
+        let mut template = BackupConfig::default();
+        template.s3.prefix = "backups".into();
+        let d = DirectoryDiscovery::new("/data/tenants/*.db", template);
+        let cfg = d.config_for("/data/tenants/acme.db");
+        assert_eq!(cfg.db_path, "/data/tenants/acme.db");
+        assert_eq!(cfg.s3.prefix, "backups/acme");
+    }
+
+    #[test]
+    fn config_for_empty_template_prefix() {
+        let d = DirectoryDiscovery::new("/data/*.db", BackupConfig::default());
+        assert_eq!(d.config_for("/data/acme.db").s3.prefix, "acme");
+    }
+
+    #[test]
+    fn matches_requires_same_directory() {
+        let d = DirectoryDiscovery::new("/data/tenants/*.db", BackupConfig::default());
+        assert!(d.matches("/data/tenants/a.db"));
+        assert!(!d.matches("/data/other/a.db"));
+        assert!(!d.matches("/data/tenants/a.db-wal"));
+    }
+
+    #[test]
+    fn scan_lists_matching_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        for name in ["b.db", "a.db", "a.db-wal", "notes.txt"] {
+            std::fs::write(tmp.path().join(name), b"").unwrap();
+        }
+        std::fs::create_dir(tmp.path().join("dir.db")).unwrap();
+
+        let pattern = tmp.path().join("*.db");
+        let d = DirectoryDiscovery::new(pattern.to_str().unwrap(), BackupConfig::default());
+        let found = d.scan().unwrap();
+        let names: Vec<_> = found
+            .iter()
+            .map(|p| Path::new(p).file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["a.db", "b.db"]);
+        assert!(found.iter().all(|p| d.matches(p)));
+    }
+}
@@ -44,10 +44,15 @@ pub fn encrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>> {
     Ok(out)
 }
 
+/// Whether `data` was produced by [`encrypt`].
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.len() >= MAGIC.len() + SALT_LEN + NONCE_LEN && &data[..4] == MAGIC
+}
+
 /// Decrypt data. Auto-detects encrypted data via magic bytes.
 /// Returns data unchanged if not encrypted.
 pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>> {
-    if data.len() < MAGIC.len() + SALT_LEN + NONCE_LEN || &data[..4] != MAGIC {
+    if !is_encrypted(data) {
         return Ok(data.to_vec());
     }
 
@@ -60,9 +65,9 @@ pub fn decrypt(data: &[u8], passphrase: &str) -> Result<Vec<u8>> {
         Aes256Gcm::new_from_slice(&key).map_err(|e| Error::Other(format!("aes-gcm init: {e}")))?;
     let nonce = Nonce::from_slice(nonce_bytes);
 
-    cipher
-        .decrypt(nonce, ciphertext)
-        .map_err(|e| Error::Other(format!("decryption: {e}")))
+    cipher.decrypt(nonce, ciphertext).map_err(|_| {
+        Error::Other("decryption failed: wrong encryption key or corrupted data".into())
+    })
 }
 
 #[cfg(test)]
@@ -82,6 +87,13 @@ mod tests {
         assert_eq!(&decrypted, data);
     }
 
+    #[test]
+    fn test_is_encrypted() {
+        assert!(is_encrypted(&encrypt(b"data", "key").unwrap()));
+        assert!(!is_encrypted(b"plain text data that is long enough"));
+        assert!(!is_encrypted(MAGIC));
+    }
+
     #[test]
     fn test_decrypt_unencrypted_passthrough() {
         let data = b"plain text data";
@@ -14,6 +14,18 @@ pub enum Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    /// Short name of the error variant, for stats and metrics labels.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Sqlite(_) => "sqlite",
+            Error::Io(_) => "io",
+            Error::S3(_) => "s3",
+            Error::Other(_) => "other",
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -43,6 +55,12 @@ mod tests {
         assert_eq!(err.to_string(), "something broke");
     }
 
+    #[test]
+    fn kind_names_variant() {
+        assert_eq!(Error::S3("x".into()).kind(), "s3");
+        assert_eq!(Error::Other("x".into()).kind(), "other");
+    }
+
     #[test]
     fn from_rusqlite_error() {
         let sqlite_err = rusqlite::Error::QueryReturnedNoRows;
@@ -0,0 +1,105 @@
+use serde::Serialize;
+
+/// Capacity of the event channel. Subscribers that fall further behind miss
+/// the oldest events and receive `RecvError::Lagged`.
+pub(crate) const EVENT_CAPACITY: usize = 256;
+
+/// Why a [`BackupManager`](crate::BackupManager) started a new generation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GenerationReason {
+    /// [`checkpoint`](crate::BackupManager::checkpoint) was called.
+    Checkpoint,
+    /// The WAL was reset or truncated behind the manager's back.
+    Recovery,
+    /// The `snapshot_interval` elapsed.
+    Schedule,
+}
+
+/// Replication activity, delivered to receivers from
+/// [`BackupManager::subscribe`](crate::BackupManager::subscribe).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReplicationEvent {
+    /// A WAL segment was uploaded.
+    SegmentUploaded {
+        generation: String,
+        index: u32,
+        /// Offset of the segment in the WAL file.
+        offset: u64,
+        /// Size of the segment before compression and encryption.
+        bytes: u64,
+        /// Size of the uploaded object.
+        uploaded_bytes: u64,
+    },
+    /// A full or delta snapshot was uploaded.
+    SnapshotUploaded {
+        generation: String,
+        uploaded_bytes: u64,
+        delta: bool,
+    },
+    /// Uploading a snapshot failed.
+    SnapshotFailed {
+        generation: String,
+        /// `sqlite`, `io`, `s3` or `other`.
+        kind: &'static str,
+        error: String,
+    },
+    /// A new generation started; its snapshot follows as
+    /// [`SnapshotUploaded`](Self::SnapshotUploaded).
+    GenerationStarted {
+        generation: String,
+        reason: GenerationReason,
+    },
+    /// Retention deleted these generations.
+    RetentionDeleted { generations: Vec<String> },
+    /// Compaction of the current generation finished.
+    CompactionFinished {
+        generation: String,
+        segments_before: u32,
+        segments_after: u32,
+    },
+    /// [`sync_wal`](crate::BackupManager::sync_wal) failed.
+    SyncFailed {
+        /// `sqlite`, `io`, `s3` or `other`.
+        kind: &'static str,
+        error: String,
+        /// Failed syncs since the last successful one, including this one.
+        consecutive_errors: u32,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_with_type_tag() {
+        let event = ReplicationEvent::GenerationStarted {
+            generation: "g1".into(),
+            reason: GenerationReason::Recovery,
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "generation_started",
+                "generation": "g1",
+                "reason": "recovery",
+            })
+        );
+    }
+
+    #[test]
+    fn serializes_sync_failure() {
+        let event = ReplicationEvent::SyncFailed {
+            kind: "s3",
+            error: "s3: timeout".into(),
+            consecutive_errors: 2,
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "sync_failed");
+        assert_eq!(json["kind"], "s3");
+        assert_eq!(json["consecutive_errors"], 2);
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::Read;
+use std::pin::Pin;
+
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+
+use crate::error::{Error, Result};
+use crate::events::ReplicationEvent;
+use crate::health::Health;
+use crate::manager::{
+    BackupManager, WAL_HEADER_SIZE, WAL_SALT_LEN, WAL_SALT_OFFSET, frame_aligned_len,
+};
+use crate::stats::BackupStats;
+
+/// Number of retired WAL salts remembered for resolving old positions.
+const RETIRED_SALTS: usize = 64;
+
+/// A point in the database's WAL, taken right after a commit.
+///
+/// The salt identifies one incarnation of the WAL file: SQLite picks a new
+/// salt whenever the WAL restarts after a checkpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WalPosition {
+    salt: [u8; WAL_SALT_LEN],
+    offset: u64,
+}
+
+impl WalPosition {
+    /// The end of the last complete frame in `{db_path}-wal`.
+    /// Returns `None` if there is no WAL or it holds no frames, in which case
+    /// every commit has already been checkpointed into the database file.
+    pub fn current(db_path: &str) -> Result<Option<Self>> {
+        let mut file = match std::fs::File::open(format!("{db_path}-wal")) {
+            Ok(f) => f,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let len = file.metadata()?.len();
+        let mut header = [0u8; WAL_HEADER_SIZE as usize];
+        if len < WAL_HEADER_SIZE {
+            return Ok(None);
+        }
+        file.read_exact(&mut header)?;
+
+        let offset = frame_aligned_len(&header, len);
+        if offset <= WAL_HEADER_SIZE {
+            return Ok(None);
+        }
+        let mut salt = [0u8; WAL_SALT_LEN];
+        salt.copy_from_slice(&header[WAL_SALT_OFFSET..WAL_SALT_OFFSET + WAL_SALT_LEN]);
+        Ok(Some(Self { salt, offset }))
+    }
+
+    /// Byte offset in the WAL file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+/// How far a [`BackupManager`](crate::BackupManager) has replicated, published
+/// after every WAL upload and snapshot.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ReplicationProgress {
+    /// Salt of the WAL currently being replicated.
+    salt: Option<[u8; WAL_SALT_LEN]>,
+    /// End of the frames uploaded from that WAL.
+    offset: u64,
+    /// Salts of earlier WALs whose frames are all covered by a later snapshot.
+    retired: VecDeque<[u8; WAL_SALT_LEN]>,
+}
+
+impl ReplicationProgress {
+    pub fn advance(&mut self, salt: [u8; WAL_SALT_LEN], offset: u64) {
+        self.salt = Some(salt);
+        self.offset = offset;
+    }
+
+    /// Record that the WAL with `salt` has restarted and a snapshot now holds
+    /// every frame it had.
+    pub fn retire(&mut self, salt: [u8; WAL_SALT_LEN]) {
+        if self.salt == Some(salt) {
+            self.salt = None;
+            self.offset = 0;
+        }
+        if self.retired.len() == RETIRED_SALTS {
+            self.retired.pop_front();
+        }
+        self.retired.push_back(salt);
+    }
+
+    /// Whether everything up to `position` has been uploaded.
+    pub fn covers(&self, position: &WalPosition) -> bool {
+        (self.salt == Some(position.salt) && self.offset >= position.offset)
+            || self.retired.contains(&position.salt)
+    }
+}
+
+/// A future borrowing the manager, run inside the replication task.
+pub(crate) type ManagerFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A job run with exclusive access to the manager, between syncs.
+pub(crate) type Job =
+    Box<dyn for<'a> FnOnce(&'a mut BackupManager) -> ManagerFuture<'a, ()> + Send>;
+
+/// Requests from a [`ReplicationHandle`] to the replication task.
+pub(crate) enum Command {
+    SyncNow(oneshot::Sender<Result<bool>>),
+    Shutdown(oneshot::Sender<Result<()>>),
+    Run(Job),
+}
+
+/// Handle to a [`BackupManager`](crate::BackupManager) running in its own task,
+/// created by [`BackupManager::spawn`](crate::BackupManager::spawn).
+///
+/// Handles are cheap to clone. When the last one is dropped, the task shuts
+/// the manager down gracefully.
+#[derive(Clone)]
+pub struct ReplicationHandle {
+    db_path: String,
+    commands: mpsc::Sender<Command>,
+    progress: watch::Receiver<ReplicationProgress>,
+    events: broadcast::Sender<ReplicationEvent>,
+}
+
+impl ReplicationHandle {
+    pub(crate) fn new(
+        db_path: String,
+        commands: mpsc::Sender<Command>,
+        progress: watch::Receiver<ReplicationProgress>,
+        events: broadcast::Sender<ReplicationEvent>,
+    ) -> Self {
+        Self {
+            db_path,
+            commands,
+            progress,
+            events,
+        }
+    }
+
+    /// Receive replication events from now on, like
+    /// [`BackupManager::subscribe`](crate::BackupManager::subscribe).
+    pub fn subscribe(&self) -> broadcast::Receiver<ReplicationEvent> {
+        self.events.subscribe()
+    }
+
+    /// The current WAL position of the replicated database. Call it right
+    /// after a commit and pass the result to
+    /// [`wait_replicated`](Self::wait_replicated).
+    pub fn wal_position(&self) -> Result<Option<WalPosition>> {
+        WalPosition::current(&self.db_path)
+    }
+
+    /// Whether everything up to `position` has been uploaded.
+    pub fn is_replicated(&self, position: &WalPosition) -> bool {
+        self.progress.borrow().covers(position)
+    }
+
+    /// Wait until `sync_wal` has uploaded every frame up to `position`, or a
+    /// checkpoint has restarted its WAL and a snapshot covers it. Does not trigger a sync by itself; combine
+    /// with [`sync_now`](Self::sync_now) to avoid waiting for the next one.
+    ///
+    /// Positions from a WAL that was reset by another process without the
+    /// manager ever seeing it never resolve, so callers should apply a timeout.
+    pub async fn wait_replicated(&self, position: WalPosition) -> Result<()> {
+        let mut progress = self.progress.clone();
+        progress
+            .wait_for(|p| p.covers(&position))
+            .await
+            .map(|_| ())
+            .map_err(|_| Error::Other("replication task stopped".into()))
+    }
+
+    /// Sync new WAL frames immediately instead of waiting for the next
+    /// trigger. Returns true if new data was uploaded.
+    pub async fn sync_now(&self) -> Result<bool> {
+        let (tx, rx) = oneshot::channel();
+        self.send(Command::SyncNow(tx)).await?;
+        rx.await
+            .map_err(|_| Error::Other("replication task stopped".into()))?
+    }
+
+    /// Replication statistics, like [`BackupManager::stats`].
+    pub async fn stats(&self) -> Result<BackupStats> {
+        self.with_manager(|mgr| Box::pin(async move { mgr.stats() }))
+            .await
+    }
+
+    /// Replication health, like [`BackupManager::health`].
+    pub async fn health(&self) -> Result<Health> {
+        self.with_manager(|mgr| Box::pin(async move { mgr.health() }))
+            .await
+    }
+
+    /// Metrics in the Prometheus text format, like
+    /// [`BackupManager::prometheus_metrics`].
+    #[cfg(feature = "metrics")]
+    pub async fn prometheus_metrics(&self) -> Result<String> {
+        self.with_manager(|mgr| Box::pin(async move { mgr.prometheus_metrics() }))
+            .await
+    }
+
+    /// Upload a snapshot of the current generation, like
+    /// [`BackupManager::snapshot`].
+    pub async fn snapshot(&self) -> Result<()> {
+        self.with_manager(|mgr| Box::pin(mgr.snapshot())).await?
+    }
+
+    /// Checkpoint and start a new generation, like
+    /// [`BackupManager::checkpoint`].
+    pub async fn checkpoint(&self) -> Result<()> {
+        self.with_manager(|mgr| Box::pin(mgr.checkpoint())).await?
+    }
+
+    /// Run `f` on the manager inside the replication task, between syncs.
+    pub(crate) async fn with_manager<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: for<'a> FnOnce(&'a mut BackupManager) -> ManagerFuture<'a, T> + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move |mgr| {
+            Box::pin(async move {
+                let _ = tx.send(f(mgr).await);
+            })
+        });
+        self.send(Command::Run(job)).await?;
+        rx.await
+            .map_err(|_| Error::Other("replication task stopped".into()))
+    }
+
+    /// Stop the replication task, performing a graceful
+    /// [`shutdown`](crate::BackupManager::shutdown) of the manager.
+    pub async fn shutdown(&self) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.send(Command::Shutdown(tx)).await?;
+        rx.await
+            .map_err(|_| Error::Other("replication task stopped".into()))?
+    }
+
+    async fn send(&self, command: Command) -> Result<()> {
+        self.commands
+            .send(command)
+            .await
+            .map_err(|_| Error::Other("replication task stopped".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(salt: u8, offset: u64) -> WalPosition {
+        WalPosition {
+            salt: [salt; WAL_SALT_LEN],
+            offset,
+        }
+    }
+
+    #[test]
+    fn progress_covers_same_salt_up_to_offset() {
+        let mut p = ReplicationProgress::default();
+        assert!(!p.covers(&position(1, 100)));
+        p.advance([1; WAL_SALT_LEN], 100);
+        assert!(p.covers(&position(1, 100)));
+        assert!(p.covers(&position(1, 50)));
+        assert!(!p.covers(&position(1, 101)));
+        assert!(!p.covers(&position(2, 50)));
+    }
+
+    #[test]
+    fn progress_covers_retired_salts() {
+        let mut p = ReplicationProgress::default();
+        p.advance([1; WAL_SALT_LEN], 100);
+        p.retire([1; WAL_SALT_LEN]);
+        assert!(p.covers(&position(1, 10_000)));
+        p.advance([2; WAL_SALT_LEN], 64);
+        assert!(p.covers(&position(1, 10_000)));
+        assert!(!p.covers(&position(2, 65)));
+    }
+
+    #[test]
+    fn progress_forgets_oldest_retired_salt() {
+        let mut p = ReplicationProgress::default();
+        for salt in 0..=RETIRED_SALTS as u8 {
+            p.retire([salt; WAL_SALT_LEN]);
+        }
+        assert!(!p.covers(&position(0, 1)));
+        assert!(p.covers(&position(1, 1)));
+    }
+
+    #[test]
+    fn position_of_missing_or_empty_wal() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("app.db");
+        let db_path = db_path.to_str().unwrap();
+        assert_eq!(WalPosition::current(db_path).unwrap(), None);
+        std::fs::write(format!("{db_path}-wal"), [0u8; 32]).unwrap();
+        assert_eq!(WalPosition::current(db_path).unwrap(), None);
+    }
+
+    #[test]
+    fn position_after_commit() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("app.db");
+        let db_path = db_path.to_str().unwrap();
+        let conn = rusqlite::Connection::open(db_path).unwrap();
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA wal_autocheckpoint = 0;
+             CREATE TABLE t (x INTEGER);",
+        )
+        .unwrap();
+        let first = WalPosition::current(db_path).unwrap().unwrap();
+        conn.execute("INSERT INTO t VALUES (1)", []).unwrap();
+        let second = WalPosition::current(db_path).unwrap().unwrap();
+        assert_eq!(first.salt, second.salt);
+        assert!(second.offset() > first.offset());
+    }
+}
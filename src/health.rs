@@ -0,0 +1,190 @@
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+use crate::config::{HealthThresholds, Threshold};
+
+/// Overall replication health, ordered from best to worst.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    Healthy,
+    /// Replication is behind or retrying, but still making progress.
+    Degraded,
+    /// Replication has stalled; recent writes are at risk.
+    Failing,
+}
+
+impl fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HealthStatus::Healthy => "healthy",
+            HealthStatus::Degraded => "degraded",
+            HealthStatus::Failing => "failing",
+        })
+    }
+}
+
+/// Result of [`BackupManager::health`](crate::BackupManager::health).
+///
+/// Serializes the ages as `sync_age_secs` and `snapshot_age_secs`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Health {
+    pub status: HealthStatus,
+    /// One explanation per measurement that crossed a threshold.
+    pub reasons: Vec<String>,
+    /// Time since the last successful sync (or the initial snapshot, before
+    /// the first sync).
+    #[serde(rename = "sync_age_secs", serialize_with = "secs")]
+    pub sync_age: Option<Duration>,
+    /// WAL bytes not yet uploaded.
+    pub pending_wal_bytes: u64,
+    /// Failed syncs since the last successful one.
+    pub consecutive_errors: u32,
+    /// Time since the last snapshot.
+    #[serde(rename = "snapshot_age_secs", serialize_with = "secs")]
+    pub snapshot_age: Option<Duration>,
+}
+
+fn secs<S: Serializer>(age: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+    age.map(|d| d.as_secs_f64()).serialize(serializer)
+}
+
+impl Health {
+    /// Evaluate measurements against `thresholds`.
+    pub(crate) fn evaluate(
+        thresholds: &HealthThresholds,
+        sync_age: Option<Duration>,
+        pending_wal_bytes: u64,
+        consecutive_errors: u32,
+        snapshot_age: Option<Duration>,
+    ) -> Self {
+        let mut health = Self {
+            status: HealthStatus::Healthy,
+            reasons: Vec::new(),
+            sync_age,
+            pending_wal_bytes,
+            consecutive_errors,
+            snapshot_age,
+        };
+        if let Some(age) = sync_age {
+            health.check("last successful sync", age, &thresholds.sync_age, |d| {
+                format!("{:.1}s ago", d.as_secs_f64())
+            });
+        }
+        health.check(
+            "pending WAL",
+            pending_wal_bytes,
+            &thresholds.pending_wal_bytes,
+            |b| format!("{b} bytes"),
+        );
+        health.check(
+            "consecutive sync errors",
+            consecutive_errors,
+            &thresholds.consecutive_errors,
+            |n| n.to_string(),
+        );
+        if let (Some(age), Some(threshold)) = (snapshot_age, &thresholds.snapshot_age) {
+            health.check("last snapshot", age, threshold, |d| {
+                format!("{:.1}s ago", d.as_secs_f64())
+            });
+        }
+        health
+    }
+
+    fn check<T: PartialOrd + Copy>(
+        &mut self,
+        what: &str,
+        value: T,
+        threshold: &Threshold<T>,
+        show: impl Fn(T) -> String,
+    ) {
+        let (status, limit) = if value >= threshold.failing {
+            (HealthStatus::Failing, threshold.failing)
+        } else if value >= threshold.degraded {
+            (HealthStatus::Degraded, threshold.degraded)
+        } else {
+            return;
+        };
+        self.status = self.status.max(status);
+        self.reasons.push(format!(
+            "{what}: {} (limit for {status}: {})",
+            show(value),
+            show(limit)
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(sync_age_secs: u64, pending: u64, errors: u32) -> Health {
+        Health::evaluate(
+            &HealthThresholds::default(),
+            Some(Duration::from_secs(sync_age_secs)),
+            pending,
+            errors,
+            Some(Duration::from_secs(86400)),
+        )
+    }
+
+    #[test]
+    fn healthy_within_thresholds() {
+        let health = evaluate(1, 0, 0);
+        assert_eq!(health.status, HealthStatus::Healthy);
+        assert!(health.reasons.is_empty());
+    }
+
+    #[test]
+    fn serializes_ages_in_seconds() {
+        let json = serde_json::to_value(evaluate(2, 0, 0)).unwrap();
+        assert_eq!(json["status"], "healthy");
+        assert_eq!(json["sync_age_secs"], 2.0);
+        assert_eq!(json["snapshot_age_secs"], 86400.0);
+    }
+
+    #[test]
+    fn worst_check_wins() {
+        let health = evaluate(60, 0, 0);
+        assert_eq!(health.status, HealthStatus::Degraded);
+        let health = evaluate(60, 0, 10);
+        assert_eq!(health.status, HealthStatus::Failing);
+        assert_eq!(health.reasons.len(), 2);
+        assert!(health.reasons[1].starts_with("consecutive sync errors: 10"));
+    }
+
+    #[test]
+    fn pending_bytes_thresholds() {
+        assert_eq!(
+            evaluate(1, 16 * 1024 * 1024, 0).status,
+            HealthStatus::Degraded
+        );
+        assert_eq!(
+            evaluate(1, 64 * 1024 * 1024, 0).status,
+            HealthStatus::Failing
+        );
+    }
+
+    #[test]
+    fn snapshot_age_only_checked_when_configured() {
+        let thresholds = HealthThresholds {
+            snapshot_age: Some(Threshold {
+                degraded: Duration::from_secs(3600),
+                failing: Duration::from_secs(7200),
+            }),
+            ..Default::default()
+        };
+        let health = Health::evaluate(&thresholds, None, 0, 0, Some(Duration::from_secs(4000)));
+        assert_eq!(health.status, HealthStatus::Degraded);
+        assert!(health.reasons[0].starts_with("last snapshot"));
+    }
+
+    #[test]
+    fn status_ordering_and_display() {
+        assert!(HealthStatus::Healthy < HealthStatus::Degraded);
+        assert!(HealthStatus::Degraded < HealthStatus::Failing);
+        assert_eq!(HealthStatus::Failing.to_string(), "failing");
+    }
+}
@@ -1,19 +1,56 @@
+#[cfg(feature = "axum")]
+pub mod admin;
+mod alerts;
 pub mod compression;
 #[cfg(feature = "compression-lz4")]
 mod compression_lz4;
 #[cfg(feature = "compression-zstd")]
 mod compression_zstd;
 mod config;
+#[cfg(feature = "config-file")]
+mod config_file;
+mod delta;
+mod discovery;
 #[cfg(feature = "encryption")]
 pub mod encryption;
 mod error;
+mod events;
+mod handle;
+mod health;
 mod manager;
 mod manifest;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod preflight;
+mod replica_set;
 mod s3;
+#[cfg(feature = "sqlx")]
+pub mod sqlite_pool;
 mod stats;
+mod trigger;
+mod wal;
 
-pub use config::{BackupConfig, CompressionAlgorithm, S3Config};
+pub use alerts::{Alert, AlertKind};
+#[cfg(feature = "watch")]
+pub use config::WatchConfig;
+pub use config::{
+    AlertConfig, AlertTarget, BackupConfig, CompressionAlgorithm, DeltaSnapshotConfig,
+    HealthThresholds, S3Config, SnapshotSource, Threshold, parse_duration,
+};
+#[cfg(feature = "config-file")]
+pub use config_file::ConfigFormat;
+pub use discovery::{DirectoryDiscovery, DiscoveryChanges};
 pub use error::{Error, Result};
-pub use manager::{BackupManager, CompactionResult};
+pub use events::{GenerationReason, ReplicationEvent};
+pub use handle::{ReplicationHandle, WalPosition};
+pub use health::{Health, HealthStatus};
+pub use manager::{
+    BackupManager, CompactionResult, CopyResult, GenerationInfo, PruneResult, RestorePlan,
+    StoredObject,
+};
 pub use manifest::{GenerationManifest, SegmentMeta};
-pub use stats::BackupStats;
+pub use preflight::{CheckStatus, PreflightCheck, PreflightReport, check_connection, preflight};
+pub use replica_set::{ReplicaSet, ReplicaSetStats};
+pub use stats::{BackupStats, LastError};
+pub use trigger::{SyncNotifier, SyncTrigger};
+pub use wal::{WalFile, WalFrame, WalHeader};
@@ -1,31 +1,50 @@
-use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use rusqlite::Connection;
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::{Connection, MAIN_DB};
+use serde::Serialize;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
 
+use crate::alerts;
 use crate::compression;
-use crate::config::{BackupConfig, S3Config};
+use crate::config::{BackupConfig, CompressionAlgorithm, S3Config, SnapshotSource};
+use crate::delta::{self, PageHash};
 use crate::error::{Error, Result};
-use crate::manifest::GenerationManifest;
+use crate::events::{EVENT_CAPACITY, GenerationReason, ReplicationEvent};
+use crate::handle::{Command, ReplicationHandle, ReplicationProgress};
+use crate::health::Health;
+use crate::manifest::{GenerationManifest, SegmentMeta};
 use crate::s3::S3Client;
 use crate::stats::{BackupStats, StatsTracker};
+use crate::trigger::{SyncNotifier, SyncTrigger, TriggerSource};
 
-const WAL_HEADER_SIZE: u64 = 32;
-const WAL_FRAME_HEADER_SIZE: u64 = 24;
+pub(crate) const WAL_HEADER_SIZE: u64 = 32;
+pub(crate) const WAL_FRAME_HEADER_SIZE: u64 = 24;
 /// Offset of the salt fields in the WAL header (bytes 16..24).
-const WAL_SALT_OFFSET: usize = 16;
-const WAL_SALT_LEN: usize = 8;
+pub(crate) const WAL_SALT_OFFSET: usize = 16;
+pub(crate) const WAL_SALT_LEN: usize = 8;
+/// Upper bound on delta snapshot chain length followed during restore.
+const MAX_DELTA_CHAIN: usize = 1024;
+/// How long a sync triggered by a commit waits for the commit to reach the WAL.
+const COMMIT_WAIT: Duration = Duration::from_secs(1);
+/// How long a backup API snapshot waits for a locked database.
+const BACKUP_API_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Compute the largest prefix of `wal_data` that contains only complete frames.
 /// Returns `WAL_HEADER_SIZE` (i.e. zero complete frames) if the WAL is too short
 /// or the page size cannot be parsed.
 fn wal_aligned_len(wal_data: &[u8]) -> u64 {
-    let len = wal_data.len() as u64;
-    if len < WAL_HEADER_SIZE + WAL_FRAME_HEADER_SIZE {
+    frame_aligned_len(wal_data, wal_data.len() as u64)
+}
+
+/// Like [`wal_aligned_len`], for a WAL of `len` bytes starting with `header`.
+pub(crate) fn frame_aligned_len(header: &[u8], len: u64) -> u64 {
+    if len < WAL_HEADER_SIZE + WAL_FRAME_HEADER_SIZE || header.len() < 12 {
         return WAL_HEADER_SIZE.min(len);
     }
     // Page size is stored as big-endian u32 at bytes 8..12 of the WAL header.
-    let page_size =
-        u32::from_be_bytes([wal_data[8], wal_data[9], wal_data[10], wal_data[11]]) as u64;
+    let page_size = u32::from_be_bytes([header[8], header[9], header[10], header[11]]) as u64;
     if page_size == 0 {
         return WAL_HEADER_SIZE;
     }
@@ -35,7 +54,45 @@ fn wal_aligned_len(wal_data: &[u8]) -> u64 {
     WAL_HEADER_SIZE + complete_frames * frame_size
 }
 
-fn now_ms() -> u64 {
+/// Copy a database through SQLite's online backup API into memory and return
+/// the serialized image. All pages are copied in one step, so the image
+/// reflects a single committed transaction.
+///
+/// Gives up with an error if the database stays locked for `busy_timeout`.
+fn backup_api_image(db_path: &str, busy_timeout: Duration) -> Result<Vec<u8>> {
+    let deadline = Instant::now() + busy_timeout;
+    let src = Connection::open(db_path)?;
+    src.busy_timeout(busy_timeout)?;
+    let page_size: i64 = src.query_row("PRAGMA page_size", [], |row| row.get(0))?;
+
+    // An in-memory destination must use the source page size for the copy to succeed.
+    let mut dst = Connection::open_in_memory()?;
+    dst.execute_batch(&format!("PRAGMA page_size = {page_size};"))?;
+    {
+        let backup = Backup::new(&src, &mut dst)?;
+        loop {
+            match backup.step(-1)? {
+                StepResult::Done => break,
+                StepResult::More => {}
+                _ if Instant::now() >= deadline => {
+                    return Err(Error::Other(format!(
+                        "backup API snapshot: database locked for more than {busy_timeout:?}"
+                    )));
+                }
+                _ => std::thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    }
+    Ok(dst.serialize(MAIN_DB)?.to_vec())
+}
+
+/// Length of the file at `path`, or 0 if there is none.
+async fn file_len(path: &str) -> u64 {
+    tokio::fs::metadata(path).await.map_or(0, |m| m.len())
+}
+
+/// Milliseconds since the Unix epoch (UTC).
+pub(crate) fn now_ms() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default()
@@ -43,12 +100,91 @@ fn now_ms() -> u64 {
 }
 
 /// Result of a compaction operation.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
 pub struct CompactionResult {
     pub segments_before: u32,
     pub segments_after: u32,
 }
 
+/// Outcome of [`BackupManager::prune`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PruneResult {
+    /// Generations deleted, or that would be with `dry_run`, oldest first.
+    pub deleted: Vec<String>,
+    /// Generations kept, oldest first.
+    pub retained: Vec<String>,
+    /// Objects and stored bytes of the deleted generations.
+    pub objects: usize,
+    pub bytes: u64,
+}
+
+/// Outcome of [`BackupManager::copy_replica`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CopyResult {
+    /// Generations with a manifest in the source.
+    pub generations: usize,
+    /// Objects written to the destination, and their stored size there.
+    pub objects_copied: usize,
+    pub bytes_copied: u64,
+    /// Objects already in the destination from an earlier copy.
+    pub objects_skipped: usize,
+}
+
+/// What a restore would download, from [`BackupManager::restore_plan`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RestorePlan {
+    /// The generation restored.
+    pub generation: String,
+    /// Generations whose snapshots make up the image: the full snapshot
+    /// first, then the deltas applied on top of it.
+    pub snapshot_chain: Vec<String>,
+    pub snapshot_timestamp_ms: u64,
+    /// WAL segments replayed on top of the snapshot, in order.
+    pub segments: Vec<SegmentMeta>,
+    /// Total size of those segments before compression and encryption.
+    pub wal_bytes: u64,
+    /// Upload time of the last replayed segment, or of the snapshot.
+    pub restores_to_ms: u64,
+}
+
+/// Contents of a generation in the replica, from
+/// [`BackupManager::generation_info`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct GenerationInfo {
+    pub generation: String,
+    /// `None` for generations written before manifests existed.
+    pub manifest: Option<GenerationManifest>,
+    /// The stored objects, sorted by key.
+    pub objects: Vec<StoredObject>,
+    /// Stored size of the snapshot or delta snapshot.
+    pub snapshot_bytes: u64,
+    /// Stored size of the WAL segments.
+    pub wal_bytes: u64,
+    /// Stored size of all objects.
+    pub total_bytes: u64,
+    /// Whether the manifest is encrypted; `None` without a manifest.
+    pub encrypted: Option<bool>,
+    /// `lz4`, `zstd` or `none`, detected from the manifest.
+    pub compression: Option<&'static str>,
+}
+
+/// An object in the replica.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StoredObject {
+    /// Key relative to the replica prefix, e.g. `{generation}/wal/00000001`.
+    pub key: String,
+    pub size: u64,
+}
+
+/// Page hashes of the most recent snapshot, used as the base of the next delta.
+struct SnapshotPages {
+    generation: String,
+    page_size: usize,
+    hashes: Vec<PageHash>,
+    /// Number of deltas between this snapshot and its full base.
+    chain_length: u32,
+}
+
 /// Manages Litestream-style progressive backups of a SQLite database to S3.
 pub struct BackupManager {
     config: BackupConfig,
@@ -68,10 +204,18 @@ pub struct BackupManager {
     stats: StatsTracker,
     /// When the last snapshot was taken (for snapshot scheduling).
     last_snapshot_time: Instant,
+    /// Page hashes of the last snapshot (only tracked when delta snapshots are enabled).
+    snapshot_pages: Option<SnapshotPages>,
     /// Whether shutdown() has been called.
     shutdown_complete: bool,
     /// Whether a read transaction is currently active.
     has_read_transaction: bool,
+    /// Replication progress, observed by [`ReplicationHandle`]s.
+    progress: watch::Sender<ReplicationProgress>,
+    /// Wakes the sync loop on application commits.
+    notifier: SyncNotifier,
+    /// Replication activity, delivered to [`subscribe`](Self::subscribe) receivers.
+    events: broadcast::Sender<ReplicationEvent>,
 }
 
 impl BackupManager {
@@ -82,6 +226,13 @@ impl BackupManager {
     /// - Starts a long-running read transaction (pins the WAL)
     /// - Takes an initial full snapshot and uploads it to S3
     pub async fn new(config: BackupConfig) -> Result<Self> {
+        let s3 = S3Client::new(&config.s3)?;
+        Self::with_client(config, s3).await
+    }
+
+    /// Create a BackupManager that uploads through an existing S3 client.
+    /// The client's prefix takes the place of `config.s3.prefix`.
+    pub(crate) async fn with_client(config: BackupConfig, s3: S3Client) -> Result<Self> {
         // Auto-restore: if DB doesn't exist and flag is set, restore from S3
         if config.auto_restore
             && !tokio::fs::try_exists(&config.db_path)
@@ -92,8 +243,6 @@ impl BackupManager {
             Self::restore_with_config(&config, &config.db_path).await?;
         }
 
-        let s3 = S3Client::new(&config.s3)?;
-
         let read_conn = Connection::open(&config.db_path)?;
         read_conn.execute_batch(
             "PRAGMA journal_mode = WAL;
@@ -111,6 +260,15 @@ impl BackupManager {
         let ts = now_ms();
         let manifest = GenerationManifest::new(generation.clone(), ts);
 
+        let events = broadcast::Sender::new(EVENT_CAPACITY);
+        if !config.alerts.targets.is_empty() {
+            alerts::spawn(
+                config.db_path.clone(),
+                config.alerts.clone(),
+                events.subscribe(),
+            );
+        }
+
         let mut mgr = Self {
             config,
             s3,
@@ -122,8 +280,12 @@ impl BackupManager {
             manifest,
             stats: StatsTracker::new(),
             last_snapshot_time: Instant::now(),
+            snapshot_pages: None,
             shutdown_complete: false,
             has_read_transaction: true,
+            progress: watch::Sender::new(ReplicationProgress::default()),
+            notifier: SyncNotifier::default(),
+            events,
         };
 
         mgr.snapshot().await?;
@@ -155,11 +317,30 @@ impl BackupManager {
         format!("{}-wal", self.config.db_path)
     }
 
+    /// Current size of the `-wal` file, or 0 if it does not exist.
+    fn wal_size(&self) -> u64 {
+        std::fs::metadata(self.wal_path())
+            .map(|m| m.len())
+            .unwrap_or(0)
+    }
+
     /// Apply the data pipeline before upload: compress, then optionally encrypt.
     fn pipeline_encode(&self, data: &[u8]) -> Result<Vec<u8>> {
-        let compressed = compression::compress(data, &self.config.compression)?;
         #[cfg(feature = "encryption")]
-        if let Some(ref key) = self.config.encryption_key {
+        let key = self.config.encryption_key.as_deref();
+        #[cfg(not(feature = "encryption"))]
+        let key = None;
+        Self::pipeline_encode_with(data, &self.config.compression, key)
+    }
+
+    fn pipeline_encode_with(
+        data: &[u8],
+        compression: &CompressionAlgorithm,
+        #[allow(unused)] encryption_key: Option<&str>,
+    ) -> Result<Vec<u8>> {
+        let compressed = compression::compress(data, compression)?;
+        #[cfg(feature = "encryption")]
+        if let Some(key) = encryption_key {
             return crate::encryption::encrypt(&compressed, key);
         }
         Ok(compressed)
@@ -172,6 +353,10 @@ impl BackupManager {
         {
             decrypted = if let Some(ref key) = config.encryption_key {
                 crate::encryption::decrypt(data, key)?
+            } else if crate::encryption::is_encrypted(data) {
+                return Err(Error::Other(
+                    "backup is encrypted, but no encryption key is configured".into(),
+                ));
             } else {
                 data.to_vec()
             };
@@ -183,41 +368,186 @@ impl BackupManager {
         compression::decompress(&decrypted)
     }
 
-    /// Upload a full copy of the database file as a snapshot.
+    /// Read the database image for a snapshot from the configured source.
+    async fn read_snapshot_image(config: &BackupConfig) -> Result<Vec<u8>> {
+        match config.snapshot_source {
+            SnapshotSource::File => Ok(tokio::fs::read(&config.db_path).await?),
+            SnapshotSource::BackupApi => {
+                let db_path = config.db_path.clone();
+                tokio::task::spawn_blocking(move || {
+                    backup_api_image(&db_path, BACKUP_API_BUSY_TIMEOUT)
+                })
+                .await
+                .map_err(|e| Error::Other(format!("snapshot task: {e}")))?
+            }
+        }
+    }
+
+    /// Upload a copy of the database as a snapshot.
     ///
-    /// Safety: the active read transaction prevents SQLite from checkpointing,
-    /// so the DB file is stable and safe to read even while the application writes.
+    /// When delta snapshots are enabled, only the pages that changed since the
+    /// previous generation's snapshot are uploaded, unless the delta policy
+    /// calls for a fresh full snapshot.
+    ///
+    /// Safety: with [`SnapshotSource::File`], the active read transaction prevents
+    /// SQLite from checkpointing, so the DB file is stable and safe to read even
+    /// while the application writes. [`SnapshotSource::BackupApi`] copies a
+    /// consistent image through SQLite itself.
     pub async fn snapshot(&mut self) -> Result<()> {
-        let raw_data = tokio::fs::read(&self.config.db_path).await?;
-        let data = self.pipeline_encode(&raw_data)?;
-        let key = format!("{}/snapshot", self.generation);
-        self.s3.put_object(&key, &data).await?;
+        let result = self.snapshot_inner().await;
+        if let Err(e) = &result {
+            self.emit(ReplicationEvent::SnapshotFailed {
+                generation: self.generation.clone(),
+                kind: e.kind(),
+                error: e.to_string(),
+            });
+        }
+        result
+    }
 
-        // Record this as the latest generation
-        self.s3
-            .put_object("latest", self.generation.as_bytes())
-            .await?;
+    async fn snapshot_inner(&mut self) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        let started = Instant::now();
+        let raw_data = Self::read_snapshot_image(&self.config).await?;
+        let page_size = self
+            .config
+            .delta_snapshots
+            .as_ref()
+            .and_then(|_| delta::db_page_size(&raw_data));
+        let hashes = page_size.map(|ps| delta::page_hashes(&raw_data, ps));
+
+        let plan = match (page_size, &hashes) {
+            (Some(ps), Some(h)) => self.plan_delta(ps, h),
+            _ => None,
+        };
+
+        let (uploaded, raw_len, chain_length, is_delta) = match plan {
+            Some((base, changed, chain_length)) => {
+                let page_size = page_size.unwrap_or_default();
+                let payload = delta::encode(&raw_data, page_size, &changed);
+                let data = self.pipeline_encode(&payload)?;
+                let key = format!("{}/delta", self.generation);
+                self.s3.put_object(&key, &data).await?;
+                tracing::info!(
+                    generation = %self.generation,
+                    base = %base,
+                    pages = changed.len(),
+                    "delta snapshot uploaded"
+                );
+                self.manifest.base_generation = Some(base);
+                (data.len() as u64, payload.len() as u64, chain_length, true)
+            }
+            None => {
+                let data = self.pipeline_encode(&raw_data)?;
+                let key = format!("{}/snapshot", self.generation);
+                self.s3.put_object(&key, &data).await?;
+                // A full snapshot supersedes an earlier delta in the same generation.
+                if self.manifest.base_generation.take().is_some() {
+                    let delta_key = format!("{}/delta", self.generation);
+                    if let Err(e) = self.s3.delete_object(&delta_key).await {
+                        tracing::warn!(error = %e, "failed to delete superseded delta snapshot");
+                    }
+                }
+                tracing::info!(generation = %self.generation, "snapshot uploaded");
+                (data.len() as u64, raw_data.len() as u64, 0, false)
+            }
+        };
+
+        self.snapshot_pages = page_size
+            .zip(hashes)
+            .map(|(page_size, hashes)| SnapshotPages {
+                generation: self.generation.clone(),
+                page_size,
+                hashes,
+                chain_length,
+            });
 
         // Reset WAL tracking — segments are relative to the snapshot
         self.wal_offset = 0;
         self.wal_index = 0;
         self.wal_header_salt = None;
 
-        self.stats.record_snapshot(data.len() as u64);
+        self.stats.record_snapshot(uploaded);
+        self.stats.record_raw_bytes(raw_len);
         self.last_snapshot_time = Instant::now();
 
-        // Update manifest
+        // Update manifest before the `latest` marker so a restore never sees a
+        // delta snapshot without the manifest that names its base.
         self.manifest.snapshot_timestamp_ms = now_ms();
         self.manifest.segments.clear();
         self.upload_manifest().await?;
 
-        tracing::info!(generation = %self.generation, "snapshot uploaded");
+        // Record this as the latest generation
+        self.s3
+            .put_object("latest", self.generation.as_bytes())
+            .await?;
+
+        self.emit(ReplicationEvent::SnapshotUploaded {
+            generation: self.generation.clone(),
+            uploaded_bytes: uploaded,
+            delta: is_delta,
+        });
+        #[cfg(feature = "metrics")]
+        self.stats
+            .metrics
+            .snapshot_duration
+            .observe_duration(started.elapsed());
         Ok(())
     }
 
+    /// Decide whether the next snapshot can be a delta against the previous
+    /// snapshot. Returns the base generation, the changed page indices and the
+    /// resulting chain length, or `None` if a full snapshot should be taken.
+    fn plan_delta(&self, page_size: usize, hashes: &[PageHash]) -> Option<(String, Vec<u32>, u32)> {
+        let policy = self.config.delta_snapshots.as_ref()?;
+        let prev = self.snapshot_pages.as_ref()?;
+        // A generation cannot be its own base, and the page layout must match.
+        if prev.generation == self.generation || prev.page_size != page_size {
+            return None;
+        }
+        if prev.chain_length >= policy.max_chain_length {
+            return None;
+        }
+        let changed = delta::changed_pages(&prev.hashes, hashes);
+        if changed.len() as u64 * 100 > hashes.len() as u64 * policy.max_changed_percent as u64 {
+            return None;
+        }
+        Some((prev.generation.clone(), changed, prev.chain_length + 1))
+    }
+
     /// Sync new WAL frames to S3. Only uploads complete, frame-aligned data
     /// added since the last sync. Returns true if new data was uploaded.
+    /// Failures are counted in [`BackupStats::error_count`].
     pub async fn sync_wal(&mut self) -> Result<bool> {
+        #[cfg(feature = "metrics")]
+        let started = Instant::now();
+        let result = self.sync_wal_inner().await;
+        match &result {
+            Ok(_uploaded) => {
+                self.stats.record_success();
+                #[cfg(feature = "metrics")]
+                if *_uploaded {
+                    self.stats
+                        .metrics
+                        .sync_duration
+                        .observe_duration(started.elapsed());
+                }
+            }
+            Err(e) => {
+                self.stats.record_error(e);
+                #[cfg(feature = "metrics")]
+                self.stats.metrics.record_error(e);
+                self.emit(ReplicationEvent::SyncFailed {
+                    kind: e.kind(),
+                    error: e.to_string(),
+                    consecutive_errors: self.stats.consecutive_errors,
+                });
+            }
+        }
+        result
+    }
+
+    async fn sync_wal_inner(&mut self) -> Result<bool> {
         let wal_path = self.wal_path();
         if !tokio::fs::try_exists(&wal_path).await.unwrap_or(false) {
             return Ok(false);
@@ -234,7 +564,16 @@ impl BackupManager {
         // Check for WAL discontinuity (salt change or shrink)
         if self.wal_needs_recovery(&wal_data, wal_len) {
             tracing::warn!("WAL discontinuity detected, starting recovery");
+            // Only a WAL with a new salt has restarted; a shrunk WAL that kept
+            // its salt may still receive frames under it.
+            let restarted = self.wal_header_salt.filter(|salt| {
+                wal_data[WAL_SALT_OFFSET..WAL_SALT_OFFSET + WAL_SALT_LEN] != salt[..]
+            });
             self.recover().await?;
+            // Every frame of the previous WAL is now part of the snapshot.
+            if let Some(salt) = restarted {
+                self.progress.send_modify(|p| p.retire(salt));
+            }
             // Re-read WAL after recovery
             return Ok(false);
         }
@@ -273,11 +612,52 @@ impl BackupManager {
         self.upload_manifest().await?;
 
         self.stats.record_sync(encoded.len() as u64);
+        self.stats.record_raw_bytes(segment_size);
+        #[cfg(feature = "metrics")]
+        self.stats
+            .metrics
+            .segment_size
+            .observe(encoded.len() as f64);
         self.wal_offset = aligned_len;
         self.wal_index += 1;
+        if let Some(salt) = self.wal_header_salt {
+            self.progress.send_modify(|p| p.advance(salt, aligned_len));
+        }
+        self.emit(ReplicationEvent::SegmentUploaded {
+            generation: self.generation.clone(),
+            index: self.wal_index - 1,
+            offset: aligned_len - segment_size,
+            bytes: segment_size,
+            uploaded_bytes: encoded.len() as u64,
+        });
         Ok(true)
     }
 
+    /// Sync after a commit notification. The commit hook fires before SQLite
+    /// writes the commit, so if the first sync finds nothing new, wait up to
+    /// [`COMMIT_WAIT`] for the WAL to grow. Then sync again for as long as the
+    /// WAL keeps growing while a sync runs.
+    async fn sync_commit(&mut self) -> Result<bool> {
+        let wal_path = self.wal_path();
+        let mut uploaded = self.sync_wal().await?;
+        if !uploaded {
+            let deadline = Instant::now() + COMMIT_WAIT.min(self.config.sync_interval);
+            while file_len(&wal_path).await <= self.wal_offset.max(WAL_HEADER_SIZE) {
+                if Instant::now() >= deadline {
+                    return Ok(false);
+                }
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            uploaded = self.sync_wal().await?;
+        }
+        while uploaded && file_len(&wal_path).await > self.wal_offset {
+            if !self.sync_wal().await? {
+                break;
+            }
+        }
+        Ok(uploaded)
+    }
+
     /// Detect WAL discontinuity: shrink or salt change.
     fn wal_needs_recovery(&self, wal_data: &[u8], wal_len: u64) -> bool {
         check_wal_discontinuity(
@@ -295,10 +675,7 @@ impl BackupManager {
         // End current read transaction
         self.end_read_transaction();
 
-        // New generation
-        self.generation = uuid::Uuid::new_v4().to_string();
-        self.manifest = GenerationManifest::new(self.generation.clone(), now_ms());
-        self.stats.record_new_generation();
+        self.start_generation(GenerationReason::Recovery);
 
         // Take a fresh snapshot
         self.snapshot().await?;
@@ -310,23 +687,41 @@ impl BackupManager {
         Ok(())
     }
 
+    /// Start a new generation, to be followed by a snapshot.
+    fn start_generation(&mut self, reason: GenerationReason) {
+        self.generation = uuid::Uuid::new_v4().to_string();
+        self.manifest = GenerationManifest::new(self.generation.clone(), now_ms());
+        self.stats.record_new_generation();
+        self.emit(ReplicationEvent::GenerationStarted {
+            generation: self.generation.clone(),
+            reason,
+        });
+    }
+
     /// Checkpoint the database: flush WAL to the main DB file, then start
     /// a new generation with a fresh snapshot.
     pub async fn checkpoint(&mut self) -> Result<()> {
+        self.checkpoint_with_reason(GenerationReason::Checkpoint)
+            .await
+    }
+
+    async fn checkpoint_with_reason(&mut self, reason: GenerationReason) -> Result<()> {
+        #[cfg(feature = "metrics")]
+        let started = Instant::now();
         // Sync any remaining WAL data before checkpointing
         self.sync_wal().await?;
 
         // Release the read transaction so checkpoint can proceed.
         self.end_read_transaction();
 
-        // Checkpoint: write WAL pages back to DB and truncate WAL
-        self.read_conn
-            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE)")?;
+        // Checkpoint: write WAL pages back to DB and truncate WAL. If another
+        // connection blocked it, the WAL keeps its salt and is not restarted.
+        let busy: i64 = self
+            .read_conn
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| row.get(0))?;
+        let restarted = self.wal_header_salt.filter(|_| busy == 0);
 
-        // New generation
-        self.generation = uuid::Uuid::new_v4().to_string();
-        self.manifest = GenerationManifest::new(self.generation.clone(), now_ms());
-        self.stats.record_new_generation();
+        self.start_generation(reason);
 
         // Take a fresh snapshot (DB is now fully up to date).
         // Always re-acquire the read transaction even if snapshot fails,
@@ -335,6 +730,16 @@ impl BackupManager {
         self.begin_read_transaction()?;
         snapshot_result?;
 
+        // Every frame of the truncated WAL is now part of the snapshot.
+        if let Some(salt) = restarted {
+            self.progress.send_modify(|p| p.retire(salt));
+        }
+        self.stats.record_checkpoint();
+        #[cfg(feature = "metrics")]
+        self.stats
+            .metrics
+            .checkpoint_duration
+            .observe_duration(started.elapsed());
         tracing::info!(generation = %self.generation, "checkpoint complete, new generation");
         Ok(())
     }
@@ -345,12 +750,140 @@ impl BackupManager {
         if let Some(interval) = self.config.snapshot_interval
             && self.last_snapshot_time.elapsed() >= interval
         {
-            self.checkpoint().await?;
+            self.checkpoint_with_reason(GenerationReason::Schedule)
+                .await?;
             return Ok(true);
         }
         Ok(false)
     }
 
+    /// Run the sync loop until `shutdown` completes, then perform a graceful
+    /// [`shutdown`](Self::shutdown).
+    ///
+    /// Syncs are triggered every `sync_interval`, or by filesystem
+    /// notifications about the `-wal` file when `watch` is configured, and
+    /// after commits on connections with the [`sync_notifier`](Self::sync_notifier)
+    /// installed. Scheduled snapshots are taken along the way. Sync failures are logged
+    /// and retried on the next trigger.
+    pub async fn run<F>(&mut self, shutdown: F) -> Result<()>
+    where
+        F: std::future::Future<Output = ()>,
+    {
+        self.run_loop(shutdown, None).await;
+        self.shutdown().await
+    }
+
+    /// Move the manager into a background task running the sync loop, and
+    /// return a handle for waiting on replication, forcing syncs and
+    /// shutting down. Must be called from within a Tokio runtime.
+    pub fn spawn(self) -> ReplicationHandle {
+        let (commands, rx) = mpsc::channel(16);
+        let handle = ReplicationHandle::new(
+            self.config.db_path.clone(),
+            commands,
+            self.progress.subscribe(),
+            self.events.clone(),
+        );
+        tokio::spawn(async move {
+            let mut mgr = self;
+            let reply = mgr.run_loop(std::future::pending(), Some(rx)).await;
+            let result = mgr.shutdown().await;
+            match reply {
+                Some(reply) => {
+                    let _ = reply.send(result);
+                }
+                None => {
+                    if let Err(e) = result {
+                        tracing::warn!(error = %e, "shutdown of replication task failed");
+                    }
+                }
+            }
+        });
+        handle
+    }
+
+    /// The sync loop shared by [`run`](Self::run) and [`spawn`](Self::spawn).
+    /// Returns when `shutdown` completes, a handle requests shutdown (whose
+    /// reply channel is returned) or every handle is dropped.
+    async fn run_loop<F>(
+        &mut self,
+        shutdown: F,
+        mut commands: Option<mpsc::Receiver<Command>>,
+    ) -> Option<oneshot::Sender<Result<()>>>
+    where
+        F: std::future::Future<Output = ()>,
+    {
+        let mut triggers = TriggerSource::new(&self.config, &self.notifier);
+        self.stats.watching = triggers.is_watching();
+        tokio::pin!(shutdown);
+        let reply = loop {
+            let trigger = tokio::select! {
+                _ = &mut shutdown => break None,
+                trigger = triggers.next() => trigger,
+                command = next_command(&mut commands) => match command {
+                    None => break None,
+                    Some(Command::Shutdown(reply)) => break Some(reply),
+                    Some(Command::Run(job)) => {
+                        job(self).await;
+                        continue;
+                    }
+                    Some(Command::SyncNow(reply)) => {
+                        let result = self.sync_wal().await;
+                        if let Ok(true) = result {
+                            self.stats.record_trigger(SyncTrigger::Manual);
+                        }
+                        let _ = reply.send(result);
+                        continue;
+                    }
+                },
+            };
+            let result = match trigger {
+                SyncTrigger::Commit => self.sync_commit().await,
+                _ => self.sync_wal().await,
+            };
+            match result {
+                Ok(true) => self.stats.record_trigger(trigger),
+                Ok(false) => {}
+                Err(e) => tracing::warn!(error = %e, ?trigger, "WAL sync failed"),
+            }
+            if let Err(e) = self.maybe_snapshot().await {
+                tracing::warn!(error = %e, "scheduled snapshot failed");
+            }
+        };
+        self.stats.watching = false;
+        reply
+    }
+
+    /// A notifier that makes [`run`](Self::run) and [`spawn`](Self::spawn)
+    /// sync right after commits on the application's connections, instead of
+    /// waiting for the next poll.
+    pub fn sync_notifier(&self) -> SyncNotifier {
+        self.notifier.clone()
+    }
+
+    /// Use `notifier` instead of this manager's own, e.g. one already
+    /// installed on a connection pool before the manager was created.
+    pub fn set_sync_notifier(&mut self, notifier: SyncNotifier) {
+        self.notifier = notifier;
+    }
+
+    /// Receive [`ReplicationEvent`]s for uploads, new generations, retention,
+    /// compaction and sync failures from now on. A receiver that falls more
+    /// than 256 events behind skips the oldest ones.
+    pub fn subscribe(&self) -> broadcast::Receiver<ReplicationEvent> {
+        self.events.subscribe()
+    }
+
+    /// Publish an event; having no subscribers is not an error.
+    fn emit(&self, event: ReplicationEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// The configuration this manager was created with.
+    pub fn config(&self) -> &BackupConfig {
+        &self.config
+    }
+
     /// Returns the current generation ID.
     pub fn generation(&self) -> &str {
         &self.generation
@@ -358,6 +891,7 @@ impl BackupManager {
 
     /// Returns a snapshot of current backup statistics.
     pub fn stats(&self) -> BackupStats {
+        let wal_size = self.wal_size();
         BackupStats {
             generation: self.generation.clone(),
             generation_count: self.stats.generation_count,
@@ -365,9 +899,51 @@ impl BackupManager {
             wal_index: self.wal_index,
             last_sync_time: self.stats.last_sync_time,
             last_snapshot_time: self.stats.last_snapshot_time,
+            last_sync_at_ms: self.stats.last_sync_at_ms,
+            last_snapshot_at_ms: self.stats.last_snapshot_at_ms,
+            last_checkpoint_at_ms: self.stats.last_checkpoint_at_ms,
             total_bytes_uploaded: self.stats.total_bytes_uploaded,
             sync_count: self.stats.sync_count,
             error_count: self.stats.error_count,
+            last_error: self.stats.last_error.clone(),
+            wal_size,
+            pending_wal_bytes: wal_size.saturating_sub(self.wal_offset),
+            compression_ratio: self.stats.compression_ratio(),
+            last_sync_trigger: self.stats.last_sync_trigger,
+            watching: self.stats.watching,
+        }
+    }
+
+    /// Replication health, judged against `config.health` from the time since
+    /// the last successful sync, un-uploaded WAL bytes, consecutive sync
+    /// errors and the time since the last snapshot.
+    pub fn health(&self) -> Health {
+        let sync_age = self
+            .stats
+            .last_success_time
+            .or(self.stats.last_snapshot_time)
+            .map(|t| t.elapsed());
+        Health::evaluate(
+            &self.config.health,
+            sync_age,
+            self.wal_size().saturating_sub(self.wal_offset),
+            self.stats.consecutive_errors,
+            self.stats.last_snapshot_time.map(|t| t.elapsed()),
+        )
+    }
+
+    /// Metrics for this database in the Prometheus text format.
+    #[cfg(feature = "metrics")]
+    pub fn prometheus_metrics(&self) -> String {
+        crate::metrics::render(&[self.metrics_sample()])
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn metrics_sample(&self) -> crate::metrics::Sample {
+        crate::metrics::Sample {
+            db: self.config.db_path.clone(),
+            stats: self.stats(),
+            metrics: self.stats.metrics.clone(),
         }
     }
 
@@ -383,7 +959,6 @@ impl BackupManager {
         // Best-effort final sync
         if let Err(e) = self.sync_wal().await {
             tracing::warn!(error = %e, "final WAL sync failed during shutdown");
-            self.stats.record_error();
         }
 
         // Release the read transaction
@@ -415,24 +990,42 @@ impl BackupManager {
 
         let cutoff_ms = now_ms().saturating_sub(duration.as_millis() as u64);
         let manifests = self.list_generation_manifests().await?;
-        let mut deleted = 0u32;
+        // Never delete the current generation
+        let current = self.generation.clone();
+        let expired =
+            |gen_id: &str, m: &GenerationManifest| gen_id != current && m.created_at_ms < cutoff_ms;
+        // Expired generations that retained delta snapshots still depend on
+        let protected = delta_bases(&manifests, |gen_id, m| !expired(gen_id, m));
+        let mut deleted = Vec::new();
 
         for (gen_id, manifest) in &manifests {
-            // Never delete the current generation
-            if *gen_id == self.generation {
-                continue;
-            }
-            if manifest.created_at_ms < cutoff_ms {
+            if expired(gen_id, manifest) && !protected.contains(gen_id) {
                 self.delete_generation(gen_id).await?;
-                deleted += 1;
+                deleted.push(gen_id.clone());
             }
         }
 
-        if deleted > 0 {
-            tracing::info!(deleted, "retention: deleted old generations");
+        let count = deleted.len() as u32;
+        if count > 0 {
+            tracing::info!(deleted = count, "retention: deleted old generations");
+            self.emit(ReplicationEvent::RetentionDeleted {
+                generations: deleted,
+            });
         }
 
-        Ok(deleted)
+        Ok(count)
+    }
+
+    /// Manifests of all generations in S3, oldest first.
+    pub async fn generations(&mut self) -> Result<Vec<GenerationManifest>> {
+        let mut manifests: Vec<GenerationManifest> = self
+            .list_generation_manifests()
+            .await?
+            .into_iter()
+            .map(|(_, m)| m)
+            .collect();
+        manifests.sort_by_key(|m| m.created_at_ms);
+        Ok(manifests)
     }
 
     /// List all generation manifests from S3.
@@ -441,8 +1034,8 @@ impl BackupManager {
         let mut manifests = Vec::new();
 
         for key in &all_keys {
-            if key.ends_with("/manifest.json") {
-                let gen_id = key.trim_end_matches("/manifest.json").to_string();
+            if let Some(gen_id) = manifest_generation(key) {
+                let gen_id = gen_id.to_string();
                 match self.s3.get_object(key).await {
                     Ok(data) => {
                         if let Ok(m) = Self::decode_manifest(&data, &self.config) {
@@ -556,6 +1149,11 @@ impl BackupManager {
             after = segments_after,
             "compaction complete"
         );
+        self.emit(ReplicationEvent::CompactionFinished {
+            generation: self.generation.clone(),
+            segments_before,
+            segments_after,
+        });
 
         Ok(CompactionResult {
             segments_before,
@@ -608,6 +1206,103 @@ impl BackupManager {
         Self::restore_inner(config, target_path, Some(timestamp_ms)).await
     }
 
+    /// Describe what restoring the latest backup, or the backup at
+    /// `timestamp_ms`, would download, without downloading any data.
+    pub async fn restore_plan(
+        config: &BackupConfig,
+        timestamp_ms: Option<u64>,
+    ) -> Result<RestorePlan> {
+        let s3 = S3Client::new(&config.s3)?;
+        let manifest = match timestamp_ms {
+            Some(ts) => Self::pitr_manifest(&s3, config, ts).await?,
+            None => {
+                let generation = Self::latest_generation(&s3).await?;
+                let data = s3
+                    .get_object(&format!("{generation}/manifest.json"))
+                    .await
+                    .map_err(|_| {
+                        Error::Other(format!("generation {generation} has no manifest"))
+                    })?;
+                Self::decode_manifest(&data, config)?
+            }
+        };
+        Self::plan_manifest(&s3, config, manifest, timestamp_ms).await
+    }
+
+    /// Like [`restore_plan`](Self::restore_plan), for a specific generation.
+    pub async fn generation_restore_plan(
+        config: &BackupConfig,
+        generation: &str,
+        timestamp_ms: Option<u64>,
+    ) -> Result<RestorePlan> {
+        let s3 = S3Client::new(&config.s3)?;
+        let manifest = Self::generation_manifest(&s3, config, generation, timestamp_ms).await?;
+        Self::plan_manifest(&s3, config, manifest, timestamp_ms).await
+    }
+
+    /// Restore a specific generation rather than the latest one, replaying
+    /// its WAL segments up to `timestamp_ms` if given.
+    pub async fn restore_generation_with_config(
+        config: &BackupConfig,
+        generation: &str,
+        target_path: &str,
+        timestamp_ms: Option<u64>,
+    ) -> Result<()> {
+        let s3 = S3Client::new(&config.s3)?;
+        let manifest = Self::generation_manifest(&s3, config, generation, timestamp_ms).await?;
+        tracing::info!(generation = %generation, "restoring from generation");
+        Self::restore_manifest(&s3, config, target_path, &manifest, timestamp_ms).await
+    }
+
+    /// The manifest of `generation`, checking that its snapshot was taken at
+    /// or before `timestamp_ms`.
+    async fn generation_manifest(
+        s3: &S3Client,
+        config: &BackupConfig,
+        generation: &str,
+        timestamp_ms: Option<u64>,
+    ) -> Result<GenerationManifest> {
+        let data = s3
+            .get_object(&format!("{generation}/manifest.json"))
+            .await
+            .map_err(|_| Error::Other(format!("generation {generation} has no manifest")))?;
+        let manifest = Self::decode_manifest(&data, config)?;
+        if let Some(ts) = timestamp_ms
+            && manifest.snapshot_timestamp_ms > ts
+        {
+            return Err(Error::Other(format!(
+                "generation {generation} has no snapshot before timestamp {ts}"
+            )));
+        }
+        Ok(manifest)
+    }
+
+    async fn plan_manifest(
+        s3: &S3Client,
+        config: &BackupConfig,
+        manifest: GenerationManifest,
+        timestamp_ms: Option<u64>,
+    ) -> Result<RestorePlan> {
+        let snapshot_chain = Self::snapshot_chain(s3, config, &manifest.generation).await?;
+
+        let segments: Vec<SegmentMeta> = manifest
+            .segments
+            .iter()
+            .filter(|s| timestamp_ms.is_none_or(|ts| s.timestamp_ms <= ts))
+            .cloned()
+            .collect();
+        Ok(RestorePlan {
+            snapshot_chain,
+            snapshot_timestamp_ms: manifest.snapshot_timestamp_ms,
+            wal_bytes: segments.iter().map(|s| s.size).sum(),
+            restores_to_ms: segments
+                .last()
+                .map_or(manifest.snapshot_timestamp_ms, |s| s.timestamp_ms),
+            segments,
+            generation: manifest.generation,
+        })
+    }
+
     async fn restore_inner(
         config: &BackupConfig,
         target_path: &str,
@@ -629,22 +1324,12 @@ impl BackupManager {
         decode_config: &BackupConfig,
         target_path: &str,
     ) -> Result<()> {
-        // Find the latest generation.
-        // Safety: the `latest` marker is updated only after the snapshot is
-        // successfully uploaded, so it always points to a valid generation.
-        let gen_bytes = s3
-            .get_object("latest")
-            .await
-            .map_err(|_| Error::Other("no backup found: 'latest' marker missing".into()))?;
-        let generation = String::from_utf8(gen_bytes)
-            .map_err(|e| Error::Other(format!("invalid generation id: {e}")))?;
+        let generation = Self::latest_generation(s3).await?;
 
         tracing::info!(generation = %generation, "restoring from generation");
 
         // Download the snapshot
-        let snapshot_key = format!("{}/snapshot", generation);
-        let snapshot_data = s3.get_object(&snapshot_key).await?;
-        let snapshot_decoded = Self::pipeline_decode(&snapshot_data, decode_config)?;
+        let snapshot_decoded = Self::download_snapshot(s3, decode_config, &generation).await?;
         tokio::fs::write(target_path, &snapshot_decoded).await?;
 
         // Download WAL segments using manifest for ordering (required after
@@ -684,70 +1369,614 @@ impl BackupManager {
         Ok(())
     }
 
-    /// Resolve segment keys for a generation from its manifest.
-    /// Falls back to list_keys if the manifest is missing or unparseable.
-    async fn segment_keys_from_manifest(
-        s3: &S3Client,
-        decode_config: &BackupConfig,
+    /// ID of the generation the `latest` marker of `config.s3` points to.
+    pub async fn latest_generation_id(config: &BackupConfig) -> Result<String> {
+        Self::latest_generation(&S3Client::new(&config.s3)?).await
+    }
+
+    /// IDs of all generations stored under `config.s3`, sorted. Databases
+    /// stored under a sub-prefix, as by a [`ReplicaSet`](crate::ReplicaSet),
+    /// are not included.
+    pub async fn list_generation_ids(config: &BackupConfig) -> Result<Vec<String>> {
+        let keys = S3Client::new(&config.s3)?.list_keys("").await?;
+        let mut ids: Vec<String> = keys
+            .iter()
+            .filter_map(|k| key_generation(k).map(str::to_string))
+            .collect();
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// Keys of the objects of a generation, relative to `config.s3.prefix`.
+    pub async fn list_generation_keys(
+        config: &BackupConfig,
         generation: &str,
-    ) -> Vec<String> {
-        let manifest_key = format!("{}/manifest.json", generation);
-        if let Ok(data) = s3.get_object(&manifest_key).await
-            && let Ok(manifest) = Self::decode_manifest(&data, decode_config)
-        {
-            return manifest
-                .segments
-                .iter()
-                .map(|s| format!("{}/wal/{:08}", generation, s.index))
-                .collect();
-        }
-        // Fallback: list keys (backward compat with pre-manifest backups)
-        let wal_prefix = format!("{}/wal/", generation);
-        s3.list_keys(&wal_prefix).await.unwrap_or_default()
+    ) -> Result<Vec<String>> {
+        S3Client::new(&config.s3)?
+            .list_keys(&format!("{generation}/"))
+            .await
     }
 
-    /// Point-in-time restore.
-    ///
-    /// Note: segment timestamps reflect upload time, not the time the WAL frames
-    /// were written by the application. PITR granularity is therefore limited to
-    /// the sync interval.
-    async fn restore_pitr(
-        s3: &S3Client,
-        decode_config: &BackupConfig,
-        target_path: &str,
-        timestamp_ms: u64,
-    ) -> Result<()> {
-        // List all manifests to find the right generation
-        let all_keys = s3.list_keys("").await?;
-        let mut manifests: Vec<GenerationManifest> = Vec::new();
+    /// The manifest of a generation, decoded with `config`'s encryption key,
+    /// or `None` for generations written before manifests existed.
+    pub async fn read_manifest(
+        config: &BackupConfig,
+        generation: &str,
+    ) -> Result<Option<GenerationManifest>> {
+        let s3 = S3Client::new(&config.s3)?;
+        Self::find_manifest(&s3, config, generation).await
+    }
 
-        for key in &all_keys {
-            if key.ends_with("/manifest.json")
-                && let Ok(data) = s3.get_object(key).await
-                && let Ok(m) = Self::decode_manifest(&data, decode_config)
-            {
-                manifests.push(m);
+    /// The decoded WAL segments of a generation in manifest order, up to and
+    /// including segment `through`. Concatenated, they form the WAL from its
+    /// header, as a restore replays it.
+    pub async fn download_wal_segments(
+        config: &BackupConfig,
+        generation: &str,
+        through: Option<u32>,
+    ) -> Result<Vec<(SegmentMeta, Vec<u8>)>> {
+        let s3 = S3Client::new(&config.s3)?;
+        let manifest = Self::generation_manifest(&s3, config, generation, None).await?;
+        let count = match through {
+            Some(index) => {
+                let position = manifest.segments.iter().position(|s| s.index == index);
+                position.map(|p| p + 1).ok_or_else(|| {
+                    Error::Other(format!(
+                        "generation {generation} has no WAL segment {index}"
+                    ))
+                })?
             }
+            None => manifest.segments.len(),
+        };
+        let mut segments = Vec::with_capacity(count);
+        for meta in manifest.segments.into_iter().take(count) {
+            let data = s3
+                .get_object(&format!("{generation}/wal/{:08}", meta.index))
+                .await?;
+            segments.push((meta, Self::pipeline_decode(&data, config)?));
         }
+        Ok(segments)
+    }
 
-        if manifests.is_empty() {
-            return Err(Error::Other(
-                "no manifests found for point-in-time restore".into(),
-            ));
+    /// Stored sizes, encoding and manifest of a generation.
+    pub async fn generation_info(
+        config: &BackupConfig,
+        generation: &str,
+    ) -> Result<GenerationInfo> {
+        let s3 = S3Client::new(&config.s3)?;
+        let objects = s3.list_objects(&format!("{generation}/")).await?;
+        if objects.is_empty() {
+            return Err(Error::Other(format!("generation {generation} not found")));
         }
+        let size_of = |name: &str| {
+            let key = format!("{generation}/{name}");
+            objects
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, size)| *size)
+        };
+        let snapshot_bytes = size_of("snapshot")
+            .or_else(|| size_of("delta"))
+            .unwrap_or(0);
+        let wal_prefix = format!("{generation}/wal/");
+        let wal_bytes = objects
+            .iter()
+            .filter(|(k, _)| k.starts_with(&wal_prefix))
+            .map(|(_, size)| size)
+            .sum();
+
+        let (manifest, encrypted, compression) = if size_of("manifest.json").is_some() {
+            let data = s3
+                .get_object(&format!("{generation}/manifest.json"))
+                .await?;
+            #[cfg(feature = "encryption")]
+            let encrypted = crate::encryption::is_encrypted(&data);
+            #[cfg(not(feature = "encryption"))]
+            let encrypted = false;
+            let manifest = Self::decode_manifest(&data, config)?;
+            #[cfg(feature = "encryption")]
+            let data = match &config.encryption_key {
+                Some(key) => crate::encryption::decrypt(&data, key)?,
+                None => data,
+            };
+            (
+                Some(manifest),
+                Some(encrypted),
+                Some(compression::detect(&data)),
+            )
+        } else {
+            (None, None, None)
+        };
+        Ok(GenerationInfo {
+            generation: generation.to_string(),
+            manifest,
+            snapshot_bytes,
+            wal_bytes,
+            total_bytes: objects.iter().map(|(_, size)| size).sum(),
+            encrypted,
+            compression,
+            objects: objects
+                .into_iter()
+                .map(|(key, size)| StoredObject { key, size })
+                .collect(),
+        })
+    }
 
-        // Sort by snapshot timestamp descending
-        manifests.sort_by(|a, b| b.snapshot_timestamp_ms.cmp(&a.snapshot_timestamp_ms));
+    /// Delete generations created more than `older_than` ago directly in the
+    /// replica, without a running manager or the database.
+    ///
+    /// The generation `latest` points to, the `keep_last` newest generations
+    /// and the bases of retained delta snapshots are never deleted.
+    /// Generations without a manifest are left alone, since their age is
+    /// unknown, and so are manifests nested deeper than `<generation>/`.
+    /// Without a readable `latest` marker the generation being written can't
+    /// be told apart, so nothing is pruned unless `force` is set. With
+    /// `dry_run`, nothing is deleted and the result lists what would be.
+    pub async fn prune(
+        config: &BackupConfig,
+        older_than: Duration,
+        keep_last: usize,
+        dry_run: bool,
+        force: bool,
+    ) -> Result<PruneResult> {
+        let s3 = S3Client::new(&config.s3)?;
+        let latest = match Self::latest_generation(&s3).await {
+            Ok(latest) => Some(latest),
+            Err(_) if force => None,
+            Err(e) => {
+                return Err(Error::Other(format!(
+                    "refusing to prune without the latest generation: {e}"
+                )));
+            }
+        };
+        let objects = s3.list_objects("").await?;
+        let mut manifests = Vec::new();
+        for (key, _) in &objects {
+            if let Some(gen_id) = manifest_generation(key) {
+                // Unlike the manager's own retention, an undecodable manifest
+                // is an error: pruning nothing must not look like success.
+                let data = s3.get_object(key).await?;
+                let manifest = Self::decode_manifest(&data, config)
+                    .map_err(|e| Error::Other(format!("generation {gen_id}: {e}")))?;
+                manifests.push((gen_id.to_string(), manifest));
+            }
+        }
+        manifests.sort_by_key(|(_, m)| std::cmp::Reverse(m.created_at_ms));
 
-        // Find the latest generation whose snapshot is <= target time
-        let manifest = manifests
+        let cutoff_ms = now_ms().saturating_sub(older_than.as_millis() as u64);
+        let newest: HashSet<&str> = manifests
             .iter()
-            .find(|m| m.snapshot_timestamp_ms <= timestamp_ms)
-            .ok_or_else(|| {
-                Error::Other(format!(
-                    "no generation found with snapshot before timestamp {timestamp_ms}"
-                ))
+            .take(keep_last)
+            .map(|(gen_id, _)| gen_id.as_str())
+            .collect();
+        let expired = |gen_id: &str, m: &GenerationManifest| {
+            latest.as_deref() != Some(gen_id)
+                && !newest.contains(gen_id)
+                && m.created_at_ms < cutoff_ms
+        };
+        let protected = delta_bases(&manifests, |gen_id, m| !expired(gen_id, m));
+
+        let mut result = PruneResult::default();
+        for (gen_id, manifest) in manifests.iter().rev() {
+            if !expired(gen_id, manifest) || protected.contains(gen_id) {
+                result.retained.push(gen_id.clone());
+                continue;
+            }
+            let prefix = format!("{gen_id}/");
+            let keys: Vec<String> = objects
+                .iter()
+                .filter(|(key, _)| key.starts_with(&prefix))
+                .map(|(key, size)| {
+                    result.bytes += size;
+                    key.clone()
+                })
+                .collect();
+            result.objects += keys.len();
+            if !dry_run {
+                s3.delete_objects(&keys).await?;
+                tracing::info!(generation = %gen_id, objects = keys.len(), "generation pruned");
+            }
+            result.deleted.push(gen_id.clone());
+        }
+        Ok(result)
+    }
+
+    /// Copy every generation, manifest and `latest` marker from the replica
+    /// of `from` to the replica of `to`, e.g. to move backups to another
+    /// region or provider.
+    ///
+    /// Objects are copied byte for byte, so no key is needed, unless
+    /// `reencode` is set: then they are decoded with `from`'s encryption key
+    /// and stored with `to`'s compression and encryption key. Each written
+    /// object is read back and checked. Objects already in the destination
+    /// are skipped, so an interrupted copy resumes where it stopped, and
+    /// manifests and markers are written after the objects they refer to.
+    /// A prefix holding several databases is copied as a whole.
+    pub async fn copy_replica(
+        from: &BackupConfig,
+        to: &BackupConfig,
+        reencode: bool,
+    ) -> Result<CopyResult> {
+        let (source, target) = (replica_location(&from.s3), replica_location(&to.s3));
+        if source == target
+            || source.starts_with(&format!("{target}/"))
+            || target.starts_with(&format!("{source}/"))
+        {
+            return Err(Error::Other(format!(
+                "cannot copy between overlapping replicas {source} and {target}"
+            )));
+        }
+        let src = S3Client::new(&from.s3)?;
+        let dst = S3Client::new(&to.s3)?;
+
+        // Read manifests and markers before listing the objects they refer
+        // to, so a replica still being written to is copied consistently.
+        let mut manifests = Vec::new();
+        let mut markers = Vec::new();
+        for key in src.list_keys("").await? {
+            let name = key.rsplit('/').next().unwrap_or_default();
+            if name == "manifest.json" || name == "latest" {
+                let data = src.get_object(&key).await?;
+                if name == "latest" {
+                    markers.push((key, data));
+                } else {
+                    manifests.push((key, data));
+                }
+            }
+        }
+        if manifests.is_empty() && markers.is_empty() {
+            return Err(Error::Other(format!("no backups found in {source}")));
+        }
+        let existing: HashMap<String, u64> = dst.list_objects("").await?.into_iter().collect();
+        let mut result = CopyResult {
+            generations: manifests.len(),
+            ..Default::default()
+        };
+
+        let copy = |data: Vec<u8>| -> Result<(Vec<u8>, Vec<u8>)> {
+            if !reencode {
+                return Ok((data.clone(), data));
+            }
+            let plain = Self::pipeline_decode(&data, from)?;
+            #[cfg(feature = "encryption")]
+            let key = to.encryption_key.as_deref();
+            #[cfg(not(feature = "encryption"))]
+            let key = None;
+            let encoded = Self::pipeline_encode_with(&plain, &to.compression, key)?;
+            Ok((encoded, plain))
+        };
+        let put_verified = async |key: &str, encoded: &[u8], plain: &[u8]| -> Result<()> {
+            dst.put_object(key, encoded).await?;
+            let stored = dst.get_object(key).await?;
+            let intact = if reencode {
+                Self::pipeline_decode(&stored, to).is_ok_and(|data| data == plain)
+            } else {
+                stored == encoded
+            };
+            if !intact {
+                return Err(Error::Other(format!("verification of copied {key} failed")));
+            }
+            Ok(())
+        };
+
+        for (key, size) in src.list_objects("").await? {
+            let name = key.rsplit('/').next().unwrap_or_default();
+            if name == "manifest.json" || name == "latest" || name.starts_with('.') {
+                continue;
+            }
+            // A re-encoded object has a different size, but any object in
+            // the destination was written whole.
+            let stored = existing.get(&key);
+            if stored.is_some_and(|&stored| reencode || stored == size) {
+                result.objects_skipped += 1;
+                continue;
+            }
+            let (encoded, plain) = copy(src.get_object(&key).await?)?;
+            put_verified(&key, &encoded, &plain).await?;
+            result.objects_copied += 1;
+            result.bytes_copied += encoded.len() as u64;
+        }
+
+        for (key, data) in manifests {
+            // A manifest grows while its generation is replicated, so compare
+            // contents rather than presence.
+            if existing.contains_key(&key) {
+                let stored = dst.get_object(&key).await?;
+                let unchanged = if reencode {
+                    let stored = Self::decode_manifest(&stored, to).ok();
+                    stored.is_some() && stored == Self::decode_manifest(&data, from).ok()
+                } else {
+                    stored == data
+                };
+                if unchanged {
+                    result.objects_skipped += 1;
+                    continue;
+                }
+            }
+            let (encoded, plain) = copy(data)?;
+            put_verified(&key, &encoded, &plain).await?;
+            result.objects_copied += 1;
+            result.bytes_copied += encoded.len() as u64;
+        }
+
+        // Markers are plain generation IDs, and go last so a restore from the
+        // destination never finds a generation that is not fully there.
+        for (key, data) in markers {
+            if existing.contains_key(&key) && dst.get_object(&key).await? == data {
+                result.objects_skipped += 1;
+                continue;
+            }
+            dst.put_object(&key, &data).await?;
+            if dst.get_object(&key).await? != data {
+                return Err(Error::Other(format!("verification of copied {key} failed")));
+            }
+            result.objects_copied += 1;
+            result.bytes_copied += data.len() as u64;
+        }
+
+        tracing::info!(
+            source = %source,
+            target = %target,
+            copied = result.objects_copied,
+            skipped = result.objects_skipped,
+            "replica copied"
+        );
+        Ok(result)
+    }
+
+    /// Merge the WAL segments of a generation directly in the replica,
+    /// like [`compact`](Self::compact) does for the current generation.
+    ///
+    /// Segments keep the compression and encryption they were stored with;
+    /// each merged segment takes the upload time of the newest segment it
+    /// contains, so point-in-time restores never replay more than before.
+    /// The generation must not be one a manager is still writing to, as that
+    /// manager would later upload a manifest naming the old segments.
+    pub async fn compact_generation(
+        config: &BackupConfig,
+        generation: &str,
+        max_segment_size: Option<usize>,
+    ) -> Result<CompactionResult> {
+        let max_size = max_segment_size.unwrap_or(4 * 1024 * 1024);
+        let s3 = S3Client::new(&config.s3)?;
+        let manifest_key = format!("{generation}/manifest.json");
+        let manifest_data = s3
+            .get_object(&manifest_key)
+            .await
+            .map_err(|_| Error::Other(format!("generation {generation} has no manifest")))?;
+        let (json, manifest_compression, manifest_encryption) =
+            Self::decode_stored(&manifest_data, config)?;
+        let mut manifest: GenerationManifest = serde_json::from_slice(&json)
+            .map_err(|e| Error::Other(format!("manifest deserialize: {e}")))?;
+        let segments_before = manifest.segments.len() as u32;
+        if segments_before <= 1 {
+            return Ok(CompactionResult {
+                segments_before,
+                segments_after: segments_before,
+            });
+        }
+
+        let wal_prefix = format!("{generation}/wal/");
+        let existing = s3.list_keys(&wal_prefix).await?;
+        let mut new_index = existing
+            .iter()
+            .filter_map(|k| k.strip_prefix(&wal_prefix)?.parse::<u32>().ok())
+            .chain(manifest.segments.iter().map(|s| s.index))
+            .max()
+            .map_or(0, |max| max + 1);
+
+        // Decode every segment, remembering where each one ends.
+        let mut all_data = Vec::new();
+        let mut ends = Vec::new();
+        let mut encoding = None;
+        let old_segment_keys: Vec<String> = manifest
+            .segments
+            .iter()
+            .map(|s| format!("{wal_prefix}{:08}", s.index))
+            .collect();
+        for (key, meta) in old_segment_keys.iter().zip(&manifest.segments) {
+            let data = s3.get_object(key).await?;
+            let (decoded, compression, encryption_key) = Self::decode_stored(&data, config)?;
+            encoding.get_or_insert((compression, encryption_key));
+            all_data.extend_from_slice(&decoded);
+            ends.push((all_data.len(), meta.timestamp_ms));
+        }
+        let (compression, encryption_key) = encoding.unwrap_or_default();
+
+        let mut new_segments = Vec::new();
+        let mut offset = 0usize;
+        while offset < all_data.len() {
+            let end = (offset + max_size).min(all_data.len());
+            let encoded =
+                Self::pipeline_encode_with(&all_data[offset..end], &compression, encryption_key)?;
+            s3.put_object(&format!("{wal_prefix}{new_index:08}"), &encoded)
+                .await?;
+            // The first segment ending at or after this chunk holds its last byte.
+            let timestamp_ms = ends
+                .iter()
+                .find(|(seg_end, _)| *seg_end >= end)
+                .map_or(0, |(_, ts)| *ts);
+            new_segments.push(SegmentMeta {
+                index: new_index,
+                timestamp_ms,
+                offset: offset as u64,
+                size: (end - offset) as u64,
+            });
+            offset = end;
+            new_index += 1;
+        }
+        let segments_after = new_segments.len() as u32;
+
+        // Same ordering as `compact`: the manifest switches over before the
+        // old segments are deleted.
+        manifest.segments = new_segments;
+        let json = serde_json::to_vec(&manifest)
+            .map_err(|e| Error::Other(format!("manifest serialize: {e}")))?;
+        let encoded =
+            Self::pipeline_encode_with(&json, &manifest_compression, manifest_encryption)?;
+        s3.put_object(&manifest_key, &encoded).await?;
+        s3.delete_objects(&old_segment_keys).await?;
+
+        tracing::info!(
+            generation,
+            before = segments_before,
+            after = segments_after,
+            "compaction complete"
+        );
+        Ok(CompactionResult {
+            segments_before,
+            segments_after,
+        })
+    }
+
+    /// Like `pipeline_decode`, also returning the compression and encryption
+    /// key to re-encode the data with so it keeps the encoding it has.
+    fn decode_stored<'a>(
+        data: &[u8],
+        #[allow(unused)] config: &'a BackupConfig,
+    ) -> Result<(Vec<u8>, CompressionAlgorithm, Option<&'a str>)> {
+        #[cfg(feature = "encryption")]
+        let decrypted;
+        #[cfg(feature = "encryption")]
+        let (data, key) = if crate::encryption::is_encrypted(data) {
+            let key = config.encryption_key.as_deref();
+            let key = key.ok_or_else(|| {
+                Error::Other("backup is encrypted, but no encryption key is configured".into())
             })?;
+            decrypted = crate::encryption::decrypt(data, key)?;
+            (decrypted.as_slice(), Some(key))
+        } else {
+            (data, None)
+        };
+        #[cfg(not(feature = "encryption"))]
+        let key = None;
+        let compression = match compression::detect(data) {
+            #[cfg(feature = "compression-lz4")]
+            "lz4" => CompressionAlgorithm::Lz4,
+            #[cfg(feature = "compression-zstd")]
+            "zstd" => CompressionAlgorithm::Zstd,
+            _ => CompressionAlgorithm::None,
+        };
+        Ok((compression::decompress(data)?, compression, key))
+    }
+
+    /// Read the `latest` marker.
+    ///
+    /// Safety: the `latest` marker is updated only after the snapshot is
+    /// successfully uploaded, so it always points to a valid generation.
+    async fn latest_generation(s3: &S3Client) -> Result<String> {
+        let gen_bytes = s3
+            .get_object("latest")
+            .await
+            .map_err(|_| Error::Other("no backup found: 'latest' marker missing".into()))?;
+        String::from_utf8(gen_bytes)
+            .map_err(|e| Error::Other(format!("invalid generation id: {e}")))
+    }
+
+    /// The decoded manifest of a generation, or `None` if it has none, as
+    /// generations written before manifests existed. Errors fetching or
+    /// decoding a manifest that exists are returned, not treated as absence.
+    async fn find_manifest(
+        s3: &S3Client,
+        decode_config: &BackupConfig,
+        generation: &str,
+    ) -> Result<Option<GenerationManifest>> {
+        let key = format!("{generation}/manifest.json");
+        if !s3.list_keys(&key).await?.contains(&key) {
+            return Ok(None);
+        }
+        let data = s3.get_object(&key).await?;
+        Self::decode_manifest(&data, decode_config).map(Some)
+    }
+
+    /// The generations whose snapshots make up the image of `generation`:
+    /// the nearest full snapshot first, then each delta on top of it in order,
+    /// ending with `generation` itself.
+    async fn snapshot_chain(
+        s3: &S3Client,
+        decode_config: &BackupConfig,
+        generation: &str,
+    ) -> Result<Vec<String>> {
+        let mut chain = vec![generation.to_string()];
+        let mut current = generation.to_string();
+        while let Some(base) = Self::find_manifest(s3, decode_config, &current)
+            .await?
+            .and_then(|m| m.base_generation)
+        {
+            if chain.len() > MAX_DELTA_CHAIN {
+                return Err(Error::Other(format!(
+                    "delta snapshot chain for generation {generation} is too long"
+                )));
+            }
+            chain.push(base.clone());
+            current = base;
+        }
+        chain.reverse();
+        Ok(chain)
+    }
+
+    /// Download the snapshot image of a generation. If the generation holds a
+    /// delta snapshot, its chain of bases is followed back to the nearest full
+    /// snapshot and the deltas are applied in order.
+    async fn download_snapshot(
+        s3: &S3Client,
+        decode_config: &BackupConfig,
+        generation: &str,
+    ) -> Result<Vec<u8>> {
+        let chain = Self::snapshot_chain(s3, decode_config, generation).await?;
+        let (base, deltas) = chain.split_first().expect("chain holds the generation");
+
+        let snapshot_key = format!("{}/snapshot", base);
+        let snapshot_data = s3.get_object(&snapshot_key).await?;
+        let mut image = Self::pipeline_decode(&snapshot_data, decode_config)?;
+
+        for gen_id in deltas {
+            let delta_key = format!("{}/delta", gen_id);
+            let data = s3.get_object(&delta_key).await?;
+            let decoded = Self::pipeline_decode(&data, decode_config)?;
+            image = delta::apply(image, &decoded)?;
+        }
+        if !deltas.is_empty() {
+            tracing::info!(base = %base, deltas = deltas.len(), "delta snapshots applied");
+        }
+        Ok(image)
+    }
+
+    /// Resolve segment keys for a generation from its manifest.
+    /// Falls back to list_keys if the manifest is missing or unparseable.
+    async fn segment_keys_from_manifest(
+        s3: &S3Client,
+        decode_config: &BackupConfig,
+        generation: &str,
+    ) -> Vec<String> {
+        let manifest_key = format!("{}/manifest.json", generation);
+        if let Ok(data) = s3.get_object(&manifest_key).await
+            && let Ok(manifest) = Self::decode_manifest(&data, decode_config)
+        {
+            return manifest
+                .segments
+                .iter()
+                .map(|s| format!("{}/wal/{:08}", generation, s.index))
+                .collect();
+        }
+        // Fallback: list keys (backward compat with pre-manifest backups)
+        let wal_prefix = format!("{}/wal/", generation);
+        s3.list_keys(&wal_prefix).await.unwrap_or_default()
+    }
+
+    /// Point-in-time restore.
+    ///
+    /// Note: segment timestamps reflect upload time, not the time the WAL frames
+    /// were written by the application. PITR granularity is therefore limited to
+    /// the sync interval.
+    async fn restore_pitr(
+        s3: &S3Client,
+        decode_config: &BackupConfig,
+        target_path: &str,
+        timestamp_ms: u64,
+    ) -> Result<()> {
+        let manifest = Self::pitr_manifest(s3, decode_config, timestamp_ms).await?;
 
         tracing::info!(
             generation = %manifest.generation,
@@ -755,18 +1984,35 @@ impl BackupManager {
             target_ts = timestamp_ms,
             "restoring to point in time"
         );
+        Self::restore_manifest(
+            s3,
+            decode_config,
+            target_path,
+            &manifest,
+            Some(timestamp_ms),
+        )
+        .await
+    }
 
+    /// Restore the generation described by `manifest`, replaying the WAL
+    /// segments uploaded at or before `timestamp_ms`, or all of them.
+    async fn restore_manifest(
+        s3: &S3Client,
+        decode_config: &BackupConfig,
+        target_path: &str,
+        manifest: &GenerationManifest,
+        timestamp_ms: Option<u64>,
+    ) -> Result<()> {
         // Download snapshot
-        let snapshot_key = format!("{}/snapshot", manifest.generation);
-        let snapshot_data = s3.get_object(&snapshot_key).await?;
-        let snapshot_decoded = Self::pipeline_decode(&snapshot_data, decode_config)?;
+        let snapshot_decoded =
+            Self::download_snapshot(s3, decode_config, &manifest.generation).await?;
         tokio::fs::write(target_path, &snapshot_decoded).await?;
 
         // Download WAL segments up to target timestamp
         let segments_to_replay: Vec<&crate::manifest::SegmentMeta> = manifest
             .segments
             .iter()
-            .filter(|s| s.timestamp_ms <= timestamp_ms)
+            .filter(|s| timestamp_ms.is_none_or(|ts| s.timestamp_ms <= ts))
             .collect();
 
         if !segments_to_replay.is_empty() {
@@ -799,9 +2045,108 @@ impl BackupManager {
         conn.execute_batch("PRAGMA wal_checkpoint(TRUNCATE)")?;
         drop(conn);
 
-        tracing::info!(target = target_path, "point-in-time restore complete");
+        tracing::info!(target = target_path, "generation restore complete");
         Ok(())
     }
+
+    /// Find the latest generation whose snapshot is at or before `timestamp_ms`.
+    async fn pitr_manifest(
+        s3: &S3Client,
+        decode_config: &BackupConfig,
+        timestamp_ms: u64,
+    ) -> Result<GenerationManifest> {
+        // List all manifests to find the right generation
+        let all_keys = s3.list_keys("").await?;
+        let mut manifests: Vec<GenerationManifest> = Vec::new();
+
+        for key in &all_keys {
+            if manifest_generation(key).is_some()
+                && let Ok(data) = s3.get_object(key).await
+                && let Ok(m) = Self::decode_manifest(&data, decode_config)
+            {
+                manifests.push(m);
+            }
+        }
+
+        if manifests.is_empty() {
+            return Err(Error::Other(
+                "no manifests found for point-in-time restore".into(),
+            ));
+        }
+
+        // Sort by snapshot timestamp descending
+        manifests.sort_by(|a, b| b.snapshot_timestamp_ms.cmp(&a.snapshot_timestamp_ms));
+
+        // Find the latest generation whose snapshot is <= target time
+        manifests
+            .into_iter()
+            .find(|m| m.snapshot_timestamp_ms <= timestamp_ms)
+            .ok_or_else(|| {
+                Error::Other(format!(
+                    "no generation found with snapshot before timestamp {timestamp_ms}"
+                ))
+            })
+    }
+}
+
+/// Where a replica's objects live, to tell whether two replicas overlap.
+fn replica_location(s3: &S3Config) -> String {
+    let base = match s3.local_dir() {
+        Some(dir) => dir.display().to_string(),
+        None => format!("{}/{}", s3.endpoint.trim_end_matches('/'), s3.bucket),
+    };
+    let location = format!("{}/{}", base.trim_end_matches('/'), s3.prefix);
+    location.trim_end_matches('/').to_string()
+}
+
+/// The generation a key belongs to, if it is one of a generation's own
+/// objects directly under the prefix: `<generation>/manifest.json`,
+/// `/snapshot`, `/delta` or `/wal/<index>`. Keys of databases nested under
+/// a sub-prefix yield `None`.
+fn key_generation(key: &str) -> Option<&str> {
+    let (gen_id, rest) = key.split_once('/')?;
+    let own = matches!(rest, "manifest.json" | "snapshot" | "delta")
+        || rest.strip_prefix("wal/").is_some_and(|s| !s.contains('/'));
+    (own && !gen_id.is_empty()).then_some(gen_id)
+}
+
+/// The generation of a top-level `<generation>/manifest.json` key.
+fn manifest_generation(key: &str) -> Option<&str> {
+    key_generation(key).filter(|_| key.ends_with("/manifest.json"))
+}
+
+/// Collect the generations that retained generations' delta snapshots depend on,
+/// following each chain of `base_generation` links transitively.
+fn delta_bases<F>(manifests: &[(String, GenerationManifest)], retained: F) -> HashSet<String>
+where
+    F: Fn(&str, &GenerationManifest) -> bool,
+{
+    let by_id: HashMap<&str, &GenerationManifest> = manifests
+        .iter()
+        .map(|(gen_id, m)| (gen_id.as_str(), m))
+        .collect();
+    let mut bases = HashSet::new();
+    for (gen_id, m) in manifests {
+        if !retained(gen_id, m) {
+            continue;
+        }
+        let mut base = m.base_generation.as_deref();
+        while let Some(b) = base {
+            if !bases.insert(b.to_string()) {
+                break;
+            }
+            base = by_id.get(b).and_then(|m| m.base_generation.as_deref());
+        }
+    }
+    bases
+}
+
+/// Receive the next command, or wait forever if there is no command channel.
+async fn next_command(commands: &mut Option<mpsc::Receiver<Command>>) -> Option<Command> {
+    match commands {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
 }
 
 /// Check if the WAL shows a discontinuity (shrink or salt change) that requires recovery.
@@ -892,6 +2237,77 @@ mod tests {
         assert!(!check_wal_discontinuity(0, Some(&old_salt), &wal_data, 20));
     }
 
+    // --- delta_bases tests ---
+
+    fn manifest_with_base(
+        gen_id: &str,
+        created_at_ms: u64,
+        base: Option<&str>,
+    ) -> (String, GenerationManifest) {
+        let mut m = GenerationManifest::new(gen_id.into(), created_at_ms);
+        m.base_generation = base.map(String::from);
+        (gen_id.into(), m)
+    }
+
+    #[test]
+    fn delta_bases_follows_chain_transitively() {
+        let manifests = vec![
+            manifest_with_base("g1", 1, None),
+            manifest_with_base("g2", 2, Some("g1")),
+            manifest_with_base("g3", 3, Some("g2")),
+            manifest_with_base("g4", 4, None),
+        ];
+        let bases = delta_bases(&manifests, |gen_id, _| gen_id == "g3");
+        assert_eq!(bases, HashSet::from(["g1".to_string(), "g2".to_string()]));
+    }
+
+    #[test]
+    fn delta_bases_ignores_unretained_deltas() {
+        let manifests = vec![
+            manifest_with_base("g1", 1, None),
+            manifest_with_base("g2", 2, Some("g1")),
+            manifest_with_base("g3", 3, None),
+        ];
+        let bases = delta_bases(&manifests, |gen_id, _| gen_id == "g3");
+        assert!(bases.is_empty());
+    }
+
+    #[test]
+    fn delta_bases_tolerates_cycles() {
+        let manifests = vec![
+            manifest_with_base("a", 1, Some("b")),
+            manifest_with_base("b", 2, Some("a")),
+        ];
+        let bases = delta_bases(&manifests, |_, _| true);
+        assert_eq!(bases.len(), 2);
+    }
+
+    // --- key_generation tests ---
+
+    #[test]
+    fn key_generation_accepts_top_level_objects() {
+        assert_eq!(key_generation("g1/manifest.json"), Some("g1"));
+        assert_eq!(key_generation("g1/snapshot"), Some("g1"));
+        assert_eq!(key_generation("g1/delta"), Some("g1"));
+        assert_eq!(key_generation("g1/wal/00000001"), Some("g1"));
+    }
+
+    #[test]
+    fn key_generation_skips_nested_databases() {
+        assert_eq!(key_generation("latest"), None);
+        assert_eq!(key_generation("app/g1/snapshot"), None);
+        assert_eq!(key_generation("app/g1/wal/00000001"), None);
+        assert_eq!(key_generation("app/latest"), None);
+        assert_eq!(key_generation("/snapshot"), None);
+    }
+
+    #[test]
+    fn manifest_generation_only_matches_top_level_manifests() {
+        assert_eq!(manifest_generation("g1/manifest.json"), Some("g1"));
+        assert_eq!(manifest_generation("g1/snapshot"), None);
+        assert_eq!(manifest_generation("app/g1/manifest.json"), None);
+    }
+
     // --- wal_aligned_len tests ---
 
     fn make_wal_header(page_size: u32) -> Vec<u8> {
@@ -955,6 +2371,179 @@ mod tests {
         assert_eq!(wal_aligned_len(&data), WAL_HEADER_SIZE);
     }
 
+    // --- backup_api_image tests ---
+
+    #[test]
+    fn backup_api_image_includes_wal_pages() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("src.db");
+        let db_path = db_path.to_str().unwrap();
+        let conn = Connection::open(db_path).unwrap();
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA wal_autocheckpoint = 0;
+             CREATE TABLE t (x INTEGER);
+             INSERT INTO t VALUES (1), (2), (3);",
+        )
+        .unwrap();
+
+        // The raw file does not contain the uncheckpointed rows yet.
+        let image = backup_api_image(db_path, BACKUP_API_BUSY_TIMEOUT).unwrap();
+        assert!(image.len() > std::fs::metadata(db_path).unwrap().len() as usize);
+
+        let copy_path = tmp.path().join("copy.db");
+        std::fs::write(&copy_path, &image).unwrap();
+        let copy = Connection::open(&copy_path).unwrap();
+        let count: i64 = copy
+            .query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn backup_api_image_gives_up_on_locked_db() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("src.db");
+        let db_path = db_path.to_str().unwrap();
+        let conn = Connection::open(db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE t (x INTEGER);
+             BEGIN EXCLUSIVE;
+             INSERT INTO t VALUES (1);",
+        )
+        .unwrap();
+
+        let started = Instant::now();
+        assert!(backup_api_image(db_path, Duration::from_millis(100)).is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    // --- replication progress tests ---
+
+    #[tokio::test]
+    async fn plain_snapshot_does_not_cover_later_writes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("app.db");
+        let db_path = db_path.to_str().unwrap().to_string();
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA wal_autocheckpoint = 0;
+             CREATE TABLE t (x INTEGER);",
+        )
+        .unwrap();
+        let replica = tmp.path().join("replica");
+        let config = BackupConfig {
+            db_path: db_path.clone(),
+            s3: S3Config::from_url(&format!("file://{}", replica.display())).unwrap(),
+            ..Default::default()
+        };
+        let mut mgr = BackupManager::new(config).await.unwrap();
+        mgr.sync_wal().await.unwrap();
+        let (commands, _rx) = mpsc::channel(1);
+        let handle = ReplicationHandle::new(
+            db_path.clone(),
+            commands,
+            mgr.progress.subscribe(),
+            mgr.events.clone(),
+        );
+
+        // The snapshot does not restart the WAL, so its salt stays live.
+        mgr.snapshot().await.unwrap();
+        conn.execute("INSERT INTO t VALUES (1)", []).unwrap();
+        let position = handle.wal_position().unwrap().unwrap();
+        let wait =
+            tokio::time::timeout(Duration::from_millis(100), handle.wait_replicated(position))
+                .await;
+        assert!(wait.is_err(), "write after snapshot reported as replicated");
+
+        assert!(mgr.sync_wal().await.unwrap());
+        tokio::time::timeout(Duration::from_secs(5), handle.wait_replicated(position))
+            .await
+            .expect("replicated after sync")
+            .unwrap();
+
+        // A checkpoint truncates the WAL, retiring its salt.
+        conn.execute("INSERT INTO t VALUES (2)", []).unwrap();
+        let before_checkpoint = handle.wal_position().unwrap().unwrap();
+        mgr.checkpoint().await.unwrap();
+        assert!(handle.is_replicated(&before_checkpoint));
+        mgr.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn commit_sync_waits_for_frames() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("app.db");
+        let db_path = db_path.to_str().unwrap().to_string();
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA wal_autocheckpoint = 0;
+             CREATE TABLE t (x INTEGER);",
+        )
+        .unwrap();
+        let replica = tmp.path().join("replica");
+        let config = BackupConfig {
+            db_path: db_path.clone(),
+            s3: S3Config::from_url(&format!("file://{}", replica.display())).unwrap(),
+            sync_interval: Duration::from_secs(3600),
+            ..Default::default()
+        };
+        let mut mgr = BackupManager::new(config).await.unwrap();
+        mgr.sync_wal().await.unwrap();
+        let notifier = mgr.sync_notifier();
+        let handle = mgr.spawn();
+
+        // The notification arrives well before the commit reaches the WAL.
+        notifier.notify();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        conn.execute("INSERT INTO t VALUES (1)", []).unwrap();
+        let position = handle.wal_position().unwrap().unwrap();
+        tokio::time::timeout(Duration::from_secs(5), handle.wait_replicated(position))
+            .await
+            .expect("commit synced without polling")
+            .unwrap();
+        handle.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn delta_snapshot_counts_payload_raw_bytes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("app.db");
+        let db_path = db_path.to_str().unwrap().to_string();
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE t (x TEXT);
+             WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 500)
+             INSERT INTO t SELECT hex(randomblob(100)) FROM n;",
+        )
+        .unwrap();
+        let replica = tmp.path().join("replica");
+        let config = BackupConfig {
+            db_path: db_path.clone(),
+            s3: S3Config::from_url(&format!("file://{}", replica.display())).unwrap(),
+            delta_snapshots: Some(crate::config::DeltaSnapshotConfig {
+                max_chain_length: 2,
+                max_changed_percent: 50,
+            }),
+            ..Default::default()
+        };
+        let mut mgr = BackupManager::new(config).await.unwrap();
+        mgr.checkpoint().await.unwrap();
+        let db_size = std::fs::metadata(&db_path).unwrap().len();
+
+        conn.execute("INSERT INTO t VALUES ('x')", []).unwrap();
+        mgr.sync_wal().await.unwrap();
+        let before = mgr.stats.total_raw_bytes;
+        mgr.checkpoint().await.unwrap();
+        assert!(mgr.manifest.base_generation.is_some(), "expected a delta snapshot");
+        let raw = mgr.stats.total_raw_bytes - before;
+        assert!(raw > 0 && raw < db_size / 2, "raw {raw} of {db_size}");
+        mgr.shutdown().await.unwrap();
+    }
+
     // --- now_ms tests ---
 
     #[test]
@@ -16,6 +16,10 @@ pub struct GenerationManifest {
     pub created_at_ms: u64,
     pub snapshot_timestamp_ms: u64,
     pub segments: Vec<SegmentMeta>,
+    /// If set, the snapshot is stored as a page delta (`{gen}/delta`) against
+    /// the snapshot of this generation instead of as a full `{gen}/snapshot`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_generation: Option<String>,
 }
 
 impl GenerationManifest {
@@ -25,6 +29,7 @@ impl GenerationManifest {
             created_at_ms: now_ms,
             snapshot_timestamp_ms: now_ms,
             segments: Vec::new(),
+            base_generation: None,
         }
     }
 
@@ -49,6 +54,7 @@ mod tests {
         assert_eq!(m.created_at_ms, 1000);
         assert_eq!(m.snapshot_timestamp_ms, 1000);
         assert!(m.segments.is_empty());
+        assert!(m.base_generation.is_none());
     }
 
     #[test]
@@ -85,4 +91,27 @@ mod tests {
         assert_eq!(m, m2);
         assert!(m2.segments.is_empty());
     }
+
+    #[test]
+    fn full_snapshot_omits_base_generation() {
+        let m = GenerationManifest::new("gen-full".into(), 0);
+        let json = serde_json::to_string(&m).unwrap();
+        assert!(!json.contains("base_generation"));
+    }
+
+    #[test]
+    fn delta_manifest_serde_roundtrip() {
+        let mut m = GenerationManifest::new("gen-delta".into(), 10);
+        m.base_generation = Some("gen-base".into());
+        let json = serde_json::to_string(&m).unwrap();
+        let m2: GenerationManifest = serde_json::from_str(&json).unwrap();
+        assert_eq!(m2.base_generation.as_deref(), Some("gen-base"));
+    }
+
+    #[test]
+    fn manifest_without_base_generation_parses() {
+        let json = r#"{"generation":"g","created_at_ms":1,"snapshot_timestamp_ms":1,"segments":[]}"#;
+        let m: GenerationManifest = serde_json::from_str(json).unwrap();
+        assert!(m.base_generation.is_none());
+    }
 }
@@ -0,0 +1,351 @@
+//! Prometheus metrics in the text exposition format.
+//!
+//! Every metric carries a `db` label with the database path, so the output
+//! of a [`ReplicaSet`](crate::ReplicaSet) covers all of its databases.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::stats::BackupStats;
+
+/// Upper bounds of duration histogram buckets, in seconds.
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+/// Upper bounds of size histogram buckets, in bytes.
+const SIZE_BUCKETS: &[f64] = &[
+    1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0, 67108864.0,
+];
+
+/// A cumulative Prometheus histogram.
+#[derive(Clone, Debug)]
+pub(crate) struct Histogram {
+    bounds: &'static [f64],
+    /// Observations per bucket (not cumulative); the last entry is `+Inf`.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|&b| value <= b)
+            .unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+
+    pub fn observe_duration(&mut self, d: Duration) {
+        self.observe(d.as_secs_f64());
+    }
+}
+
+/// Histograms and error counts collected by a [`BackupManager`](crate::BackupManager).
+#[derive(Clone, Debug)]
+pub(crate) struct Metrics {
+    pub sync_duration: Histogram,
+    pub snapshot_duration: Histogram,
+    pub checkpoint_duration: Histogram,
+    pub segment_size: Histogram,
+    /// Failed syncs by error kind.
+    pub errors: BTreeMap<&'static str, u64>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            sync_duration: Histogram::new(DURATION_BUCKETS),
+            snapshot_duration: Histogram::new(DURATION_BUCKETS),
+            checkpoint_duration: Histogram::new(DURATION_BUCKETS),
+            segment_size: Histogram::new(SIZE_BUCKETS),
+            errors: BTreeMap::new(),
+        }
+    }
+
+    pub fn record_error(&mut self, error: &Error) {
+        *self.errors.entry(error.kind()).or_default() += 1;
+    }
+}
+
+/// Everything exported for one database.
+pub(crate) struct Sample {
+    pub db: String,
+    pub stats: BackupStats,
+    pub metrics: Metrics,
+}
+
+/// Escape a label value per the exposition format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn histogram(out: &mut String, name: &str, db: &str, h: &Histogram) {
+    let mut cumulative = 0;
+    for (i, count) in h.counts.iter().enumerate() {
+        cumulative += count;
+        let le = h
+            .bounds
+            .get(i)
+            .map_or_else(|| "+Inf".to_string(), |b| b.to_string());
+        let _ = writeln!(out, "{name}_bucket{{db=\"{db}\",le=\"{le}\"}} {cumulative}");
+    }
+    let _ = writeln!(out, "{name}_sum{{db=\"{db}\"}} {}", h.sum);
+    let _ = writeln!(out, "{name}_count{{db=\"{db}\"}} {}", h.count);
+}
+
+/// Reads one value of a gauge or counter from a sample.
+type ValueFn = fn(&Sample) -> Option<f64>;
+/// Selects one histogram from collected metrics.
+type HistogramFn = fn(&Metrics) -> &Histogram;
+
+/// Render samples in the Prometheus text format, grouped by metric family.
+pub(crate) fn render(samples: &[Sample]) -> String {
+    let mut out = String::new();
+    let dbs: Vec<String> = samples.iter().map(|s| escape(&s.db)).collect();
+
+    let gauges: [(&str, &str, &str, ValueFn); 7] = [
+        (
+            "waloy_last_sync_age_seconds",
+            "gauge",
+            "Seconds since WAL frames were last uploaded.",
+            |s| s.stats.last_sync_time.map(|t| t.elapsed().as_secs_f64()),
+        ),
+        (
+            "waloy_last_snapshot_age_seconds",
+            "gauge",
+            "Seconds since the last snapshot was uploaded.",
+            |s| {
+                s.stats
+                    .last_snapshot_time
+                    .map(|t| t.elapsed().as_secs_f64())
+            },
+        ),
+        (
+            "waloy_wal_size_bytes",
+            "gauge",
+            "Current size of the -wal file.",
+            |s| Some(s.stats.wal_size as f64),
+        ),
+        (
+            "waloy_wal_pending_bytes",
+            "gauge",
+            "WAL bytes written but not yet uploaded.",
+            |s| Some(s.stats.pending_wal_bytes as f64),
+        ),
+        (
+            "waloy_uploaded_bytes_total",
+            "counter",
+            "Bytes uploaded for snapshots and WAL segments.",
+            |s| Some(s.stats.total_bytes_uploaded as f64),
+        ),
+        (
+            "waloy_syncs_total",
+            "counter",
+            "WAL segments uploaded.",
+            |s| Some(s.stats.sync_count as f64),
+        ),
+        (
+            "waloy_generations_total",
+            "counter",
+            "Generations started.",
+            |s| Some(s.stats.generation_count as f64),
+        ),
+    ];
+    for (name, kind, help, value) in gauges {
+        family(&mut out, name, kind, help);
+        for (sample, db) in samples.iter().zip(&dbs) {
+            if let Some(v) = value(sample) {
+                let _ = writeln!(out, "{name}{{db=\"{db}\"}} {v}");
+            }
+        }
+    }
+
+    family(
+        &mut out,
+        "waloy_errors_total",
+        "counter",
+        "Failed WAL syncs by error kind.",
+    );
+    for (sample, db) in samples.iter().zip(&dbs) {
+        for (kind, count) in &sample.metrics.errors {
+            let _ = writeln!(
+                out,
+                "waloy_errors_total{{db=\"{db}\",kind=\"{kind}\"}} {count}"
+            );
+        }
+    }
+
+    let histograms: [(&str, &str, HistogramFn); 4] = [
+        (
+            "waloy_sync_duration_seconds",
+            "Duration of WAL syncs that uploaded a segment.",
+            |m| &m.sync_duration,
+        ),
+        (
+            "waloy_snapshot_duration_seconds",
+            "Duration of snapshot uploads.",
+            |m| &m.snapshot_duration,
+        ),
+        (
+            "waloy_checkpoint_duration_seconds",
+            "Duration of checkpoints, including the following snapshot.",
+            |m| &m.checkpoint_duration,
+        ),
+        (
+            "waloy_segment_size_bytes",
+            "Size of uploaded WAL segments after compression and encryption.",
+            |m| &m.segment_size,
+        ),
+    ];
+    for (name, help, get) in histograms {
+        family(&mut out, name, "histogram", help);
+        for (sample, db) in samples.iter().zip(&dbs) {
+            histogram(&mut out, name, db, get(&sample.metrics));
+        }
+    }
+    out
+}
+
+/// Something that can render Prometheus metrics, served by [`router`].
+#[cfg(feature = "axum")]
+pub trait MetricsSource: Send + Sync + 'static {
+    fn prometheus_metrics(&self) -> impl std::future::Future<Output = String> + Send;
+}
+
+#[cfg(feature = "axum")]
+impl MetricsSource for tokio::sync::Mutex<crate::BackupManager> {
+    async fn prometheus_metrics(&self) -> String {
+        self.lock().await.prometheus_metrics()
+    }
+}
+
+#[cfg(feature = "axum")]
+impl MetricsSource for crate::ReplicaSet {
+    async fn prometheus_metrics(&self) -> String {
+        crate::ReplicaSet::prometheus_metrics(self).await
+    }
+}
+
+/// Serves nothing, logging a warning, once the replication task has stopped.
+#[cfg(feature = "axum")]
+impl MetricsSource for crate::ReplicationHandle {
+    async fn prometheus_metrics(&self) -> String {
+        crate::ReplicationHandle::prometheus_metrics(self)
+            .await
+            .unwrap_or_else(|e| {
+                tracing::warn!(error = %e, "metrics unavailable");
+                String::new()
+            })
+    }
+}
+
+/// An axum router serving `GET /metrics` from `source`, to be merged into
+/// the application's router.
+#[cfg(feature = "axum")]
+pub fn router<S: MetricsSource>(source: std::sync::Arc<S>) -> axum::Router {
+    use axum::http::header;
+    use axum::routing::get;
+
+    axum::Router::new().route(
+        "/metrics",
+        get(move || {
+            let source = source.clone();
+            async move {
+                (
+                    [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+                    source.prometheus_metrics().await,
+                )
+            }
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn sample(db: &str) -> Sample {
+        let mut metrics = Metrics::new();
+        metrics.sync_duration.observe(0.02);
+        metrics.sync_duration.observe(100.0);
+        metrics.segment_size.observe(2000.0);
+        metrics.record_error(&Error::S3("timeout".into()));
+        Sample {
+            db: db.into(),
+            stats: BackupStats {
+                generation: "g".into(),
+                generation_count: 2,
+                wal_offset: 100,
+                wal_index: 1,
+                last_sync_time: Some(Instant::now()),
+                last_snapshot_time: None,
+                last_sync_at_ms: None,
+                last_snapshot_at_ms: None,
+                last_checkpoint_at_ms: None,
+                total_bytes_uploaded: 4096,
+                sync_count: 3,
+                error_count: 1,
+                last_error: None,
+                wal_size: 250,
+                pending_wal_bytes: 150,
+                compression_ratio: None,
+                last_sync_trigger: None,
+                watching: false,
+            },
+            metrics,
+        }
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let out = render(&[sample("a.db")]);
+        assert!(out.contains("waloy_sync_duration_seconds_bucket{db=\"a.db\",le=\"0.01\"} 0"));
+        assert!(out.contains("waloy_sync_duration_seconds_bucket{db=\"a.db\",le=\"0.025\"} 1"));
+        assert!(out.contains("waloy_sync_duration_seconds_bucket{db=\"a.db\",le=\"60\"} 1"));
+        assert!(out.contains("waloy_sync_duration_seconds_bucket{db=\"a.db\",le=\"+Inf\"} 2"));
+        assert!(out.contains("waloy_sync_duration_seconds_count{db=\"a.db\"} 2"));
+        assert!(out.contains("waloy_segment_size_bytes_bucket{db=\"a.db\",le=\"4096\"} 1"));
+    }
+
+    #[test]
+    fn render_gauges_and_errors() {
+        let out = render(&[sample("a.db")]);
+        assert!(out.contains("waloy_wal_pending_bytes{db=\"a.db\"} 150"));
+        assert!(out.contains("waloy_uploaded_bytes_total{db=\"a.db\"} 4096"));
+        assert!(out.contains("waloy_errors_total{db=\"a.db\",kind=\"s3\"} 1"));
+        // Never snapshotted: no sample, but the family is still described.
+        assert!(out.contains("# TYPE waloy_last_snapshot_age_seconds gauge"));
+        assert!(!out.contains("waloy_last_snapshot_age_seconds{"));
+    }
+
+    #[test]
+    fn families_are_declared_once() {
+        let out = render(&[sample("a.db"), sample("b\"c.db")]);
+        assert_eq!(out.matches("# TYPE waloy_syncs_total counter").count(), 1);
+        assert!(out.contains("waloy_syncs_total{db=\"b\\\"c.db\"} 3"));
+    }
+}
@@ -0,0 +1,490 @@
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::path::Path;
+
+use rusqlite::{Connection, OpenFlags};
+
+use crate::config::BackupConfig;
+use crate::delta;
+use crate::error::{Error, Result};
+use crate::s3::S3Client;
+
+/// Outcome of a single preflight check.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    /// Replication can start, but the setting is risky or will be changed.
+    Warn,
+    /// Replication would fail or lose data.
+    Fail,
+}
+
+/// One check performed by [`preflight`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreflightCheck {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    /// What was found and, for warnings and failures, how to fix it.
+    pub detail: String,
+}
+
+impl PreflightCheck {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+        }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Warn,
+            detail: detail.into(),
+        }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl fmt::Display for PreflightCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self.status {
+            CheckStatus::Pass => "ok",
+            CheckStatus::Warn => "warn",
+            CheckStatus::Fail => "FAIL",
+        };
+        write!(f, "[{status}] {}: {}", self.name, self.detail)
+    }
+}
+
+/// Results of [`preflight`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PreflightReport {
+    pub checks: Vec<PreflightCheck>,
+}
+
+impl PreflightReport {
+    /// True if no check failed.
+    pub fn is_ok(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &PreflightCheck> {
+        self.checks.iter().filter(|c| c.status == CheckStatus::Fail)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &PreflightCheck> {
+        self.checks.iter().filter(|c| c.status == CheckStatus::Warn)
+    }
+
+    /// Add checks of the application's own connection. Its
+    /// `wal_autocheckpoint` and `locking_mode` are per-connection settings
+    /// that cannot be seen from waloy's connection.
+    pub fn with_connection(mut self, conn: &Connection) -> Self {
+        self.checks.extend(check_connection(conn));
+        self
+    }
+
+    /// Return the report if no check failed, or an error listing every failure.
+    pub fn into_result(self) -> Result<Self> {
+        if self.is_ok() {
+            return Ok(self);
+        }
+        let failures: Vec<String> = self.failures().map(|c| c.to_string()).collect();
+        Err(Error::Other(format!(
+            "preflight failed:\n{}",
+            failures.join("\n")
+        )))
+    }
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            writeln!(f, "{check}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Check that `config` can be replicated before starting a
+/// [`BackupManager`](crate::BackupManager): the database file and its
+/// directory, the journal mode and page size, and that the bucket is
+/// reachable and writable (by writing and deleting a probe object).
+///
+/// Per-connection settings of the application are checked with
+/// [`PreflightReport::with_connection`].
+pub async fn preflight(config: &BackupConfig) -> PreflightReport {
+    let mut report = PreflightReport::default();
+    let db_path = config.db_path.clone();
+    let local = tokio::task::spawn_blocking(move || check_database(&db_path)).await;
+    match local {
+        Ok(checks) => report.checks.extend(checks),
+        Err(e) => report
+            .checks
+            .push(PreflightCheck::fail("database", format!("check task: {e}"))),
+    }
+    // A missing database is fine when it will be restored from the bucket.
+    if config.auto_restore {
+        for check in &mut report.checks {
+            if check.name == "database" && check.status == CheckStatus::Fail {
+                check.status = CheckStatus::Warn;
+                check
+                    .detail
+                    .push_str("; auto_restore will restore it from the bucket");
+            }
+        }
+    }
+    report.checks.extend(check_bucket(config).await);
+    report
+}
+
+/// Whether the current user can open `path` for writing.
+fn writable(path: &Path) -> bool {
+    OpenOptions::new().write(true).open(path).is_ok()
+}
+
+/// Whether the current user can create files in `dir`, by creating and
+/// removing a probe file.
+fn dir_writable(dir: &Path) -> bool {
+    let probe = dir.join(format!(".waloy-preflight-{}", uuid::Uuid::new_v4()));
+    let created = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .is_ok();
+    if created {
+        let _ = std::fs::remove_file(&probe);
+    }
+    created
+}
+
+fn check_database(db_path: &str) -> Vec<PreflightCheck> {
+    let mut checks = Vec::new();
+    let path = Path::new(db_path);
+
+    let metadata = match std::fs::metadata(path) {
+        Ok(m) => m,
+        Err(e) => {
+            checks.push(PreflightCheck::fail(
+                "database",
+                format!("{db_path}: {e}; create the database before replicating it"),
+            ));
+            return checks;
+        }
+    };
+    if !metadata.is_file() {
+        checks.push(PreflightCheck::fail(
+            "database",
+            format!("{db_path} is not a regular file"),
+        ));
+        return checks;
+    }
+    checks.push(PreflightCheck::pass("database", db_path));
+
+    // waloy checkpoints through its own connection, and SQLite creates the
+    // -wal and -shm files next to the database.
+    let dir = match path.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    let unwritable_sidecar = ["-wal", "-shm"]
+        .map(|suffix| format!("{db_path}{suffix}"))
+        .into_iter()
+        .find(|p| Path::new(p).exists() && !writable(Path::new(p)));
+    if !writable(path) {
+        checks.push(PreflightCheck::fail(
+            "permissions",
+            format!("{db_path} is not writable; waloy needs write access to checkpoint"),
+        ));
+    } else if let Some(sidecar) = unwritable_sidecar {
+        checks.push(PreflightCheck::fail(
+            "permissions",
+            format!("{sidecar} is not writable; waloy needs write access to checkpoint"),
+        ));
+    } else if !dir_writable(dir) {
+        checks.push(PreflightCheck::fail(
+            "permissions",
+            format!(
+                "{} is not writable; SQLite must create the -wal and -shm files there",
+                dir.display()
+            ),
+        ));
+    } else {
+        checks.push(PreflightCheck::pass(
+            "permissions",
+            "database and directory are writable",
+        ));
+    }
+
+    let mut header = Vec::with_capacity(100);
+    let read = std::fs::File::open(path).and_then(|f| f.take(100).read_to_end(&mut header));
+    let page_size = header
+        .starts_with(b"SQLite format 3\0")
+        .then(|| delta::header_page_size(&header))
+        .flatten();
+    match (read, page_size) {
+        (Err(e), _) => {
+            checks.push(PreflightCheck::fail(
+                "page_size",
+                format!("cannot read header: {e}"),
+            ));
+            return checks;
+        }
+        // An empty file becomes a database on first write.
+        (Ok(0), _) => checks.push(PreflightCheck::warn(
+            "page_size",
+            "database is empty; the page size is set on first write",
+        )),
+        (Ok(_), Some(page_size)) => {
+            checks.push(PreflightCheck::pass("page_size", page_size.to_string()))
+        }
+        (Ok(_), None) => {
+            checks.push(PreflightCheck::fail(
+                "page_size",
+                format!("{db_path} is not a SQLite database"),
+            ));
+            return checks;
+        }
+    }
+
+    let journal_mode = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .and_then(|conn| conn.query_row("PRAGMA journal_mode", [], |row| row.get::<_, String>(0)));
+    checks.push(match journal_mode {
+        Ok(mode) if mode.eq_ignore_ascii_case("wal") => PreflightCheck::pass("journal_mode", mode),
+        Ok(mode) => PreflightCheck::warn(
+            "journal_mode",
+            format!(
+                "{mode}; waloy switches the database to WAL, so the application must not change it back"
+            ),
+        ),
+        Err(e) => PreflightCheck::fail("journal_mode", format!("cannot open database: {e}")),
+    });
+    checks
+}
+
+/// Check the per-connection settings of an application connection.
+pub fn check_connection(conn: &Connection) -> Vec<PreflightCheck> {
+    let mut checks = Vec::new();
+    let pragma = |name: &str| -> rusqlite::Result<String> {
+        conn.query_row(&format!("PRAGMA {name}"), [], |row| {
+            row.get::<_, rusqlite::types::Value>(0).map(|v| match v {
+                rusqlite::types::Value::Integer(i) => i.to_string(),
+                rusqlite::types::Value::Text(s) => s,
+                other => format!("{other:?}"),
+            })
+        })
+    };
+
+    checks.push(match pragma("journal_mode") {
+        Ok(mode) if mode.eq_ignore_ascii_case("wal") => {
+            PreflightCheck::pass("connection.journal_mode", mode)
+        }
+        Ok(mode) => PreflightCheck::fail(
+            "connection.journal_mode",
+            format!("{mode}; run PRAGMA journal_mode = WAL on the application connection"),
+        ),
+        Err(e) => PreflightCheck::fail("connection.journal_mode", e.to_string()),
+    });
+
+    checks.push(match pragma("wal_autocheckpoint") {
+        Ok(pages) if pages == "0" => PreflightCheck::pass("connection.wal_autocheckpoint", pages),
+        Ok(pages) => PreflightCheck::fail(
+            "connection.wal_autocheckpoint",
+            format!(
+                "{pages} pages; run PRAGMA wal_autocheckpoint = 0 on the application connection, \
+                 otherwise frames are checkpointed before upload and waloy keeps starting new generations"
+            ),
+        ),
+        Err(e) => PreflightCheck::fail("connection.wal_autocheckpoint", e.to_string()),
+    });
+
+    checks.push(match pragma("locking_mode") {
+        Ok(mode) if mode.eq_ignore_ascii_case("normal") => {
+            PreflightCheck::pass("connection.locking_mode", mode)
+        }
+        Ok(mode) => PreflightCheck::fail(
+            "connection.locking_mode",
+            format!(
+                "{mode}; waloy reads the database through its own connection, \
+                 run PRAGMA locking_mode = NORMAL"
+            ),
+        ),
+        Err(e) => PreflightCheck::fail("connection.locking_mode", e.to_string()),
+    });
+
+    checks.push(match pragma("busy_timeout") {
+        Ok(ms) if ms != "0" => PreflightCheck::pass("connection.busy_timeout", format!("{ms} ms")),
+        Ok(_) => PreflightCheck::warn(
+            "connection.busy_timeout",
+            "0; writes fail with SQLITE_BUSY while waloy checkpoints, set PRAGMA busy_timeout = 5000",
+        ),
+        Err(e) => PreflightCheck::fail("connection.busy_timeout", e.to_string()),
+    });
+    checks
+}
+
+/// Check that the bucket can be listed and written to.
+async fn check_bucket(config: &BackupConfig) -> Vec<PreflightCheck> {
+    let s3 = match S3Client::new(&config.s3) {
+        Ok(s3) => s3,
+        Err(e) => {
+            return vec![PreflightCheck::fail(
+                "bucket_read",
+                format!("invalid S3 configuration: {e}"),
+            )];
+        }
+    };
+    let bucket = match config.s3.local_dir() {
+        Some(dir) => dir.display().to_string(),
+        None => config.s3.bucket.clone(),
+    };
+    let mut checks = Vec::new();
+    checks.push(match s3.list_keys("latest").await {
+        Ok(_) => PreflightCheck::pass("bucket_read", format!("{bucket} is reachable")),
+        Err(e) => PreflightCheck::fail(
+            "bucket_read",
+            format!(
+                "cannot list {bucket} at {}: {e}; check the endpoint, region and credentials",
+                config.s3.endpoint
+            ),
+        ),
+    });
+
+    let probe = format!(".preflight-{}", uuid::Uuid::new_v4());
+    checks.push(match s3.put_object(&probe, b"waloy preflight").await {
+        Ok(()) => {
+            if let Err(e) = s3.delete_object(&probe).await {
+                tracing::warn!(key = %probe, error = %e, "failed to delete preflight probe");
+            }
+            PreflightCheck::pass("bucket_write", format!("{bucket} is writable"))
+        }
+        Err(e) => PreflightCheck::fail(
+            "bucket_write",
+            format!(
+                "cannot write to {bucket}: {e}; the credentials need PutObject and DeleteObject"
+            ),
+        ),
+    });
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wal_db(path: &Path) -> Connection {
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA busy_timeout = 5000;
+             PRAGMA wal_autocheckpoint = 0;
+             CREATE TABLE t (x INTEGER);",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn status(checks: &[PreflightCheck], name: &str) -> CheckStatus {
+        checks
+            .iter()
+            .find(|c| c.name == name)
+            .unwrap_or_else(|| panic!("missing check {name}"))
+            .status
+    }
+
+    #[test]
+    fn wal_database_passes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("app.db");
+        let _conn = wal_db(&path);
+        let checks = check_database(path.to_str().unwrap());
+        assert!(
+            checks.iter().all(|c| c.status == CheckStatus::Pass),
+            "{checks:?}"
+        );
+    }
+
+    #[test]
+    fn missing_database_fails() {
+        let checks = check_database("/nonexistent-waloy-dir/app.db");
+        assert_eq!(status(&checks, "database"), CheckStatus::Fail);
+    }
+
+    #[test]
+    fn unwritable_wal_fails() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("app.db");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch("CREATE TABLE t (x INTEGER)").unwrap();
+        // A directory cannot be opened for writing, whoever runs the test.
+        std::fs::create_dir(tmp.path().join("app.db-wal")).unwrap();
+        let checks = check_database(path.to_str().unwrap());
+        assert_eq!(status(&checks, "permissions"), CheckStatus::Fail);
+    }
+
+    #[test]
+    fn non_sqlite_file_fails() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("app.db");
+        std::fs::write(&path, vec![b'x'; 4096]).unwrap();
+        let checks = check_database(path.to_str().unwrap());
+        assert_eq!(status(&checks, "page_size"), CheckStatus::Fail);
+    }
+
+    #[test]
+    fn rollback_journal_warns() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("app.db");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch("CREATE TABLE t (x INTEGER)").unwrap();
+        let checks = check_database(path.to_str().unwrap());
+        assert_eq!(status(&checks, "journal_mode"), CheckStatus::Warn);
+    }
+
+    #[test]
+    fn connection_with_required_pragmas_passes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let conn = wal_db(&tmp.path().join("app.db"));
+        let checks = check_connection(&conn);
+        assert!(
+            checks.iter().all(|c| c.status == CheckStatus::Pass),
+            "{checks:?}"
+        );
+    }
+
+    #[test]
+    fn connection_autocheckpoint_and_exclusive_lock_fail() {
+        let tmp = tempfile::tempdir().unwrap();
+        let conn = wal_db(&tmp.path().join("app.db"));
+        conn.execute_batch(
+            "PRAGMA wal_autocheckpoint = 1000;
+             PRAGMA locking_mode = EXCLUSIVE;",
+        )
+        .unwrap();
+        let checks = check_connection(&conn);
+        assert_eq!(
+            status(&checks, "connection.wal_autocheckpoint"),
+            CheckStatus::Fail
+        );
+        assert_eq!(
+            status(&checks, "connection.locking_mode"),
+            CheckStatus::Fail
+        );
+
+        let report = PreflightReport { checks }.into_result().unwrap_err();
+        assert!(report.to_string().contains("wal_autocheckpoint = 0"));
+    }
+}
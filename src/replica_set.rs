@@ -0,0 +1,473 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::{Mutex, RwLock, Semaphore};
+use tokio::task::JoinSet;
+
+use crate::config::{BackupConfig, S3Config};
+use crate::discovery::{DirectoryDiscovery, DiscoveryChanges};
+use crate::error::{Error, Result};
+use crate::health::Health;
+use crate::manager::BackupManager;
+use crate::s3::S3Client;
+use crate::stats::BackupStats;
+
+/// A database replicated by a [`ReplicaSet`].
+struct Replica {
+    manager: Arc<Mutex<BackupManager>>,
+    sync_interval: Duration,
+    /// When this replica was last synced by [`ReplicaSet::run`].
+    last_sync: std::sync::Mutex<Option<Instant>>,
+}
+
+impl Replica {
+    /// Whether the replica's own sync interval has elapsed; if so, marks it synced.
+    fn sync_due(&self, now: Instant) -> bool {
+        take_if_due(&mut self.last_sync.lock().unwrap(), self.sync_interval, now)
+    }
+}
+
+/// Returns true (and records `now`) if `interval` has elapsed since `last`.
+fn take_if_due(last: &mut Option<Instant>, interval: Duration, now: Instant) -> bool {
+    match *last {
+        Some(t) if now.duration_since(t) < interval => false,
+        _ => {
+            *last = Some(now);
+            true
+        }
+    }
+}
+
+/// Combined statistics for all databases in a [`ReplicaSet`].
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct ReplicaSetStats {
+    /// Per-database stats, keyed by database path.
+    pub replicas: BTreeMap<String, BackupStats>,
+    pub total_bytes_uploaded: u64,
+    pub sync_count: u64,
+    pub error_count: u64,
+}
+
+/// Replicates many SQLite databases from one runtime.
+///
+/// Databases whose replicas live in the same bucket (same endpoint, region,
+/// bucket and credentials) share one S3 client, and uploads across all
+/// databases are bounded by a single concurrency limit.
+pub struct ReplicaSet {
+    replicas: RwLock<BTreeMap<String, Arc<Replica>>>,
+    /// Shared S3 clients keyed by everything but the prefix.
+    clients: std::sync::Mutex<Vec<(S3Config, S3Client)>>,
+    upload_limit: Arc<Semaphore>,
+}
+
+impl ReplicaSet {
+    /// Create an empty replica set allowing at most `max_concurrent_uploads`
+    /// S3 uploads in flight at once (at least one).
+    pub fn new(max_concurrent_uploads: usize) -> Self {
+        Self {
+            replicas: RwLock::new(BTreeMap::new()),
+            clients: std::sync::Mutex::new(Vec::new()),
+            upload_limit: Arc::new(Semaphore::new(max_concurrent_uploads.max(1))),
+        }
+    }
+
+    /// Return a client for `config`, reusing the connection of an earlier
+    /// replica in the same bucket.
+    fn client_for(&self, config: &S3Config) -> Result<S3Client> {
+        let mut clients = self.clients.lock().unwrap();
+        let same_bucket = |c: &S3Config| {
+            c.endpoint == config.endpoint
+                && c.region == config.region
+                && c.bucket == config.bucket
+                && c.access_key == config.access_key
+                && c.secret_key == config.secret_key
+        };
+        if let Some((_, client)) = clients.iter().find(|(c, _)| same_bucket(c)) {
+            return Ok(client.with_prefix(&config.prefix));
+        }
+        let client = S3Client::new(config)?.with_upload_limit(self.upload_limit.clone());
+        clients.push((config.clone(), client.clone()));
+        Ok(client)
+    }
+
+    /// Start replicating a database. Creates its [`BackupManager`], which
+    /// takes the initial snapshot.
+    pub async fn add(&self, config: BackupConfig) -> Result<()> {
+        let db_path = config.db_path.clone();
+        if self.replicas.read().await.contains_key(&db_path) {
+            return Err(Error::Other(format!(
+                "database already replicated: {db_path}"
+            )));
+        }
+
+        let s3 = self.client_for(&config.s3)?;
+        let sync_interval = config.sync_interval;
+        let mut manager = BackupManager::with_client(config, s3).await?;
+
+        let mut replicas = self.replicas.write().await;
+        if replicas.contains_key(&db_path) {
+            // A concurrent `add` of the same database won; finish this
+            // manager's replication cleanly before reporting the duplicate.
+            drop(replicas);
+            if let Err(e) = manager.shutdown().await {
+                tracing::warn!(db_path = %db_path, error = %e, "shutdown of duplicate replica failed");
+            }
+            return Err(Error::Other(format!(
+                "database already replicated: {db_path}"
+            )));
+        }
+        let replica = Arc::new(Replica {
+            manager: Arc::new(Mutex::new(manager)),
+            sync_interval,
+            last_sync: std::sync::Mutex::new(None),
+        });
+        replicas.insert(db_path.clone(), replica);
+        tracing::info!(db_path = %db_path, "replica added");
+        Ok(())
+    }
+
+    /// Stop replicating a database, performing a final sync first.
+    /// Returns false if the database was not part of the set.
+    pub async fn remove(&self, db_path: &str) -> Result<bool> {
+        let Some(replica) = self.replicas.write().await.remove(db_path) else {
+            return Ok(false);
+        };
+        replica.manager.lock().await.shutdown().await?;
+        tracing::info!(db_path, "replica removed");
+        Ok(true)
+    }
+
+    /// Paths of all replicated databases.
+    pub async fn db_paths(&self) -> Vec<String> {
+        self.replicas.read().await.keys().cloned().collect()
+    }
+
+    /// The manager for one database, for operations such as checkpoints,
+    /// retention or compaction.
+    pub async fn get(&self, db_path: &str) -> Option<Arc<Mutex<BackupManager>>> {
+        self.replicas
+            .read()
+            .await
+            .get(db_path)
+            .map(|r| r.manager.clone())
+    }
+
+    /// Sync WAL frames and take any scheduled snapshots for the given replicas
+    /// concurrently. Failures are logged and counted in each replica's stats.
+    async fn sync_replicas(replicas: Vec<(String, Arc<Replica>)>) {
+        let mut tasks = JoinSet::new();
+        for (db_path, replica) in replicas {
+            tasks.spawn(async move {
+                let mut mgr = replica.manager.lock().await;
+                if let Err(e) = mgr.sync_wal().await {
+                    tracing::warn!(db_path = %db_path, error = %e, "WAL sync failed");
+                    return;
+                }
+                if let Err(e) = mgr.maybe_snapshot().await {
+                    tracing::warn!(db_path = %db_path, error = %e, "scheduled snapshot failed");
+                }
+            });
+        }
+        while tasks.join_next().await.is_some() {}
+    }
+
+    /// Sync every database once, regardless of its sync interval.
+    pub async fn sync_all(&self) {
+        let replicas = self
+            .replicas
+            .read()
+            .await
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        Self::sync_replicas(replicas).await;
+    }
+
+    /// Run the sync loop until `shutdown` completes, then shut every replica
+    /// down. Each database is synced at its own `sync_interval`.
+    pub async fn run<F>(&self, shutdown: F) -> Result<()>
+    where
+        F: std::future::Future<Output = ()>,
+    {
+        self.run_inner(None, shutdown).await
+    }
+
+    /// Like [`run`](Self::run), but also rescans `discovery` every
+    /// `scan_interval`, replicating new databases and finalizing removed ones.
+    pub async fn run_with_discovery<F>(
+        &self,
+        discovery: &DirectoryDiscovery,
+        shutdown: F,
+    ) -> Result<()>
+    where
+        F: std::future::Future<Output = ()>,
+    {
+        self.run_inner(Some(discovery), shutdown).await
+    }
+
+    async fn run_inner<F>(&self, discovery: Option<&DirectoryDiscovery>, shutdown: F) -> Result<()>
+    where
+        F: std::future::Future<Output = ()>,
+    {
+        tokio::pin!(shutdown);
+        let mut last_scan = None;
+        loop {
+            let now = Instant::now();
+            if let Some(discovery) = discovery
+                && take_if_due(&mut last_scan, discovery.scan_interval, now)
+                && let Err(e) = self.discover(discovery).await
+            {
+                tracing::warn!(pattern = %discovery.pattern, error = %e, "discovery scan failed");
+            }
+
+            let mut tick = self
+                .replicas
+                .read()
+                .await
+                .values()
+                .map(|r| r.sync_interval)
+                .min()
+                .unwrap_or(Duration::from_secs(1));
+            if let Some(discovery) = discovery {
+                tick = tick.min(discovery.scan_interval);
+            }
+
+            tokio::select! {
+                _ = &mut shutdown => break,
+                _ = tokio::time::sleep(tick) => {}
+            }
+
+            let now = Instant::now();
+            let due = self
+                .replicas
+                .read()
+                .await
+                .iter()
+                .filter(|(_, r)| r.sync_due(now))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            Self::sync_replicas(due).await;
+        }
+        self.shutdown().await
+    }
+
+    /// Scan `discovery` once: start replicating newly matching databases and
+    /// finalize replication of matching databases whose file was removed.
+    /// Databases that fail to start are logged and retried on the next scan.
+    /// A database whose derived prefix is already used by another discovered
+    /// database, such as `a.sqlite` next to `a.db`, is logged and skipped.
+    pub async fn discover(&self, discovery: &DirectoryDiscovery) -> Result<DiscoveryChanges> {
+        let found = discovery.scan()?;
+        let current = self.db_paths().await;
+        let mut changes = DiscoveryChanges::default();
+        let mut prefixes: HashSet<String> = current
+            .iter()
+            .filter(|db_path| discovery.matches(db_path))
+            .map(|db_path| discovery.config_for(db_path).s3.prefix)
+            .collect();
+
+        for db_path in &found {
+            if current.contains(db_path) {
+                continue;
+            }
+            let config = discovery.config_for(db_path);
+            let prefix = config.s3.prefix.clone();
+            if prefixes.contains(&prefix) {
+                tracing::warn!(db_path = %db_path, prefix = %prefix, "discovered database would share a prefix with another, skipped");
+                continue;
+            }
+            match self.add(config).await {
+                Ok(()) => {
+                    prefixes.insert(prefix);
+                    changes.added.push(db_path.clone());
+                }
+                Err(e) => {
+                    tracing::warn!(db_path = %db_path, error = %e, "failed to start discovered replica");
+                }
+            }
+        }
+
+        for db_path in current {
+            if discovery.matches(&db_path) && !found.contains(&db_path) {
+                if let Err(e) = self.remove(&db_path).await {
+                    tracing::warn!(db_path = %db_path, error = %e, "final sync of removed database failed");
+                }
+                changes.removed.push(db_path);
+            }
+        }
+
+        if !changes.added.is_empty() || !changes.removed.is_empty() {
+            tracing::info!(
+                added = changes.added.len(),
+                removed = changes.removed.len(),
+                "discovery scan changed replicas"
+            );
+        }
+        Ok(changes)
+    }
+
+    /// Combined statistics across all replicas.
+    pub async fn stats(&self) -> ReplicaSetStats {
+        let mut out = ReplicaSetStats::default();
+        for (db_path, replica) in self.replicas.read().await.iter() {
+            let stats = replica.manager.lock().await.stats();
+            out.total_bytes_uploaded += stats.total_bytes_uploaded;
+            out.sync_count += stats.sync_count;
+            out.error_count += stats.error_count;
+            out.replicas.insert(db_path.clone(), stats);
+        }
+        out
+    }
+
+    /// Metrics for every replica in the Prometheus text format.
+    #[cfg(feature = "metrics")]
+    pub async fn prometheus_metrics(&self) -> String {
+        let mut samples = Vec::new();
+        for replica in self.replicas.read().await.values() {
+            samples.push(replica.manager.lock().await.metrics_sample());
+        }
+        crate::metrics::render(&samples)
+    }
+
+    /// Health of every replica, keyed by database path.
+    pub async fn health(&self) -> BTreeMap<String, Health> {
+        let mut out = BTreeMap::new();
+        for (db_path, replica) in self.replicas.read().await.iter() {
+            out.insert(db_path.clone(), replica.manager.lock().await.health());
+        }
+        out
+    }
+
+    /// Graceful shutdown of every replica: final WAL sync and release of
+    /// read transactions. Replicas stay in the set.
+    ///
+    /// A replica that fails to shut down does not stop the others; the first
+    /// error is returned once all of them have been tried.
+    pub async fn shutdown(&self) -> Result<()> {
+        let mut first_error = None;
+        for (db_path, replica) in self.replicas.read().await.iter() {
+            if let Err(e) = replica.manager.lock().await.shutdown().await {
+                tracing::warn!(db_path = %db_path, error = %e, "final sync failed");
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s3_config(bucket: &str, prefix: &str) -> S3Config {
+        S3Config {
+            endpoint: "http://localhost:3900".into(),
+            region: "us-east-1".into(),
+            bucket: bucket.into(),
+            access_key: "ak".into(),
+            secret_key: "sk".into(),
+            prefix: prefix.into(),
+        }
+    }
+
+    #[test]
+    fn client_for_shares_same_bucket() {
+        let set = ReplicaSet::new(4);
+        let a = set.client_for(&s3_config("bucket", "a")).unwrap();
+        let b = set.client_for(&s3_config("bucket", "b")).unwrap();
+        let c = set.client_for(&s3_config("other", "a")).unwrap();
+        assert!(a.shares_bucket_with(&b));
+        assert!(!a.shares_bucket_with(&c));
+    }
+
+    #[test]
+    fn new_clamps_upload_limit() {
+        let set = ReplicaSet::new(0);
+        assert_eq!(set.upload_limit.available_permits(), 1);
+    }
+
+    #[test]
+    fn take_if_due_respects_interval() {
+        let interval = Duration::from_secs(1);
+        let start = Instant::now();
+        let mut last = None;
+        assert!(take_if_due(&mut last, interval, start));
+        assert!(!take_if_due(
+            &mut last,
+            interval,
+            start + Duration::from_millis(500)
+        ));
+        assert!(take_if_due(&mut last, interval, start + interval));
+        assert_eq!(last, Some(start + interval));
+    }
+
+    #[tokio::test]
+    async fn empty_set_stats() {
+        let set = ReplicaSet::new(2);
+        let stats = set.stats().await;
+        assert!(stats.replicas.is_empty());
+        assert_eq!(stats.total_bytes_uploaded, 0);
+        assert!(set.db_paths().await.is_empty());
+        assert!(!set.remove("/nope.db").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn concurrent_add_of_same_database() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("app.db");
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA wal_autocheckpoint = 0;
+             CREATE TABLE t (x INTEGER);",
+        )
+        .unwrap();
+        let config = BackupConfig {
+            db_path: db_path.to_str().unwrap().to_string(),
+            s3: S3Config::from_url(&format!("file://{}", tmp.path().join("replica").display()))
+                .unwrap(),
+            ..Default::default()
+        };
+
+        let set = ReplicaSet::new(2);
+        let (a, b) = tokio::join!(set.add(config.clone()), set.add(config.clone()));
+        assert!(a.is_ok() != b.is_ok(), "exactly one add should win");
+        let err = a.and(b).unwrap_err();
+        assert!(err.to_string().contains("already replicated"), "{err}");
+        assert_eq!(set.db_paths().await.len(), 1);
+        set.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn discover_skips_shared_prefix() {
+        let tmp = tempfile::tempdir().unwrap();
+        for name in ["a.db", "a.sqlite", "b.db"] {
+            let conn = rusqlite::Connection::open(tmp.path().join(name)).unwrap();
+            conn.execute_batch(
+                "PRAGMA journal_mode = WAL;
+                 PRAGMA wal_autocheckpoint = 0;
+                 CREATE TABLE t (x INTEGER);",
+            )
+            .unwrap();
+        }
+        let replica = tmp.path().join("replica");
+        let template = BackupConfig {
+            s3: S3Config::from_url(&format!("file://{}", replica.display())).unwrap(),
+            ..Default::default()
+        };
+        let pattern = tmp.path().join("*.*");
+        let discovery = DirectoryDiscovery::new(pattern.to_str().unwrap(), template);
+        let path = |name: &str| tmp.path().join(name).to_str().unwrap().to_string();
+
+        let set = ReplicaSet::new(2);
+        let changes = set.discover(&discovery).await.unwrap();
+        assert_eq!(changes.added, [path("a.db"), path("b.db")]);
+        assert!(set.get(&path("a.sqlite")).await.is_none());
+
+        // Later scans keep skipping it.
+        let changes = set.discover(&discovery).await.unwrap();
+        assert!(changes.added.is_empty() && changes.removed.is_empty());
+        set.shutdown().await.unwrap();
+    }
+}
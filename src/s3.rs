@@ -1,20 +1,41 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 
 use crate::config::S3Config;
 use crate::error::{Error, Result};
 use s3::creds::Credentials;
 use s3::{Bucket, Region};
+use tokio::sync::Semaphore;
 
 const MAX_RETRIES: u32 = 3;
 const RETRY_BASE_DELAY_MS: u64 = 100;
 
+/// Where objects are stored.
+#[derive(Clone)]
+enum Backend {
+    Bucket(Arc<Bucket>),
+    /// Files under a local directory, for `file://` endpoints.
+    Directory(Arc<PathBuf>),
+}
+
+#[derive(Clone)]
 pub struct S3Client {
-    bucket: Box<Bucket>,
+    backend: Backend,
     prefix: String,
+    /// Bounds concurrent uploads across all clients sharing the semaphore.
+    upload_limit: Option<Arc<Semaphore>>,
 }
 
 impl S3Client {
     pub fn new(config: &S3Config) -> Result<Self> {
+        if let Some(dir) = config.local_dir() {
+            return Ok(Self {
+                backend: Backend::Directory(Arc::new(dir)),
+                prefix: config.prefix.clone(),
+                upload_limit: None,
+            });
+        }
         let region = Region::Custom {
             region: config.region.clone(),
             endpoint: config.endpoint.clone(),
@@ -33,11 +54,38 @@ impl S3Client {
             .with_path_style();
 
         Ok(Self {
-            bucket,
+            backend: Backend::Bucket(Arc::from(bucket)),
             prefix: config.prefix.clone(),
+            upload_limit: None,
         })
     }
 
+    /// Create a client for another prefix that shares this client's bucket
+    /// connection and upload limit.
+    pub fn with_prefix(&self, prefix: &str) -> Self {
+        Self {
+            backend: self.backend.clone(),
+            prefix: prefix.to_string(),
+            upload_limit: self.upload_limit.clone(),
+        }
+    }
+
+    /// Bound concurrent uploads by acquiring a permit from `limit` for each put.
+    pub fn with_upload_limit(mut self, limit: Arc<Semaphore>) -> Self {
+        self.upload_limit = Some(limit);
+        self
+    }
+
+    /// Whether two clients share the same underlying bucket connection.
+    #[cfg(test)]
+    pub fn shares_bucket_with(&self, other: &Self) -> bool {
+        match (&self.backend, &other.backend) {
+            (Backend::Bucket(a), Backend::Bucket(b)) => Arc::ptr_eq(a, b),
+            (Backend::Directory(a), Backend::Directory(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
     fn full_key(&self, key: &str) -> String {
         if self.prefix.is_empty() {
             key.to_string()
@@ -77,8 +125,21 @@ impl S3Client {
 
     pub async fn put_object(&self, key: &str, data: &[u8]) -> Result<()> {
         let full_key = self.full_key(key);
+        let _permit = match &self.upload_limit {
+            Some(limit) => Some(
+                limit
+                    .acquire()
+                    .await
+                    .map_err(|e| Error::Other(format!("upload limit: {e}")))?,
+            ),
+            None => None,
+        };
+        let bucket = match &self.backend {
+            Backend::Bucket(bucket) => bucket,
+            Backend::Directory(root) => return local::put(root, &full_key, data).await,
+        };
         self.retry("put_object", || async {
-            self.bucket
+            bucket
                 .put_object(&full_key, data)
                 .await
                 .map_err(|e| Error::S3(e.to_string()))?;
@@ -89,9 +150,12 @@ impl S3Client {
 
     pub async fn get_object(&self, key: &str) -> Result<Vec<u8>> {
         let full_key = self.full_key(key);
+        let bucket = match &self.backend {
+            Backend::Bucket(bucket) => bucket,
+            Backend::Directory(root) => return local::get(root, &full_key).await,
+        };
         self.retry("get_object", || async {
-            let response = self
-                .bucket
+            let response = bucket
                 .get_object(&full_key)
                 .await
                 .map_err(|e| Error::S3(e.to_string()))?;
@@ -102,8 +166,12 @@ impl S3Client {
 
     pub async fn delete_object(&self, key: &str) -> Result<()> {
         let full_key = self.full_key(key);
+        let bucket = match &self.backend {
+            Backend::Bucket(bucket) => bucket,
+            Backend::Directory(root) => return local::delete(root, &full_key).await,
+        };
         self.retry("delete_object", || async {
-            self.bucket
+            bucket
                 .delete_object(&full_key)
                 .await
                 .map_err(|e| Error::S3(e.to_string()))?;
@@ -120,32 +188,165 @@ impl S3Client {
     }
 
     pub async fn list_keys(&self, prefix: &str) -> Result<Vec<String>> {
+        let objects = self.list_objects(prefix).await?;
+        Ok(objects.into_iter().map(|(key, _)| key).collect())
+    }
+
+    /// Keys and sizes of the objects starting with `prefix`, sorted by key.
+    pub async fn list_objects(&self, prefix: &str) -> Result<Vec<(String, u64)>> {
         let full_prefix = self.full_key(prefix);
+        let bucket = match &self.backend {
+            Backend::Bucket(bucket) => bucket,
+            Backend::Directory(root) => {
+                let objects = local::list(root, &full_prefix).await?;
+                return Ok(objects
+                    .into_iter()
+                    .map(|(key, size)| (self.relative_key(&key), size))
+                    .collect());
+            }
+        };
         self.retry("list_keys", || async {
-            let results = self
-                .bucket
+            let results = bucket
                 .list(full_prefix.clone(), None)
                 .await
                 .map_err(|e| Error::S3(e.to_string()))?;
 
-            let mut keys = Vec::new();
+            let mut objects = Vec::new();
             for page in &results {
                 for obj in &page.contents {
-                    // Strip our prefix to return relative keys
-                    let rel = if self.prefix.is_empty() {
-                        &obj.key
-                    } else {
-                        obj.key
-                            .strip_prefix(&format!("{}/", self.prefix))
-                            .unwrap_or(&obj.key)
-                    };
-                    keys.push(rel.to_string());
+                    objects.push((self.relative_key(&obj.key), obj.size));
                 }
             }
+            objects.sort();
+            Ok(objects)
+        })
+        .await
+    }
+
+    /// Strip our prefix to return relative keys.
+    fn relative_key(&self, key: &str) -> String {
+        if self.prefix.is_empty() {
+            key.to_string()
+        } else {
+            key.strip_prefix(&format!("{}/", self.prefix))
+                .unwrap_or(key)
+                .to_string()
+        }
+    }
+}
+
+/// Object operations on a local directory. Keys map to relative paths;
+/// writes go to a hidden temporary file that is synced and renamed into
+/// place, so readers never see partial objects and a crash can't lose an
+/// object that was reported as written.
+mod local {
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+
+    pub(super) async fn put(root: &Path, key: &str, data: &[u8]) -> Result<()> {
+        let path = root.join(key);
+        let dir = path.parent().unwrap_or(root);
+        tokio::fs::create_dir_all(dir).await?;
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("object");
+        let tmp = dir.join(format!(".{name}.{}.tmp", uuid::Uuid::new_v4()));
+        let written = async {
+            let mut file = tokio::fs::File::create(&tmp).await?;
+            file.write_all(data).await?;
+            // The data must be durable before the rename makes it visible.
+            file.sync_all().await?;
+            tokio::fs::rename(&tmp, &path).await
+        };
+        if let Err(e) = written.await {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return Err(e.into());
+        }
+        sync_dir(dir).await
+    }
+
+    /// Persist the directory entry created by a rename.
+    #[cfg(unix)]
+    async fn sync_dir(dir: &Path) -> Result<()> {
+        tokio::fs::File::open(dir).await?.sync_all().await?;
+        Ok(())
+    }
+
+    /// Directories can't be opened for syncing on this platform.
+    #[cfg(not(unix))]
+    async fn sync_dir(_dir: &Path) -> Result<()> {
+        Ok(())
+    }
+
+    pub(super) async fn get(root: &Path, key: &str) -> Result<Vec<u8>> {
+        Ok(tokio::fs::read(root.join(key)).await?)
+    }
+
+    /// Deleting a missing object succeeds, as in S3. Directories left empty
+    /// are removed up to `root`.
+    pub(super) async fn delete(root: &Path, key: &str) -> Result<()> {
+        let path = root.join(key);
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        let mut dir = path.parent();
+        while let Some(d) = dir.filter(|d| *d != root) {
+            if tokio::fs::remove_dir(d).await.is_err() {
+                break;
+            }
+            dir = d.parent();
+        }
+        Ok(())
+    }
+
+    /// Keys and sizes of the files under `root` starting with `prefix`, sorted.
+    pub(super) async fn list(root: &Path, prefix: &str) -> Result<Vec<(String, u64)>> {
+        let root = root.to_path_buf();
+        let prefix = prefix.to_string();
+        tokio::task::spawn_blocking(move || {
+            // Only the directory holding the prefix needs walking.
+            let start = prefix.rfind('/').map_or("", |i| &prefix[..i]);
+            let mut keys = Vec::new();
+            walk(&root.join(start), start, &mut keys)?;
+            keys.retain(|(k, _)| k.starts_with(&prefix));
             keys.sort();
             Ok(keys)
         })
         .await
+        .map_err(|e| Error::Other(format!("list task: {e}")))?
+    }
+
+    fn walk(dir: &Path, key_prefix: &str, keys: &mut Vec<(String, u64)>) -> Result<()> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                continue;
+            };
+            if name.starts_with('.') {
+                continue;
+            }
+            let key = if key_prefix.is_empty() {
+                name
+            } else {
+                format!("{key_prefix}/{name}")
+            };
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                walk(&entry.path(), &key, keys)?;
+            } else {
+                keys.push((key, metadata.len()));
+            }
+        }
+        Ok(())
     }
 }
 
@@ -177,6 +378,51 @@ mod tests {
         assert_eq!(client.full_key("a/b/c"), "backups/a/b/c");
     }
 
+    #[test]
+    fn with_prefix_shares_bucket() {
+        let client = S3Client::new(&dummy_config("a")).unwrap();
+        let other = client.with_prefix("b");
+        assert!(client.shares_bucket_with(&other));
+        assert_eq!(other.full_key("foo"), "b/foo");
+
+        let separate = S3Client::new(&dummy_config("a")).unwrap();
+        assert!(!client.shares_bucket_with(&separate));
+    }
+
+    #[tokio::test]
+    async fn directory_backend_round_trip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let config = S3Config::from_url(&format!("file://{}", tmp.path().display())).unwrap();
+        let client = S3Client::new(&config).unwrap().with_prefix("app");
+
+        client.put_object("latest", b"g1").await.unwrap();
+        client.put_object("g1/manifest.json", b"{}").await.unwrap();
+        client.put_object("g1/wal/00000001", b"wal").await.unwrap();
+        client.put_object("g10/snapshot", b"db").await.unwrap();
+        assert_eq!(client.get_object("latest").await.unwrap(), b"g1");
+        assert!(tmp.path().join("app/g1/wal/00000001").is_file());
+
+        assert_eq!(
+            client.list_keys("g1/").await.unwrap(),
+            ["g1/manifest.json", "g1/wal/00000001"]
+        );
+        assert_eq!(client.list_keys("g1").await.unwrap().len(), 3);
+        assert_eq!(client.list_keys("").await.unwrap().len(), 4);
+        assert!(client.list_keys("missing/").await.unwrap().is_empty());
+        assert_eq!(
+            client.list_objects("g10/").await.unwrap(),
+            [("g10/snapshot".to_string(), 2)]
+        );
+
+        client
+            .delete_objects(&["g1/manifest.json".into(), "g1/wal/00000001".into()])
+            .await
+            .unwrap();
+        client.delete_object("g1/manifest.json").await.unwrap();
+        assert!(!tmp.path().join("app/g1").exists());
+        assert!(client.get_object("g1/manifest.json").await.is_err());
+    }
+
     #[test]
     fn full_key_without_prefix() {
         let client = S3Client::new(&dummy_config("")).unwrap();
@@ -0,0 +1,186 @@
+//! Integration with sqlx `SqlitePool`s.
+//!
+//! ```ignore
+//! let notifier = SyncNotifier::default();
+//! let pool = sqlite_pool::pool_options(&notifier)
+//!     .connect_with(sqlite_pool::connect_options("app.db"))
+//!     .await?;
+//! sqlite_pool::check_pool(&pool).await?;
+//!
+//! let mut mgr = BackupManager::new(config).await?;
+//! mgr.set_sync_notifier(notifier);
+//! let handle = mgr.spawn();
+//! ```
+
+use std::path::Path;
+use std::time::Duration;
+
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
+use sqlx::{Connection, SqliteConnection};
+
+use crate::error::{Error, Result};
+use crate::trigger::SyncNotifier;
+
+fn sqlx_error(e: sqlx::Error) -> Error {
+    Error::Other(format!("sqlx: {e}"))
+}
+
+/// Connect options with the PRAGMAs waloy requires: WAL journal mode,
+/// `wal_autocheckpoint = 0` and a 5 second busy timeout. Creates the database
+/// if it is missing.
+pub fn connect_options(db_path: impl AsRef<Path>) -> SqliteConnectOptions {
+    SqliteConnectOptions::new()
+        .filename(db_path)
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .busy_timeout(Duration::from_secs(5))
+        .pragma("wal_autocheckpoint", "0")
+}
+
+/// Pool options whose `after_connect` hook installs a commit hook on every new
+/// connection, so commits through the pool signal `notifier`.
+pub fn pool_options(notifier: &SyncNotifier) -> SqlitePoolOptions {
+    let notifier = notifier.clone();
+    SqlitePoolOptions::new().after_connect(move |conn, _meta| {
+        let notifier = notifier.clone();
+        Box::pin(async move { install(conn, notifier).await })
+    })
+}
+
+/// Install a commit hook signalling `notifier` on one sqlx connection.
+pub async fn install(conn: &mut SqliteConnection, notifier: SyncNotifier) -> sqlx::Result<()> {
+    conn.lock_handle().await?.set_commit_hook(move || {
+        notifier.notify();
+        // Unlike rusqlite, sqlx rolls back when the hook returns false.
+        true
+    });
+    Ok(())
+}
+
+/// Refuse to replicate if the pool's connections are not in WAL mode or have
+/// auto-checkpointing enabled, which would let SQLite checkpoint frames
+/// before they are uploaded. Checks every idle connection, or one freshly
+/// acquired connection if none is idle.
+pub async fn check_pool(pool: &SqlitePool) -> Result<()> {
+    let mut conns = Vec::new();
+    while let Some(conn) = pool.try_acquire() {
+        conns.push(conn);
+    }
+    if conns.is_empty() {
+        conns.push(pool.acquire().await.map_err(sqlx_error)?);
+    }
+    for conn in &mut conns {
+        check_connection(conn).await?;
+    }
+    Ok(())
+}
+
+/// Check the PRAGMAs of a single connection. See [`check_pool`].
+pub async fn check_connection(conn: &mut SqliteConnection) -> Result<()> {
+    let journal_mode: String = sqlx::query_scalar("PRAGMA journal_mode")
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(sqlx_error)?;
+    if !journal_mode.eq_ignore_ascii_case("wal") {
+        return Err(Error::Other(format!(
+            "pool connection uses journal_mode = {journal_mode}, expected wal"
+        )));
+    }
+    let autocheckpoint: i64 = sqlx::query_scalar("PRAGMA wal_autocheckpoint")
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(sqlx_error)?;
+    if autocheckpoint != 0 {
+        return Err(Error::Other(format!(
+            "pool connection has wal_autocheckpoint = {autocheckpoint}; set it to 0 so only waloy checkpoints"
+        )));
+    }
+    conn.ping().await.map_err(sqlx_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BackupConfig;
+    use crate::trigger::{SyncTrigger, TriggerSource};
+
+    #[tokio::test]
+    async fn preset_pool_passes_check() {
+        let tmp = tempfile::tempdir().unwrap();
+        let pool = pool_options(&SyncNotifier::default())
+            .connect_with(connect_options(tmp.path().join("app.db")))
+            .await
+            .unwrap();
+        check_pool(&pool).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn autocheckpoint_is_rejected() {
+        let tmp = tempfile::tempdir().unwrap();
+        let options =
+            connect_options(tmp.path().join("app.db")).pragma("wal_autocheckpoint", "1000");
+        let pool = SqlitePoolOptions::new()
+            .connect_with(options)
+            .await
+            .unwrap();
+        let err = check_pool(&pool).await.unwrap_err();
+        assert!(
+            err.to_string().contains("wal_autocheckpoint = 1000"),
+            "got: {err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn rollback_journal_is_rejected() {
+        let tmp = tempfile::tempdir().unwrap();
+        let options =
+            connect_options(tmp.path().join("app.db")).journal_mode(SqliteJournalMode::Delete);
+        let pool = SqlitePoolOptions::new()
+            .connect_with(options)
+            .await
+            .unwrap();
+        let err = check_pool(&pool).await.unwrap_err();
+        assert!(
+            err.to_string().contains("journal_mode = delete"),
+            "got: {err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn pool_commit_signals_notifier() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("app.db");
+        let config = BackupConfig {
+            db_path: db_path.to_str().unwrap().to_string(),
+            sync_interval: Duration::from_secs(3600),
+            ..Default::default()
+        };
+        let notifier = SyncNotifier::default();
+        let mut source = TriggerSource::new(&config, &notifier);
+
+        let pool = pool_options(&notifier)
+            .connect_with(connect_options(&db_path))
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE t (x INTEGER)")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let trigger = tokio::time::timeout(Duration::from_secs(5), source.next())
+            .await
+            .expect("commit should trigger");
+        assert_eq!(trigger, SyncTrigger::Commit);
+
+        // The hook lets commits through.
+        sqlx::query("INSERT INTO t VALUES (1)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM t")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+}
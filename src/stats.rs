@@ -1,17 +1,54 @@
 use std::time::Instant;
 
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::manager::now_ms;
+use crate::trigger::SyncTrigger;
+
+/// The most recent sync failure.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LastError {
+    /// `sqlite`, `io`, `s3` or `other`.
+    pub kind: &'static str,
+    pub message: String,
+    /// When it happened, in milliseconds since the Unix epoch.
+    pub at_ms: u64,
+}
+
 /// Public snapshot of backup statistics.
-#[derive(Clone, Debug)]
+///
+/// Serializes with wall-clock timestamps (`*_at_ms`, milliseconds since the
+/// Unix epoch); the `Instant` fields are only meaningful inside the process
+/// and are skipped.
+#[derive(Clone, Debug, Serialize)]
 pub struct BackupStats {
     pub generation: String,
     pub generation_count: u64,
     pub wal_offset: u64,
     pub wal_index: u32,
+    #[serde(skip)]
     pub last_sync_time: Option<Instant>,
+    #[serde(skip)]
     pub last_snapshot_time: Option<Instant>,
+    pub last_sync_at_ms: Option<u64>,
+    pub last_snapshot_at_ms: Option<u64>,
+    pub last_checkpoint_at_ms: Option<u64>,
     pub total_bytes_uploaded: u64,
     pub sync_count: u64,
     pub error_count: u64,
+    pub last_error: Option<LastError>,
+    /// Current size of the `-wal` file.
+    pub wal_size: u64,
+    /// WAL bytes written but not yet uploaded.
+    pub pending_wal_bytes: u64,
+    /// Bytes before compression and encryption divided by bytes uploaded.
+    /// `None` until something was uploaded.
+    pub compression_ratio: Option<f64>,
+    /// What triggered the most recent upload from the sync loop.
+    pub last_sync_trigger: Option<SyncTrigger>,
+    /// Whether the sync loop is driven by filesystem notifications.
+    pub watching: bool,
 }
 
 /// Internal mutable tracker updated by BackupManager operations.
@@ -19,9 +56,23 @@ pub(crate) struct StatsTracker {
     pub generation_count: u64,
     pub last_sync_time: Option<Instant>,
     pub last_snapshot_time: Option<Instant>,
+    pub last_sync_at_ms: Option<u64>,
+    pub last_snapshot_at_ms: Option<u64>,
+    pub last_checkpoint_at_ms: Option<u64>,
     pub total_bytes_uploaded: u64,
+    /// Bytes uploaded, measured before compression and encryption.
+    pub total_raw_bytes: u64,
     pub sync_count: u64,
     pub error_count: u64,
+    pub last_error: Option<LastError>,
+    /// Failed syncs since the last successful one.
+    pub consecutive_errors: u32,
+    /// When `sync_wal` last succeeded, whether or not it uploaded data.
+    pub last_success_time: Option<Instant>,
+    pub last_sync_trigger: Option<SyncTrigger>,
+    pub watching: bool,
+    #[cfg(feature = "metrics")]
+    pub metrics: crate::metrics::Metrics,
 }
 
 impl StatsTracker {
@@ -30,29 +81,72 @@ impl StatsTracker {
             generation_count: 1,
             last_sync_time: None,
             last_snapshot_time: None,
+            last_sync_at_ms: None,
+            last_snapshot_at_ms: None,
+            last_checkpoint_at_ms: None,
             total_bytes_uploaded: 0,
+            total_raw_bytes: 0,
             sync_count: 0,
             error_count: 0,
+            last_error: None,
+            consecutive_errors: 0,
+            last_success_time: None,
+            last_sync_trigger: None,
+            watching: false,
+            #[cfg(feature = "metrics")]
+            metrics: crate::metrics::Metrics::new(),
         }
     }
 
     pub fn record_snapshot(&mut self, bytes: u64) {
         self.last_snapshot_time = Some(Instant::now());
+        self.last_snapshot_at_ms = Some(now_ms());
         self.total_bytes_uploaded += bytes;
     }
 
     pub fn record_sync(&mut self, bytes: u64) {
         self.last_sync_time = Some(Instant::now());
+        self.last_sync_at_ms = Some(now_ms());
         self.total_bytes_uploaded += bytes;
         self.sync_count += 1;
     }
 
+    /// Count the size of uploaded data before compression and encryption.
+    pub fn record_raw_bytes(&mut self, bytes: u64) {
+        self.total_raw_bytes += bytes;
+    }
+
+    pub fn record_checkpoint(&mut self) {
+        self.last_checkpoint_at_ms = Some(now_ms());
+    }
+
+    pub fn compression_ratio(&self) -> Option<f64> {
+        (self.total_bytes_uploaded > 0)
+            .then(|| self.total_raw_bytes as f64 / self.total_bytes_uploaded as f64)
+    }
+
     pub fn record_new_generation(&mut self) {
         self.generation_count += 1;
     }
 
-    pub fn record_error(&mut self) {
+    pub fn record_error(&mut self, error: &Error) {
+        self.last_error = Some(LastError {
+            kind: error.kind(),
+            message: error.to_string(),
+            at_ms: now_ms(),
+        });
         self.error_count += 1;
+        self.consecutive_errors = self.consecutive_errors.saturating_add(1);
+    }
+
+    /// A sync succeeded, whether or not it uploaded data.
+    pub fn record_success(&mut self) {
+        self.consecutive_errors = 0;
+        self.last_success_time = Some(Instant::now());
+    }
+
+    pub fn record_trigger(&mut self, trigger: SyncTrigger) {
+        self.last_sync_trigger = Some(trigger);
     }
 }
 
@@ -69,6 +163,10 @@ mod tests {
         assert_eq!(t.total_bytes_uploaded, 0);
         assert_eq!(t.sync_count, 0);
         assert_eq!(t.error_count, 0);
+        assert!(t.last_sync_trigger.is_none());
+        assert!(!t.watching);
+        assert!(t.last_error.is_none());
+        assert!(t.compression_ratio().is_none());
     }
 
     #[test]
@@ -109,12 +207,51 @@ mod tests {
     fn record_error_increments() {
         let mut t = StatsTracker::new();
         assert_eq!(t.error_count, 0);
-        t.record_error();
+        t.record_error(&Error::Other("boom".into()));
         assert_eq!(t.error_count, 1);
-        t.record_error();
+        t.record_error(&Error::Other("boom".into()));
         assert_eq!(t.error_count, 2);
     }
 
+    #[test]
+    fn record_error_keeps_last_error() {
+        let mut t = StatsTracker::new();
+        t.record_error(&Error::S3("timeout".into()));
+        let err = t.last_error.clone().unwrap();
+        assert_eq!(err.kind, "s3");
+        assert_eq!(err.message, "s3: timeout");
+        assert!(err.at_ms > 0);
+    }
+
+    #[test]
+    fn compression_ratio_from_raw_and_uploaded_bytes() {
+        let mut t = StatsTracker::new();
+        t.record_raw_bytes(4000);
+        t.record_sync(1000);
+        assert_eq!(t.compression_ratio(), Some(4.0));
+        assert!(t.last_sync_at_ms.is_some());
+    }
+
+    #[test]
+    fn record_success_resets_consecutive_errors() {
+        let mut t = StatsTracker::new();
+        t.record_error(&Error::Other("boom".into()));
+        t.record_error(&Error::Other("boom".into()));
+        assert_eq!(t.consecutive_errors, 2);
+        t.record_success();
+        assert_eq!(t.consecutive_errors, 0);
+        assert_eq!(t.error_count, 2);
+        assert!(t.last_success_time.is_some());
+    }
+
+    #[test]
+    fn record_trigger_keeps_latest() {
+        let mut t = StatsTracker::new();
+        t.record_trigger(SyncTrigger::Poll);
+        t.record_trigger(SyncTrigger::FileChange);
+        assert_eq!(t.last_sync_trigger, Some(SyncTrigger::FileChange));
+    }
+
     #[test]
     fn record_zero_byte_snapshot() {
         let mut t = StatsTracker::new();
@@ -0,0 +1,352 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rusqlite::Connection;
+use tokio::sync::Notify;
+
+use crate::config::BackupConfig;
+use crate::error::Result;
+
+/// What caused a sync in [`BackupManager::run`](crate::BackupManager::run).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncTrigger {
+    /// The `sync_interval` elapsed.
+    Poll,
+    /// A filesystem notification reported a write to the `-wal` file.
+    FileChange,
+    /// [`ReplicationHandle::sync_now`](crate::ReplicationHandle::sync_now) was called.
+    Manual,
+    /// A commit on a connection with a [`SyncNotifier`] installed.
+    ///
+    /// The commit hook fires before SQLite writes the commit to the WAL, so
+    /// the sync waits for the WAL to grow first.
+    Commit,
+}
+
+/// Wakes the sync loop of a [`BackupManager`](crate::BackupManager) when the
+/// application commits, obtained from
+/// [`BackupManager::sync_notifier`](crate::BackupManager::sync_notifier).
+///
+/// Commits that arrive while a sync is running are coalesced into one more sync.
+#[derive(Clone, Debug, Default)]
+pub struct SyncNotifier {
+    notify: Arc<Notify>,
+}
+
+impl SyncNotifier {
+    /// Install a commit hook on one of the application's connections, so that
+    /// every commit on it triggers a sync. Replaces any commit hook already set
+    /// on the connection.
+    ///
+    /// With a connection pool, call this from the pool's connection customizer
+    /// (e.g. `r2d2::CustomizeConnection::on_acquire`) so every pooled
+    /// connection notifies the replication task.
+    pub fn install(&self, conn: &Connection) -> Result<()> {
+        let notifier = self.clone();
+        conn.commit_hook(Some(move || {
+            notifier.notify();
+            // Returning false lets the commit proceed.
+            false
+        }))?;
+        Ok(())
+    }
+
+    /// Remove the commit hook installed by [`install`](Self::install).
+    pub fn uninstall(conn: &Connection) -> Result<()> {
+        conn.commit_hook(None::<fn() -> bool>)?;
+        Ok(())
+    }
+
+    /// Signal a commit made outside a hooked connection.
+    pub fn notify(&self) {
+        self.notify.notify_one();
+    }
+}
+
+/// Produces sync triggers: polling at `sync_interval`, or filesystem
+/// notifications plus slower fallback polling when a watcher is configured
+/// and available.
+pub(crate) struct TriggerSource {
+    poll_interval: Duration,
+    commits: Arc<Notify>,
+    #[cfg(feature = "watch")]
+    watcher: Option<watcher::WalWatcher>,
+}
+
+impl TriggerSource {
+    pub fn new(config: &BackupConfig, notifier: &SyncNotifier) -> Self {
+        Self {
+            poll_interval: config.sync_interval,
+            commits: notifier.notify.clone(),
+            #[cfg(feature = "watch")]
+            watcher: config.watch.as_ref().and_then(|watch| {
+                match watcher::WalWatcher::new(&config.db_path, watch) {
+                    Ok(w) => Some(w),
+                    Err(e) => {
+                        tracing::warn!(error = %e, "WAL watcher unavailable, falling back to polling");
+                        None
+                    }
+                }
+            }),
+        }
+    }
+
+    /// Whether filesystem notifications are driving syncs.
+    pub fn is_watching(&self) -> bool {
+        #[cfg(feature = "watch")]
+        {
+            self.watcher.is_some()
+        }
+        #[cfg(not(feature = "watch"))]
+        {
+            false
+        }
+    }
+
+    /// Wait until the next sync is due.
+    pub async fn next(&mut self) -> SyncTrigger {
+        let commits = self.commits.clone();
+        tokio::select! {
+            _ = commits.notified() => SyncTrigger::Commit,
+            trigger = self.next_scheduled() => trigger,
+        }
+    }
+
+    /// Wait for a filesystem notification or the poll interval, whichever
+    /// comes first. While watching, polling slows down to the watcher's
+    /// `fallback_poll_interval`, so an idle database still reaches scheduled
+    /// snapshots and a missed notification is eventually picked up.
+    async fn next_scheduled(&mut self) -> SyncTrigger {
+        #[cfg(feature = "watch")]
+        if let Some(w) = &mut self.watcher {
+            let fallback = w.fallback_poll_interval();
+            tokio::select! {
+                trigger = w.next() => match trigger {
+                    Some(trigger) => return trigger,
+                    None => {
+                        tracing::warn!("WAL watcher stopped, falling back to polling");
+                        self.watcher = None;
+                    }
+                },
+                _ = tokio::time::sleep(fallback) => return SyncTrigger::Poll,
+            }
+        }
+        tokio::time::sleep(self.poll_interval).await;
+        SyncTrigger::Poll
+    }
+}
+
+#[cfg(feature = "watch")]
+mod watcher {
+    use std::ffi::OsString;
+    use std::path::Path;
+    use std::time::Duration;
+
+    use notify::event::ModifyKind;
+    use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+    use tokio::sync::mpsc;
+    use tokio::time::Instant;
+
+    use super::SyncTrigger;
+    use crate::config::WatchConfig;
+    use crate::error::{Error, Result};
+
+    /// Watches the directory holding the `-wal` file, since SQLite may create
+    /// and remove the file itself.
+    pub(super) struct WalWatcher {
+        _watcher: RecommendedWatcher,
+        events: mpsc::UnboundedReceiver<()>,
+        config: WatchConfig,
+    }
+
+    impl WalWatcher {
+        pub fn new(db_path: &str, config: &WatchConfig) -> Result<Self> {
+            let wal_path = format!("{db_path}-wal");
+            let wal_path = Path::new(&wal_path);
+            let dir = match wal_path.parent() {
+                Some(p) if !p.as_os_str().is_empty() => p,
+                _ => Path::new("."),
+            };
+            let wal_name: OsString = wal_path
+                .file_name()
+                .map(|n| n.to_owned())
+                .ok_or_else(|| Error::Other(format!("invalid database path: {db_path}")))?;
+
+            let (tx, events) = mpsc::unbounded_channel();
+            let mut watcher =
+                notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+                    let Ok(event) = res else { return };
+                    // Our own reads of the WAL show up as access events; only writes matter.
+                    let is_write = matches!(
+                        event.kind,
+                        EventKind::Create(_)
+                            | EventKind::Modify(ModifyKind::Data(_))
+                            | EventKind::Modify(ModifyKind::Any)
+                    );
+                    if is_write && event.paths.iter().any(|p| p.file_name() == Some(&wal_name)) {
+                        let _ = tx.send(());
+                    }
+                })
+                .map_err(|e| Error::Other(format!("watcher: {e}")))?;
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(|e| Error::Other(format!("watcher: {e}")))?;
+
+            Ok(Self {
+                _watcher: watcher,
+                events,
+                config: config.clone(),
+            })
+        }
+
+        /// How often to poll while the watcher runs.
+        pub fn fallback_poll_interval(&self) -> Duration {
+            self.config.fallback_poll_interval
+        }
+
+        /// Wait for a write, then for `debounce` of quiet (but no longer than
+        /// `max_latency` after the first write). Returns `None` if the watcher stopped.
+        pub async fn next(&mut self) -> Option<SyncTrigger> {
+            self.events.recv().await?;
+            let first = Instant::now();
+            let mut last = first;
+            loop {
+                let deadline = (last + self.config.debounce).min(first + self.config.max_latency);
+                tokio::select! {
+                    event = self.events.recv() => {
+                        event?;
+                        last = Instant::now();
+                    }
+                    _ = tokio::time::sleep_until(deadline) => return Some(SyncTrigger::FileChange),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn polls_without_watch_config() {
+        let config = BackupConfig {
+            sync_interval: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let mut source = TriggerSource::new(&config, &SyncNotifier::default());
+        assert!(!source.is_watching());
+        assert_eq!(source.next().await, SyncTrigger::Poll);
+    }
+
+    #[tokio::test]
+    async fn commit_hook_triggers_sync() {
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("app.db");
+        let config = BackupConfig {
+            db_path: db_path.to_str().unwrap().to_string(),
+            sync_interval: Duration::from_secs(3600),
+            ..Default::default()
+        };
+        let notifier = SyncNotifier::default();
+        let mut source = TriggerSource::new(&config, &notifier);
+
+        let conn = Connection::open(&db_path).unwrap();
+        notifier.install(&conn).unwrap();
+        conn.execute_batch("CREATE TABLE t (x INTEGER)").unwrap();
+
+        let trigger = tokio::time::timeout(Duration::from_secs(5), source.next())
+            .await
+            .expect("commit should trigger");
+        assert_eq!(trigger, SyncTrigger::Commit);
+
+        // Without the hook, commits no longer notify.
+        SyncNotifier::uninstall(&conn).unwrap();
+        conn.execute("INSERT INTO t VALUES (1)", []).unwrap();
+        let next = tokio::time::timeout(Duration::from_millis(100), source.next()).await;
+        assert!(next.is_err());
+    }
+
+    #[cfg(feature = "watch")]
+    #[tokio::test]
+    async fn wal_write_triggers_file_change() {
+        use crate::config::WatchConfig;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("app.db");
+        let db_path = db_path.to_str().unwrap().to_string();
+        let config = BackupConfig {
+            db_path: db_path.clone(),
+            sync_interval: Duration::from_secs(3600),
+            watch: Some(WatchConfig {
+                debounce: Duration::from_millis(20),
+                max_latency: Duration::from_millis(200),
+                fallback_poll_interval: Duration::from_secs(3600),
+            }),
+            ..Default::default()
+        };
+        let mut source = TriggerSource::new(&config, &SyncNotifier::default());
+        assert!(source.is_watching());
+
+        // Unrelated files in the same directory are ignored.
+        std::fs::write(tmp.path().join("other.txt"), b"x").unwrap();
+        std::fs::write(format!("{db_path}-wal"), b"frames").unwrap();
+
+        let trigger = tokio::time::timeout(Duration::from_secs(5), source.next())
+            .await
+            .expect("watcher should fire");
+        assert_eq!(trigger, SyncTrigger::FileChange);
+    }
+
+    #[cfg(feature = "watch")]
+    #[tokio::test]
+    async fn idle_watcher_still_polls() {
+        use crate::config::WatchConfig;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let db_path = tmp.path().join("app.db").to_str().unwrap().to_string();
+        let config = BackupConfig {
+            db_path: db_path.clone(),
+            sync_interval: Duration::from_secs(3600),
+            watch: Some(WatchConfig {
+                fallback_poll_interval: Duration::from_millis(10),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut source = TriggerSource::new(&config, &SyncNotifier::default());
+        assert!(source.is_watching());
+        let trigger = tokio::time::timeout(Duration::from_secs(5), source.next())
+            .await
+            .expect("fallback poll interval should fire");
+        assert_eq!(trigger, SyncTrigger::Poll);
+
+        // While watching, `sync_interval` no longer drives polling.
+        let config = BackupConfig {
+            db_path,
+            sync_interval: Duration::from_millis(10),
+            watch: Some(WatchConfig::default()),
+            ..Default::default()
+        };
+        let mut source = TriggerSource::new(&config, &SyncNotifier::default());
+        let next = tokio::time::timeout(Duration::from_millis(100), source.next()).await;
+        assert!(next.is_err());
+    }
+
+    #[cfg(feature = "watch")]
+    #[tokio::test]
+    async fn missing_directory_falls_back_to_polling() {
+        use crate::config::WatchConfig;
+
+        let config = BackupConfig {
+            db_path: "/nonexistent-waloy-dir/app.db".into(),
+            sync_interval: Duration::from_millis(10),
+            watch: Some(WatchConfig::default()),
+            ..Default::default()
+        };
+        let mut source = TriggerSource::new(&config, &SyncNotifier::default());
+        assert!(!source.is_watching());
+        assert_eq!(source.next().await, SyncTrigger::Poll);
+    }
+}
@@ -0,0 +1,215 @@
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::manager::{WAL_FRAME_HEADER_SIZE, WAL_HEADER_SIZE};
+
+/// WAL magic numbers; the low bit selects big-endian checksums.
+const WAL_MAGIC_LE: u32 = 0x377f_0682;
+const WAL_MAGIC_BE: u32 = 0x377f_0683;
+
+/// The 32-byte header at the start of a WAL file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct WalHeader {
+    pub magic: u32,
+    pub format_version: u32,
+    pub page_size: u32,
+    pub checkpoint_seq: u32,
+    pub salt: [u32; 2],
+    pub checksum: [u32; 2],
+    /// Whether `checksum` matches the first 24 bytes of the header.
+    pub checksum_valid: bool,
+}
+
+impl WalHeader {
+    /// Whether checksums are computed over big-endian words.
+    pub fn big_endian_checksums(&self) -> bool {
+        self.magic == WAL_MAGIC_BE
+    }
+}
+
+/// The header of one frame, i.e. one page written to the WAL.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct WalFrame {
+    /// Frame number, counting from 1 as SQLite does.
+    pub frame: u32,
+    /// Byte offset of the frame header in the WAL.
+    pub offset: u64,
+    pub page_number: u32,
+    /// Database size in pages for the last frame of a transaction, else 0.
+    pub commit_size: u32,
+    pub salt: [u32; 2],
+    pub checksum: [u32; 2],
+    /// Whether `salt` matches the WAL header.
+    pub salt_valid: bool,
+    /// Whether `checksum` matches the frame, chained from the previous
+    /// frame's stored checksum.
+    pub checksum_valid: bool,
+}
+
+impl WalFrame {
+    /// Whether SQLite would replay this frame, as far as this frame alone
+    /// tells. Frames after an invalid one are never replayed.
+    pub fn is_valid(&self) -> bool {
+        self.salt_valid && self.checksum_valid
+    }
+}
+
+/// A decoded WAL: its header and the header of every complete frame.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct WalFile {
+    pub header: WalHeader,
+    pub frames: Vec<WalFrame>,
+    /// Bytes after the last complete frame.
+    pub trailing_bytes: u64,
+}
+
+impl WalFile {
+    /// Decode the header and frame headers of WAL file contents, checking
+    /// salts and checksums.
+    ///
+    /// Each frame's checksum is chained from the checksum stored in the
+    /// previous frame rather than a recomputed one, so a corrupted frame
+    /// does not make every following frame look corrupted too.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if (data.len() as u64) < WAL_HEADER_SIZE {
+            return Err(Error::Other(format!(
+                "WAL is {} bytes, shorter than its header",
+                data.len()
+            )));
+        }
+        let magic = read_u32(data, 0);
+        if magic != WAL_MAGIC_LE && magic != WAL_MAGIC_BE {
+            return Err(Error::Other(format!("not a WAL: bad magic {magic:#010x}")));
+        }
+        let big_endian = magic == WAL_MAGIC_BE;
+        let page_size = read_u32(data, 8);
+        if !(512..=65536).contains(&page_size) || !page_size.is_power_of_two() {
+            return Err(Error::Other(format!("invalid WAL page size {page_size}")));
+        }
+        let checksum = [read_u32(data, 24), read_u32(data, 28)];
+        let header = WalHeader {
+            magic,
+            format_version: read_u32(data, 4),
+            page_size,
+            checkpoint_seq: read_u32(data, 12),
+            salt: [read_u32(data, 16), read_u32(data, 20)],
+            checksum,
+            checksum_valid: wal_checksum(big_endian, [0, 0], &data[..24]) == checksum,
+        };
+
+        let frame_size = (WAL_FRAME_HEADER_SIZE + page_size as u64) as usize;
+        let body = &data[WAL_HEADER_SIZE as usize..];
+        let mut previous = checksum;
+        let mut frames = Vec::with_capacity(body.len() / frame_size);
+        for (i, frame) in body.chunks_exact(frame_size).enumerate() {
+            let checksum = [read_u32(frame, 16), read_u32(frame, 20)];
+            let computed = wal_checksum(big_endian, previous, &frame[..8]);
+            let computed = wal_checksum(big_endian, computed, &frame[24..]);
+            let salt = [read_u32(frame, 8), read_u32(frame, 12)];
+            frames.push(WalFrame {
+                frame: i as u32 + 1,
+                offset: WAL_HEADER_SIZE + (i * frame_size) as u64,
+                page_number: read_u32(frame, 0),
+                commit_size: read_u32(frame, 4),
+                salt,
+                checksum,
+                salt_valid: salt == header.salt,
+                checksum_valid: computed == checksum,
+            });
+            previous = checksum;
+        }
+        Ok(Self {
+            header,
+            trailing_bytes: (body.len() % frame_size) as u64,
+            frames,
+        })
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+/// SQLite's WAL checksum over `data` (a multiple of 8 bytes), continuing
+/// from `seed`.
+fn wal_checksum(big_endian: bool, seed: [u32; 2], data: &[u8]) -> [u32; 2] {
+    let word = |bytes: &[u8]| {
+        let bytes = bytes.try_into().unwrap();
+        if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    };
+    let [mut s0, mut s1] = seed;
+    for pair in data.chunks_exact(8) {
+        s0 = s0.wrapping_add(word(&pair[..4])).wrapping_add(s1);
+        s1 = s1.wrapping_add(word(&pair[4..])).wrapping_add(s0);
+    }
+    [s0, s1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    /// The WAL of a database after three committed transactions.
+    fn real_wal() -> Vec<u8> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.db");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA wal_autocheckpoint = 0;
+             CREATE TABLE t (x TEXT);
+             INSERT INTO t VALUES (randomblob(100));
+             INSERT INTO t VALUES (randomblob(100));",
+        )
+        .unwrap();
+        let wal = std::fs::read(dir.path().join("test.db-wal")).unwrap();
+        drop(conn);
+        wal
+    }
+
+    #[test]
+    fn parses_real_wal() {
+        let wal = real_wal();
+        let parsed = WalFile::parse(&wal).unwrap();
+        assert!(parsed.header.checksum_valid);
+        assert_eq!(parsed.header.format_version, 3_007_000);
+        assert_eq!(parsed.header.page_size, 4096);
+        assert_eq!(parsed.trailing_bytes, 0);
+        assert!(parsed.frames.len() >= 3);
+        assert!(parsed.frames.iter().all(WalFrame::is_valid));
+        assert_eq!(parsed.frames[0].offset, WAL_HEADER_SIZE);
+        assert!(parsed.frames.last().unwrap().commit_size > 0);
+        let commits = parsed.frames.iter().filter(|f| f.commit_size > 0).count();
+        assert_eq!(commits, 3);
+    }
+
+    #[test]
+    fn flags_only_the_corrupted_frame() {
+        let mut wal = real_wal();
+        let frame_size = (WAL_FRAME_HEADER_SIZE + 4096) as usize;
+        // A byte of the first frame's page, then the second frame's salt.
+        wal[WAL_HEADER_SIZE as usize + 100] ^= 0xff;
+        wal[WAL_HEADER_SIZE as usize + frame_size + 8] ^= 0xff;
+        wal.extend_from_slice(&[0; 10]);
+
+        let parsed = WalFile::parse(&wal).unwrap();
+        let frames = &parsed.frames;
+        assert!(!frames[0].checksum_valid && frames[0].salt_valid);
+        // Salts are not part of the frame checksum.
+        assert!(!frames[1].salt_valid && frames[1].checksum_valid);
+        assert!(frames[2..].iter().all(WalFrame::is_valid));
+        assert_eq!(parsed.trailing_bytes, 10);
+    }
+
+    #[test]
+    fn rejects_non_wal_data() {
+        assert!(WalFile::parse(&[0; 16]).is_err());
+        let err = WalFile::parse(&[0; 64]).unwrap_err();
+        assert!(err.to_string().contains("bad magic"));
+    }
+}
@@ -1,7 +1,11 @@
 use std::env;
 
 use rusqlite::{Connection, params};
-use waloy::{BackupConfig, BackupManager, S3Config};
+use waloy::{
+    BackupConfig, BackupManager, DeltaSnapshotConfig, DirectoryDiscovery, DiscoveryChanges,
+    GenerationReason, HealthStatus, ReplicaSet, ReplicationEvent, S3Config, SnapshotSource,
+    preflight,
+};
 #[cfg(any(feature = "compression-lz4", feature = "compression-zstd"))]
 use waloy::CompressionAlgorithm;
 
@@ -364,6 +368,589 @@ async fn test_compaction() {
     println!("=== test_compaction PASSED ===");
 }
 
+#[tokio::test]
+async fn test_delta_snapshot_restore() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SKIP: S3 env vars not set");
+        return;
+    };
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 500);
+
+    let config = BackupConfig {
+        delta_snapshots: Some(DeltaSnapshotConfig {
+            max_chain_length: 2,
+            max_changed_percent: 50,
+        }),
+        ..test_config(db_path_str.clone(), s3.clone())
+    };
+    let mut mgr = BackupManager::new(config).await.expect("create manager");
+
+    // The first checkpoint moves the 500 rows into the DB file; nearly every
+    // page changed, so this generation gets a full snapshot.
+    mgr.checkpoint().await.expect("checkpoint");
+    let db_size = std::fs::metadata(&db_path).expect("db metadata").len();
+
+    // Small changes afterwards produce delta generations on top of that base
+    for batch in 0..2 {
+        insert_rows(&app_conn, 1000 + batch * 10, 5);
+        mgr.sync_wal().await.expect("sync wal");
+        let before = mgr.stats().total_bytes_uploaded;
+        mgr.checkpoint().await.expect("checkpoint");
+        let delta_bytes = mgr.stats().total_bytes_uploaded - before;
+        println!("Delta snapshot {batch}: {delta_bytes} bytes (db: {db_size})");
+        assert!(
+            delta_bytes < db_size,
+            "delta snapshot should upload less than the full database"
+        );
+    }
+
+    insert_rows(&app_conn, 2000, 5);
+    mgr.sync_wal().await.expect("sync wal after deltas");
+
+    let restore_path = tmp.path().join("delta_restored.db");
+    let restore_path_str = restore_path.to_str().unwrap().to_string();
+
+    BackupManager::restore(&s3, &restore_path_str)
+        .await
+        .expect("restore from delta chain");
+
+    let restored_conn = Connection::open(&restore_path_str).expect("open restored");
+    assert_eq!(count_rows(&restored_conn), count_rows(&app_conn));
+    assert_eq!(sum_values(&restored_conn), sum_values(&app_conn));
+
+    mgr.shutdown().await.expect("shutdown");
+
+    println!("=== test_delta_snapshot_restore PASSED ===");
+}
+
+#[tokio::test]
+async fn test_delta_restore_with_unreadable_base_manifest() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+    let replica_dir = tmp.path().join("replica");
+
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 500);
+
+    let s3 = S3Config::from_url(&format!("file://{}", replica_dir.display())).expect("replica url");
+    let config = BackupConfig {
+        delta_snapshots: Some(DeltaSnapshotConfig {
+            max_chain_length: 2,
+            max_changed_percent: 50,
+        }),
+        ..test_config(db_path_str.clone(), s3.clone())
+    };
+    let mut mgr = BackupManager::new(config.clone())
+        .await
+        .expect("create manager");
+    mgr.checkpoint().await.expect("checkpoint");
+    insert_rows(&app_conn, 1000, 5);
+    mgr.sync_wal().await.expect("sync wal");
+    mgr.checkpoint().await.expect("delta checkpoint");
+    mgr.shutdown().await.expect("shutdown");
+
+    // The latest generation is a delta; damage the manifest of its base.
+    let latest = BackupManager::latest_generation_id(&config)
+        .await
+        .expect("latest");
+    let base = BackupManager::read_manifest(&config, &latest)
+        .await
+        .expect("read manifest")
+        .expect("manifest")
+        .base_generation
+        .expect("delta generation");
+    std::fs::write(replica_dir.join(&base).join("manifest.json"), b"garbage")
+        .expect("overwrite manifest");
+
+    let restore_path = tmp.path().join("restored.db");
+    let err = BackupManager::restore(&s3, restore_path.to_str().unwrap())
+        .await
+        .expect_err("restore with unreadable base manifest");
+    assert!(err.to_string().contains("manifest"), "{err}");
+    BackupManager::restore_plan(&config, None)
+        .await
+        .expect_err("restore plan with unreadable base manifest");
+
+    println!("=== test_delta_restore_with_unreadable_base_manifest PASSED ===");
+}
+
+#[tokio::test]
+async fn test_backup_api_snapshot_restore() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SKIP: S3 env vars not set");
+        return;
+    };
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+
+    // Rows stay in the WAL: the raw DB file does not contain them yet
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 20);
+
+    let config = BackupConfig {
+        snapshot_source: SnapshotSource::BackupApi,
+        ..test_config(db_path_str.clone(), s3.clone())
+    };
+    let mut mgr = BackupManager::new(config).await.expect("create manager");
+
+    insert_rows(&app_conn, 21, 10);
+    mgr.sync_wal().await.expect("sync wal");
+
+    let restore_path = tmp.path().join("backup_api_restored.db");
+    let restore_path_str = restore_path.to_str().unwrap().to_string();
+
+    BackupManager::restore(&s3, &restore_path_str)
+        .await
+        .expect("restore");
+
+    let restored_conn = Connection::open(&restore_path_str).expect("open restored");
+    assert_eq!(count_rows(&restored_conn), 30);
+    assert_eq!(sum_values(&restored_conn), sum_values(&app_conn));
+
+    mgr.shutdown().await.expect("shutdown");
+
+    println!("=== test_backup_api_snapshot_restore PASSED ===");
+}
+
+#[tokio::test]
+async fn test_preflight() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SKIP: S3 env vars not set");
+        return;
+    };
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+    let app_conn = create_test_db(&db_path_str);
+
+    let config = test_config(db_path_str.clone(), s3.clone());
+    let report = preflight(&config).await.with_connection(&app_conn);
+    println!("{report}");
+    assert!(report.is_ok(), "unexpected failures:\n{report}");
+    assert_eq!(report.warnings().count(), 0);
+
+    // An application connection with auto-checkpoint enabled is refused.
+    app_conn
+        .execute_batch("PRAGMA wal_autocheckpoint = 1000;")
+        .expect("pragma");
+    let err = preflight(&config)
+        .await
+        .with_connection(&app_conn)
+        .into_result()
+        .unwrap_err();
+    assert!(err.to_string().contains("wal_autocheckpoint"), "got: {err}");
+
+    // A missing database fails unless it will be auto-restored.
+    let mut config = test_config(tmp.path().join("missing.db").to_str().unwrap().into(), s3);
+    let report = preflight(&config).await;
+    let failed: Vec<_> = report.failures().map(|c| c.name).collect();
+    assert_eq!(failed, vec!["database"]);
+    config.auto_restore = true;
+    assert!(preflight(&config).await.is_ok());
+
+    println!("=== test_preflight PASSED ===");
+}
+
+#[tokio::test]
+async fn test_health() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SKIP: S3 env vars not set");
+        return;
+    };
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    let mut config = test_config(db_path_str.clone(), s3.clone());
+    config.health.pending_wal_bytes.degraded = 1;
+    let mut mgr = BackupManager::new(config).await.expect("create manager");
+
+    // Rows committed to the WAL but not yet uploaded.
+    insert_rows(&app_conn, 11, 10);
+    let health = mgr.health();
+    assert!(health.pending_wal_bytes > 0);
+    assert_eq!(health.status, HealthStatus::Degraded, "{health:?}");
+
+    mgr.sync_wal().await.expect("sync wal");
+    let health = mgr.health();
+    assert_eq!(health.pending_wal_bytes, 0);
+    assert_eq!(health.consecutive_errors, 0);
+    assert_eq!(health.status, HealthStatus::Healthy, "{health:?}");
+
+    mgr.shutdown().await.expect("shutdown");
+
+    println!("=== test_health PASSED ===");
+}
+
+#[tokio::test]
+async fn test_stats_serialize() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SKIP: S3 env vars not set");
+        return;
+    };
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    let config = test_config(db_path_str.clone(), s3.clone());
+    let mut mgr = BackupManager::new(config).await.expect("create manager");
+    insert_rows(&app_conn, 11, 10);
+    mgr.sync_wal().await.expect("sync wal");
+    mgr.checkpoint().await.expect("checkpoint");
+
+    let stats = serde_json::to_value(mgr.stats()).expect("serialize stats");
+    println!("{stats:#}");
+    let now_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    for field in [
+        "last_sync_at_ms",
+        "last_snapshot_at_ms",
+        "last_checkpoint_at_ms",
+    ] {
+        let at = stats[field].as_u64().expect(field);
+        assert!(at <= now_ms && now_ms - at < 60_000, "{field} = {at}");
+    }
+    assert!(stats.get("last_sync_time").is_none());
+    assert_eq!(stats["pending_wal_bytes"], 0);
+    assert!(stats["last_error"].is_null());
+    assert!(stats["compression_ratio"].as_f64().unwrap() > 0.0);
+
+    mgr.shutdown().await.expect("shutdown");
+
+    println!("=== test_stats_serialize PASSED ===");
+}
+
+#[tokio::test]
+async fn test_events() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SKIP: S3 env vars not set");
+        return;
+    };
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    let config = test_config(db_path_str.clone(), s3.clone());
+    let mut mgr = BackupManager::new(config).await.expect("create manager");
+    let mut events = mgr.subscribe();
+
+    insert_rows(&app_conn, 11, 10);
+    mgr.sync_wal().await.expect("sync wal");
+    match events.try_recv().expect("segment event") {
+        ReplicationEvent::SegmentUploaded {
+            generation,
+            index,
+            offset,
+            bytes,
+            ..
+        } => {
+            assert_eq!(generation, mgr.generation());
+            assert_eq!(index, 0);
+            assert_eq!(offset, 0);
+            assert!(bytes > 0);
+        }
+        other => panic!("unexpected event {other:?}"),
+    }
+
+    mgr.checkpoint().await.expect("checkpoint");
+    assert_eq!(
+        events.try_recv().expect("generation event"),
+        ReplicationEvent::GenerationStarted {
+            generation: mgr.generation().to_string(),
+            reason: GenerationReason::Checkpoint,
+        }
+    );
+    match events.try_recv().expect("snapshot event") {
+        ReplicationEvent::SnapshotUploaded {
+            generation, delta, ..
+        } => {
+            assert_eq!(generation, mgr.generation());
+            assert!(!delta);
+        }
+        other => panic!("unexpected event {other:?}"),
+    }
+
+    for batch in 0..3 {
+        insert_rows(&app_conn, (batch + 1) * 100, 10);
+        mgr.sync_wal().await.expect("sync wal");
+    }
+    mgr.compact(Some(10 * 1024 * 1024)).await.expect("compact");
+    let last = std::iter::from_fn(|| events.try_recv().ok()).last();
+    assert!(
+        matches!(
+            last,
+            Some(ReplicationEvent::CompactionFinished {
+                segments_before: 3,
+                ..
+            })
+        ),
+        "{last:?}"
+    );
+
+    mgr.shutdown().await.expect("shutdown");
+
+    println!("=== test_events PASSED ===");
+}
+
+#[tokio::test]
+async fn test_wait_replicated() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SKIP: S3 env vars not set");
+        return;
+    };
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    let config = BackupConfig {
+        sync_interval: std::time::Duration::from_secs(3600),
+        ..test_config(db_path_str.clone(), s3.clone())
+    };
+    let handle = BackupManager::new(config)
+        .await
+        .expect("create manager")
+        .spawn();
+
+    insert_rows(&app_conn, 11, 5);
+    let position = handle
+        .wal_position()
+        .expect("wal position")
+        .expect("WAL has frames");
+    assert!(!handle.is_replicated(&position));
+
+    assert!(handle.sync_now().await.expect("sync now"));
+    tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        handle.wait_replicated(position),
+    )
+    .await
+    .expect("replicated in time")
+    .expect("wait replicated");
+
+    // The replicated position is restorable.
+    let restore_path = tmp.path().join("wait_restored.db");
+    let restore_path_str = restore_path.to_str().unwrap().to_string();
+    BackupManager::restore(&s3, &restore_path_str)
+        .await
+        .expect("restore");
+    let restored_conn = Connection::open(&restore_path_str).expect("open restored");
+    assert_eq!(count_rows(&restored_conn), 15);
+
+    handle.shutdown().await.expect("shutdown");
+    assert!(handle.sync_now().await.is_err());
+
+    println!("=== test_wait_replicated PASSED ===");
+}
+
+#[tokio::test]
+async fn test_commit_hook_triggers_sync() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SKIP: S3 env vars not set");
+        return;
+    };
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    // Polling alone would not sync within the test's timeout.
+    let config = BackupConfig {
+        sync_interval: std::time::Duration::from_secs(3600),
+        ..test_config(db_path_str.clone(), s3.clone())
+    };
+    let mgr = BackupManager::new(config).await.expect("create manager");
+    mgr.sync_notifier()
+        .install(&app_conn)
+        .expect("install commit hook");
+    let handle = mgr.spawn();
+
+    insert_rows(&app_conn, 11, 5);
+    let position = handle
+        .wal_position()
+        .expect("wal position")
+        .expect("WAL has frames");
+    tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        handle.wait_replicated(position),
+    )
+    .await
+    .expect("commit should trigger a sync")
+    .expect("wait replicated");
+
+    handle.shutdown().await.expect("shutdown");
+
+    println!("=== test_commit_hook_triggers_sync PASSED ===");
+}
+
+#[tokio::test]
+async fn test_replica_set_multiple_databases() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SKIP: S3 env vars not set");
+        return;
+    };
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let set = ReplicaSet::new(2);
+    let mut conns = Vec::new();
+
+    for (i, name) in ["tenant-a", "tenant-b", "catalog"].iter().enumerate() {
+        let db_path = tmp.path().join(format!("{name}.db"));
+        let db_path_str = db_path.to_str().unwrap().to_string();
+        let app_conn = create_test_db(&db_path_str);
+        insert_rows(&app_conn, 1, 5 * (i as i64 + 1));
+
+        let s3 = S3Config {
+            prefix: format!("{}/{name}", s3.prefix),
+            ..s3.clone()
+        };
+        set.add(test_config(db_path_str.clone(), s3.clone()))
+            .await
+            .expect("add replica");
+        conns.push((db_path_str, s3, app_conn));
+    }
+    assert_eq!(set.db_paths().await.len(), 3);
+
+    // Adding the same database twice is rejected
+    let dup = test_config(conns[0].0.clone(), conns[0].1.clone());
+    assert!(set.add(dup).await.is_err());
+
+    for (_, _, app_conn) in &conns {
+        insert_rows(app_conn, 100, 3);
+    }
+    set.sync_all().await;
+
+    let stats = set.stats().await;
+    assert_eq!(stats.replicas.len(), 3);
+    assert!(stats.sync_count >= 3);
+    assert_eq!(stats.error_count, 0);
+
+    for (db_path, s3, app_conn) in &conns {
+        let restore_path = format!("{db_path}.restored");
+        BackupManager::restore(s3, &restore_path)
+            .await
+            .expect("restore replica");
+        let restored_conn = Connection::open(&restore_path).expect("open restored");
+        assert_eq!(count_rows(&restored_conn), count_rows(app_conn));
+    }
+
+    assert!(set.remove(&conns[2].0).await.expect("remove replica"));
+    assert_eq!(set.db_paths().await.len(), 2);
+
+    // The sync loop picks up new writes and shuts the replicas down at the end
+    let (db_path, s3, app_conn) = &conns[0];
+    insert_rows(app_conn, 200, 4);
+    set.run(tokio::time::sleep(std::time::Duration::from_millis(2500)))
+        .await
+        .expect("run sync loop");
+
+    let restore_path = format!("{db_path}.after-run");
+    BackupManager::restore(s3, &restore_path)
+        .await
+        .expect("restore after run");
+    let restored_conn = Connection::open(&restore_path).expect("open restored");
+    assert_eq!(count_rows(&restored_conn), count_rows(app_conn));
+
+    println!("=== test_replica_set_multiple_databases PASSED ===");
+}
+
+#[tokio::test]
+async fn test_directory_discovery() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SKIP: S3 env vars not set");
+        return;
+    };
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let tenants = tmp.path().join("tenants");
+    std::fs::create_dir(&tenants).expect("create tenants dir");
+    let db_path = |name: &str| tenants.join(format!("{name}.db")).to_str().unwrap().to_string();
+
+    let conn_a = create_test_db(&db_path("acme"));
+    insert_rows(&conn_a, 1, 5);
+    let conn_b = create_test_db(&db_path("globex"));
+    insert_rows(&conn_b, 1, 7);
+
+    let pattern = tenants.join("*.db");
+    let discovery = DirectoryDiscovery::new(
+        pattern.to_str().unwrap(),
+        test_config(String::new(), s3.clone()),
+    );
+    let set = ReplicaSet::new(2);
+
+    let changes = set.discover(&discovery).await.expect("discover");
+    assert_eq!(changes.added, vec![db_path("acme"), db_path("globex")]);
+    assert!(changes.removed.is_empty());
+
+    // A second scan with no changes is a no-op
+    let changes = set.discover(&discovery).await.expect("rescan");
+    assert_eq!(changes, DiscoveryChanges::default());
+
+    // New tenant appears
+    let conn_c = create_test_db(&db_path("initech"));
+    insert_rows(&conn_c, 1, 3);
+    let changes = set.discover(&discovery).await.expect("discover new");
+    assert_eq!(changes.added, vec![db_path("initech")]);
+
+    // Each tenant is replicated under its own derived prefix
+    set.sync_all().await;
+    let tenant_s3 = S3Config {
+        prefix: format!("{}/initech", s3.prefix),
+        ..s3.clone()
+    };
+    let restore_path = tmp.path().join("initech_restored.db");
+    let restore_path_str = restore_path.to_str().unwrap().to_string();
+    BackupManager::restore(&tenant_s3, &restore_path_str)
+        .await
+        .expect("restore discovered tenant");
+    let restored_conn = Connection::open(&restore_path_str).expect("open restored");
+    assert_eq!(count_rows(&restored_conn), 3);
+
+    // Tenant removed: replication is finalized and dropped from the set
+    drop(conn_b);
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{suffix}", db_path("globex")));
+    }
+    let changes = set.discover(&discovery).await.expect("discover removal");
+    assert_eq!(changes.removed, vec![db_path("globex")]);
+    assert_eq!(set.db_paths().await.len(), 2);
+
+    set.shutdown().await.expect("shutdown");
+    drop(conn_a);
+
+    println!("=== test_directory_discovery PASSED ===");
+}
+
 // ---------------------------------------------------------------------------
 // Feature-gated integration tests: compression & encryption
 // ---------------------------------------------------------------------------
@@ -704,3 +1291,334 @@ async fn test_compression_compaction() {
     mgr.shutdown().await.expect("shutdown");
     println!("=== test_compression_compaction PASSED ===");
 }
+
+#[tokio::test]
+async fn test_file_replica() {
+    // A local directory needs no S3 credentials, so this always runs.
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let replica_dir = tmp.path().join("replica");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    let mut s3 =
+        S3Config::from_url(&format!("file://{}", replica_dir.display())).expect("replica url");
+    s3.prefix = "app".into();
+    let mut mgr = BackupManager::new(test_config(db_path_str.clone(), s3.clone()))
+        .await
+        .expect("create manager");
+    insert_rows(&app_conn, 11, 10);
+    mgr.sync_wal().await.expect("sync wal");
+    mgr.checkpoint().await.expect("checkpoint");
+    insert_rows(&app_conn, 21, 10);
+    mgr.sync_wal().await.expect("sync wal after checkpoint");
+    assert_eq!(mgr.generations().await.expect("generations").len(), 2);
+    assert!(replica_dir.join("app/latest").is_file());
+
+    let restore_path = tmp.path().join("restored.db");
+    let restore_path_str = restore_path.to_str().unwrap().to_string();
+    BackupManager::restore(&s3, &restore_path_str)
+        .await
+        .expect("restore");
+    let restored_conn = Connection::open(&restore_path_str).expect("open restored");
+    assert_eq!(count_rows(&restored_conn), 30);
+    assert_eq!(sum_values(&restored_conn), sum_values(&app_conn));
+
+    mgr.shutdown().await.expect("shutdown");
+
+    println!("=== test_file_replica PASSED ===");
+}
+
+#[tokio::test]
+async fn test_restore_specific_generation() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    let s3 = S3Config::from_url(&format!("file://{}", tmp.path().join("replica").display()))
+        .expect("replica url");
+    let config = test_config(db_path_str.clone(), s3);
+    let mut mgr = BackupManager::new(config.clone())
+        .await
+        .expect("create manager");
+    insert_rows(&app_conn, 11, 10);
+    mgr.sync_wal().await.expect("sync wal");
+    mgr.checkpoint().await.expect("checkpoint");
+    insert_rows(&app_conn, 21, 10);
+    mgr.sync_wal().await.expect("sync wal after checkpoint");
+    mgr.shutdown().await.expect("shutdown");
+
+    let generations = BackupManager::list_generation_ids(&config)
+        .await
+        .expect("list generations");
+    let latest = BackupManager::latest_generation_id(&config)
+        .await
+        .expect("latest");
+    let first = generations
+        .iter()
+        .find(|g| **g != latest)
+        .expect("older generation");
+
+    let plan = BackupManager::generation_restore_plan(&config, first, None)
+        .await
+        .expect("plan");
+    assert_eq!(&plan.generation, first);
+    assert_eq!(plan.segments.len(), 1);
+
+    let restore_path = tmp.path().join("restored.db");
+    let restore_path_str = restore_path.to_str().unwrap().to_string();
+    BackupManager::restore_generation_with_config(&config, first, &restore_path_str, None)
+        .await
+        .expect("restore generation");
+    let restored_conn = Connection::open(&restore_path_str).expect("open restored");
+    assert_eq!(count_rows(&restored_conn), 20);
+
+    // The snapshot of a generation must predate the requested time.
+    let err = BackupManager::generation_restore_plan(&config, first, Some(1))
+        .await
+        .expect_err("time before snapshot");
+    assert!(err.to_string().contains("no snapshot before"), "{err}");
+
+    println!("=== test_restore_specific_generation PASSED ===");
+}
+
+#[tokio::test]
+async fn test_prune_and_compact_generation() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    let s3 = S3Config::from_url(&format!("file://{}", tmp.path().join("replica").display()))
+        .expect("replica url");
+    let config = test_config(db_path_str.clone(), s3);
+    let mut mgr = BackupManager::new(config.clone())
+        .await
+        .expect("create manager");
+    let first = mgr.generation().to_string();
+    for start in [11, 21, 31] {
+        insert_rows(&app_conn, start, 10);
+        mgr.sync_wal().await.expect("sync wal");
+    }
+    mgr.checkpoint().await.expect("checkpoint");
+    mgr.checkpoint().await.expect("second checkpoint");
+    insert_rows(&app_conn, 41, 10);
+    mgr.sync_wal().await.expect("sync wal in latest generation");
+    mgr.shutdown().await.expect("shutdown");
+
+    // Offline compaction keeps the data and the segments' upload times.
+    let before = BackupManager::read_manifest(&config, &first)
+        .await
+        .expect("read manifest")
+        .expect("manifest");
+    assert_eq!(before.segments.len(), 3);
+    let result = BackupManager::compact_generation(&config, &first, None)
+        .await
+        .expect("compact");
+    assert_eq!((result.segments_before, result.segments_after), (3, 1));
+    let after = BackupManager::read_manifest(&config, &first)
+        .await
+        .expect("read manifest")
+        .expect("manifest");
+    assert_eq!(after.segments[0].index, 3);
+    assert_eq!(
+        after.segments[0].timestamp_ms,
+        before.segments[2].timestamp_ms
+    );
+    let wal_keys: Vec<String> = BackupManager::list_generation_keys(&config, &first)
+        .await
+        .expect("list keys")
+        .into_iter()
+        .filter(|k| k.contains("/wal/"))
+        .collect();
+    assert_eq!(wal_keys, [format!("{first}/wal/00000003")]);
+    let restore_path = tmp.path().join("compacted.db");
+    let restore_path_str = restore_path.to_str().unwrap().to_string();
+    BackupManager::restore_generation_with_config(&config, &first, &restore_path_str, None)
+        .await
+        .expect("restore compacted generation");
+    let restored_conn = Connection::open(&restore_path_str).expect("open restored");
+    assert_eq!(count_rows(&restored_conn), 40);
+
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    let latest = BackupManager::latest_generation_id(&config)
+        .await
+        .expect("latest");
+    let dry_run = BackupManager::prune(&config, std::time::Duration::ZERO, 0, true, false)
+        .await
+        .expect("dry run");
+    assert_eq!(dry_run.deleted.len(), 2);
+    assert_eq!(dry_run.deleted[0], first);
+    assert_eq!(dry_run.retained, [latest]);
+    assert!(dry_run.objects > 0 && dry_run.bytes > 0);
+    assert_eq!(
+        BackupManager::list_generation_ids(&config)
+            .await
+            .expect("list generations")
+            .len(),
+        3
+    );
+
+    let pruned = BackupManager::prune(&config, std::time::Duration::ZERO, 2, false, false)
+        .await
+        .expect("prune");
+    assert_eq!(pruned.deleted, [first]);
+    assert_eq!(pruned.retained.len(), 2);
+    assert_eq!(
+        BackupManager::list_generation_ids(&config)
+            .await
+            .expect("list generations")
+            .len(),
+        2
+    );
+
+    // A manifest nested below a generation is not a generation of its own.
+    let replica = tmp.path().join("replica");
+    let nested = replica.join("archive").join("old");
+    std::fs::create_dir_all(&nested).expect("create nested dir");
+    std::fs::copy(
+        replica.join(&pruned.retained[0]).join("manifest.json"),
+        nested.join("manifest.json"),
+    )
+    .expect("copy manifest");
+    let pruned = BackupManager::prune(&config, std::time::Duration::ZERO, 0, true, false)
+        .await
+        .expect("prune with nested manifest");
+    assert!(
+        pruned
+            .deleted
+            .iter()
+            .chain(&pruned.retained)
+            .all(|g| !g.contains('/'))
+    );
+
+    // Without the `latest` marker, pruning needs `force`.
+    std::fs::remove_file(replica.join("latest")).expect("remove latest marker");
+    let err = BackupManager::prune(&config, std::time::Duration::ZERO, 0, true, false)
+        .await
+        .expect_err("prune without latest marker");
+    assert!(err.to_string().contains("latest"), "{err}");
+    let forced = BackupManager::prune(&config, std::time::Duration::ZERO, 0, true, true)
+        .await
+        .expect("forced prune");
+    assert_eq!(forced.deleted.len(), 2);
+
+    println!("=== test_prune_and_compact_generation PASSED ===");
+}
+
+#[tokio::test]
+async fn test_pitr_ignores_nested_database() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let replica = format!("file://{}", tmp.path().join("replica").display());
+
+    let parent_path = tmp.path().join("parent.db");
+    let parent_path_str = parent_path.to_str().unwrap().to_string();
+    let parent_conn = create_test_db(&parent_path_str);
+    insert_rows(&parent_conn, 1, 10);
+    let parent_config = test_config(
+        parent_path_str,
+        S3Config::from_url(&replica).expect("replica url"),
+    );
+    let mut parent = BackupManager::new(parent_config.clone())
+        .await
+        .expect("create parent manager");
+    parent.shutdown().await.expect("shutdown parent");
+
+    // Another database stored below the parent's prefix, with a newer snapshot.
+    let child_path = tmp.path().join("child.db");
+    let child_path_str = child_path.to_str().unwrap().to_string();
+    let child_conn = create_test_db(&child_path_str);
+    insert_rows(&child_conn, 1, 3);
+    let mut child_s3 = S3Config::from_url(&replica).expect("replica url");
+    child_s3.prefix = "child".into();
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    let mut child = BackupManager::new(test_config(child_path_str, child_s3))
+        .await
+        .expect("create child manager");
+    child.shutdown().await.expect("shutdown child");
+
+    let restore_path = tmp.path().join("restored.db");
+    let restore_path_str = restore_path.to_str().unwrap().to_string();
+    BackupManager::restore_to_time_with_config(&parent_config, &restore_path_str, u64::MAX)
+        .await
+        .expect("point-in-time restore");
+    let restored_conn = Connection::open(&restore_path_str).expect("open restored");
+    assert_eq!(count_rows(&restored_conn), 10);
+
+    println!("=== test_pitr_ignores_nested_database PASSED ===");
+}
+
+#[tokio::test]
+async fn test_copy_replica() {
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    let replica = |name: &str| {
+        S3Config::from_url(&format!("file://{}", tmp.path().join(name).display()))
+            .expect("replica url")
+    };
+    let from = test_config(db_path_str.clone(), replica("from"));
+    let to = BackupConfig {
+        s3: replica("to"),
+        ..Default::default()
+    };
+    let mut mgr = BackupManager::new(from.clone())
+        .await
+        .expect("create manager");
+    insert_rows(&app_conn, 11, 10);
+    mgr.sync_wal().await.expect("sync wal");
+    mgr.checkpoint().await.expect("checkpoint");
+    insert_rows(&app_conn, 21, 10);
+    mgr.sync_wal().await.expect("sync wal after checkpoint");
+
+    let first = BackupManager::copy_replica(&from, &to, false)
+        .await
+        .expect("copy");
+    assert_eq!(first.generations, 2);
+    assert_eq!(first.objects_skipped, 0);
+    assert!(first.objects_copied > 0 && first.bytes_copied > 0);
+
+    // A second run resumes: only what changed since is written.
+    insert_rows(&app_conn, 31, 10);
+    mgr.sync_wal().await.expect("sync wal before second copy");
+    let second = BackupManager::copy_replica(&from, &to, false)
+        .await
+        .expect("second copy");
+    // The new WAL segment and the manifest that now lists it.
+    assert_eq!(second.objects_copied, 2);
+    assert_eq!(second.objects_skipped, first.objects_copied - 1);
+    mgr.shutdown().await.expect("shutdown");
+
+    let restore_path = tmp.path().join("restored.db");
+    let restore_path_str = restore_path.to_str().unwrap().to_string();
+    BackupManager::restore(&to.s3, &restore_path_str)
+        .await
+        .expect("restore from copy");
+    let restored_conn = Connection::open(&restore_path_str).expect("open restored");
+    assert_eq!(count_rows(&restored_conn), 40);
+
+    let nested = BackupConfig {
+        s3: S3Config {
+            prefix: "nested".into(),
+            ..from.s3.clone()
+        },
+        ..Default::default()
+    };
+    let err = BackupManager::copy_replica(&from, &nested, false)
+        .await
+        .expect_err("copy into the source");
+    assert!(err.to_string().contains("overlapping"), "{err}");
+
+    println!("=== test_copy_replica PASSED ===");
+}
@@ -108,6 +108,8 @@ async fn test_axum_server_with_backup() {
     let app = Router::new()
         .route("/items", get(list_items).post(create_item))
         .with_state(pool.clone());
+    #[cfg(all(feature = "metrics", feature = "axum"))]
+    let app = app.merge(waloy::metrics::router(mgr.clone()));
     let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
     let addr = listener.local_addr().expect("addr");
     tokio::spawn(async move {
@@ -185,6 +187,25 @@ async fn test_axum_server_with_backup() {
     assert_eq!(items.len(), 30);
     println!("Server confirms {} items via GET", items.len());
 
+    #[cfg(all(feature = "metrics", feature = "axum"))]
+    {
+        let metrics = client
+            .get(format!("{base}/metrics"))
+            .send()
+            .await
+            .expect("GET /metrics")
+            .text()
+            .await
+            .expect("metrics body");
+        let db = db_path_str.as_str();
+        assert!(metrics.contains(&format!("waloy_generations_total{{db=\"{db}\"}} 2")));
+        assert!(metrics.contains(&format!(
+            "waloy_checkpoint_duration_seconds_count{{db=\"{db}\"}} 1"
+        )));
+        assert!(metrics.contains("# TYPE waloy_sync_duration_seconds histogram"));
+        println!("Metrics endpoint serves Prometheus text");
+    }
+
     // -- Stop sync, final flush --
     sync_handle.abort();
     {
@@ -231,3 +252,237 @@ async fn test_axum_server_with_backup() {
 
     println!("=== test_axum_server_with_backup PASSED ===");
 }
+
+/// Accepts requests carrying `x-admin-token: secret`.
+#[cfg(feature = "axum")]
+struct AdminToken;
+
+#[cfg(feature = "axum")]
+impl<S: Send + Sync> axum::extract::FromRequestParts<S> for AdminToken {
+    type Rejection = axum::http::StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        match parts.headers.get("x-admin-token") {
+            Some(token) if token == "secret" => Ok(AdminToken),
+            _ => Err(axum::http::StatusCode::UNAUTHORIZED),
+        }
+    }
+}
+
+#[cfg(feature = "axum")]
+#[tokio::test]
+async fn test_admin_router() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SKIP: S3 env vars not set");
+        return;
+    };
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("app.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+
+    let app_conn = Connection::open(&db_path).expect("open db");
+    app_conn
+        .execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA busy_timeout = 5000;
+             PRAGMA wal_autocheckpoint = 0;
+             CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL);",
+        )
+        .expect("setup db");
+
+    let config = BackupConfig {
+        db_path: db_path_str.clone(),
+        s3: s3.clone(),
+        ..Default::default()
+    };
+    let mgr = Arc::new(tokio::sync::Mutex::new(
+        BackupManager::new(config)
+            .await
+            .expect("create backup manager"),
+    ));
+    let first_generation = mgr.lock().await.generation().to_string();
+
+    let app = Router::new().nest("/admin", waloy::admin::router::<AdminToken>(mgr.clone()));
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("addr");
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let client = reqwest::Client::new();
+    let base = format!("http://{addr}/admin");
+    let get = |path: &str| {
+        client
+            .get(format!("{base}{path}"))
+            .header("x-admin-token", "secret")
+    };
+    let post = |path: &str| {
+        client
+            .post(format!("{base}{path}"))
+            .header("x-admin-token", "secret")
+    };
+
+    let resp = client
+        .get(format!("{base}/stats"))
+        .send()
+        .await
+        .expect("GET /stats");
+    assert_eq!(resp.status().as_u16(), 401);
+
+    let stats: serde_json::Value = get("/stats").send().await.unwrap().json().await.unwrap();
+    assert_eq!(stats["generation"], first_generation.as_str());
+
+    let resp = get("/health").send().await.unwrap();
+    assert_eq!(resp.status().as_u16(), 200);
+    let health: serde_json::Value = resp.json().await.unwrap();
+    assert_eq!(health["status"], "healthy");
+
+    for i in 0..5 {
+        app_conn
+            .execute(
+                "INSERT INTO items (name) VALUES (?1)",
+                params![format!("item-{i}")],
+            )
+            .unwrap();
+        mgr.lock().await.sync_wal().await.expect("sync");
+    }
+    let plan: serde_json::Value = get("/restore-plan")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(plan["generation"], first_generation.as_str());
+    assert_eq!(plan["segments"].as_array().unwrap().len(), 5);
+
+    let compacted: serde_json::Value = post("/compact").send().await.unwrap().json().await.unwrap();
+    assert_eq!(compacted["segments_before"], 5);
+
+    let resp = post("/checkpoint").send().await.unwrap();
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_ne!(body["generation"], first_generation.as_str());
+
+    let generations: Vec<serde_json::Value> = get("/generations")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(generations.len(), 2);
+    assert_eq!(generations[0]["generation"], first_generation.as_str());
+
+    let retention: serde_json::Value = post("/retention")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(retention["deleted"], 0);
+
+    mgr.lock().await.shutdown().await.expect("shutdown");
+
+    println!("=== test_admin_router PASSED ===");
+}
+
+#[cfg(feature = "axum")]
+#[tokio::test]
+async fn test_admin_router_with_handle() {
+    // A local replica needs no S3 credentials, so this always runs.
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("app.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+
+    let app_conn = Connection::open(&db_path).expect("open db");
+    app_conn
+        .execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA wal_autocheckpoint = 0;
+             CREATE TABLE items (id INTEGER PRIMARY KEY, name TEXT NOT NULL);",
+        )
+        .expect("setup db");
+
+    let config = BackupConfig {
+        db_path: db_path_str.clone(),
+        s3: S3Config::from_url(&format!("file://{}", tmp.path().join("replica").display()))
+            .expect("replica url"),
+        sync_interval: Duration::from_secs(3600),
+        ..Default::default()
+    };
+    let mgr = BackupManager::new(config)
+        .await
+        .expect("create backup manager");
+    let first_generation = mgr.generation().to_string();
+    let handle = mgr.spawn();
+
+    let app = Router::new().nest(
+        "/admin",
+        waloy::admin::handle_router::<AdminToken>(handle.clone()),
+    );
+    #[cfg(feature = "metrics")]
+    let app = app.merge(waloy::metrics::router(Arc::new(handle.clone())));
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+    let addr = listener.local_addr().expect("addr");
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    let client = reqwest::Client::new();
+    let base = format!("http://{addr}");
+    let stats: serde_json::Value = client
+        .get(format!("{base}/admin/stats"))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["generation"], first_generation.as_str());
+
+    app_conn
+        .execute("INSERT INTO items (name) VALUES ('a')", [])
+        .unwrap();
+    let resp = client
+        .post(format!("{base}/admin/checkpoint"))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 200);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    assert_ne!(body["generation"], first_generation.as_str());
+    assert_eq!(handle.stats().await.expect("stats").generation_count, 2);
+
+    #[cfg(feature = "metrics")]
+    {
+        let metrics = client
+            .get(format!("{base}/metrics"))
+            .send()
+            .await
+            .expect("GET /metrics")
+            .text()
+            .await
+            .expect("metrics body");
+        let db = db_path_str.as_str();
+        assert!(metrics.contains(&format!("waloy_generations_total{{db=\"{db}\"}} 2")));
+    }
+
+    handle.shutdown().await.expect("shutdown");
+    let resp = client
+        .get(format!("{base}/admin/health"))
+        .header("x-admin-token", "secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 500);
+
+    println!("=== test_admin_router_with_handle PASSED ===");
+}
@@ -158,7 +158,7 @@ async fn test_cli_generations_inspect_restore() {
     let restore_path = tmp.path().join("cli_restored.db");
     let restore_path_str = restore_path.to_str().unwrap().to_string();
 
-    let (status, stdout, stderr) = run_cli(&s3, &["restore", "--output", &restore_path_str]);
+    let (status, stdout, stderr) = run_cli(&s3, &["restore", "-o", &restore_path_str]);
     println!("--- restore stdout ---\n{stdout}");
     if !stderr.is_empty() {
         eprintln!("--- restore stderr ---\n{stderr}");
@@ -189,3 +189,801 @@ async fn test_cli_generations_inspect_restore() {
 
     println!("=== test_cli_generations_inspect_restore PASSED ===");
 }
+
+/// Spawn the waloy CLI binary with S3 args, capturing its output.
+fn spawn_cli(s3: &S3Config, args: &[&str]) -> std::process::Child {
+    let bin = env!("CARGO_BIN_EXE_waloy");
+    Command::new(bin)
+        .args([
+            "--endpoint",
+            &s3.endpoint,
+            "--region",
+            &s3.region,
+            "--bucket",
+            &s3.bucket,
+            "--access-key",
+            &s3.access_key,
+            "--secret-key",
+            &s3.secret_key,
+            "--prefix",
+            &s3.prefix,
+        ])
+        .args(args)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("failed to spawn waloy binary")
+}
+
+fn send_signal(child: &std::process::Child, signal: &str) {
+    let status = Command::new("kill")
+        .args([&format!("-{signal}"), &child.id().to_string()])
+        .status()
+        .expect("run kill");
+    assert!(status.success());
+}
+
+#[tokio::test]
+async fn test_cli_replicate() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SKIP: S3 env vars not set");
+        return;
+    };
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    let child = spawn_cli(
+        &s3,
+        &[
+            "replicate",
+            "--db",
+            &db_path_str,
+            "--sync-interval",
+            "100ms",
+        ],
+    );
+    // Let the initial snapshot complete before writing more.
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    insert_rows(&app_conn, 11, 20);
+
+    // SIGTERM right away: the final sync on shutdown must pick up the rows.
+    send_signal(&child, "TERM");
+    let output = child.wait_with_output().expect("wait for replicate");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    println!("--- replicate stdout ---\n{stdout}");
+    assert!(output.status.success(), "waloy replicate failed: {stderr}");
+    assert!(stdout.contains("Replicating"), "{stdout}");
+    assert!(stdout.contains("Shut down after final sync"), "{stdout}");
+
+    let restore_path = tmp.path().join("restored.db");
+    let restore_path_str = restore_path.to_str().unwrap().to_string();
+    BackupManager::restore(&s3, &restore_path_str)
+        .await
+        .expect("restore");
+    let restored_conn = Connection::open(&restore_path_str).expect("open restored db");
+    assert_eq!(count_rows(&restored_conn), 30);
+    assert_eq!(sum_values(&restored_conn), sum_values(&app_conn));
+
+    println!("=== test_cli_replicate PASSED ===");
+}
+
+#[tokio::test]
+async fn test_cli_replicate_exec() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SKIP: S3 env vars not set");
+        return;
+    };
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+    let ready = tmp.path().join("child-started");
+
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    // The child signals that it runs, then exits with 7 on SIGTERM.
+    let exec = format!(
+        "trap 'exit 7' TERM; touch {}; while true; do sleep 0.05; done",
+        ready.display()
+    );
+    let child = spawn_cli(&s3, &["replicate", "--db", &db_path_str, "--exec", &exec]);
+    for _ in 0..100 {
+        if ready.exists() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert!(ready.exists(), "child process did not start");
+    insert_rows(&app_conn, 11, 20);
+
+    // SIGTERM goes to the child; waloy exits with its status after a final sync.
+    send_signal(&child, "TERM");
+    let output = child.wait_with_output().expect("wait for replicate");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    println!("--- replicate stdout ---\n{stdout}");
+    assert_eq!(output.status.code(), Some(7));
+    assert!(stdout.contains("Shut down after final sync"), "{stdout}");
+
+    let restore_path = tmp.path().join("restored.db");
+    let restore_path_str = restore_path.to_str().unwrap().to_string();
+    BackupManager::restore(&s3, &restore_path_str)
+        .await
+        .expect("restore");
+    let restored_conn = Connection::open(&restore_path_str).expect("open restored db");
+    assert_eq!(count_rows(&restored_conn), 30);
+
+    println!("=== test_cli_replicate_exec PASSED ===");
+}
+
+#[tokio::test]
+async fn test_cli_replicate_auto_restore() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SKIP: S3 env vars not set");
+        return;
+    };
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+    let mut mgr = BackupManager::new(test_config(db_path_str.clone(), s3.clone()))
+        .await
+        .expect("create manager");
+    mgr.shutdown().await.expect("shutdown");
+    drop(mgr);
+    drop(app_conn);
+
+    // A fresh host: the database is restored before the child starts.
+    let new_path = tmp.path().join("fresh.db");
+    let new_path_str = new_path.to_str().unwrap().to_string();
+    let exec = format!("test -f {new_path_str} && exit 3");
+    let child = spawn_cli(
+        &s3,
+        &[
+            "replicate",
+            "--db",
+            &new_path_str,
+            "--auto-restore",
+            "--exec",
+            &exec,
+        ],
+    );
+    let output = child.wait_with_output().expect("wait for replicate");
+    assert_eq!(
+        output.status.code(),
+        Some(3),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let restored_conn = Connection::open(&new_path_str).expect("open restored db");
+    assert_eq!(count_rows(&restored_conn), 10);
+
+    println!("=== test_cli_replicate_auto_restore PASSED ===");
+}
+
+#[tokio::test]
+async fn test_cli_config_file() {
+    let Some(s3) = s3_config() else {
+        eprintln!("SKIP: S3 env vars not set");
+        return;
+    };
+
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let app_path = tmp.path().join("app.db");
+    let jobs_path = tmp.path().join("jobs.db");
+    let app_conn = create_test_db(app_path.to_str().unwrap());
+    insert_rows(&app_conn, 1, 10);
+    let jobs_conn = create_test_db(jobs_path.to_str().unwrap());
+    insert_rows(&jobs_conn, 1, 25);
+
+    let config_path = tmp.path().join("waloy.toml");
+    std::fs::write(
+        &config_path,
+        format!(
+            r#"
+[defaults]
+sync_interval = "100ms"
+
+[defaults.replica]
+endpoint = "{endpoint}"
+region = "{region}"
+bucket = "{bucket}"
+access_key = "${{TEST_ACCESS_KEY}}"
+secret_key = "${{TEST_SECRET_KEY}}"
+
+[[databases]]
+path = "{app}"
+replica = {{ prefix = "{prefix}/app" }}
+
+[[databases]]
+path = "{jobs}"
+replica = {{ prefix = "{prefix}/jobs" }}
+"#,
+            endpoint = s3.endpoint,
+            region = s3.region,
+            bucket = s3.bucket,
+            prefix = s3.prefix,
+            app = app_path.display(),
+            jobs = jobs_path.display(),
+        ),
+    )
+    .expect("write config");
+
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_waloy"))
+            .arg("--config")
+            .arg(&config_path)
+            .args(args)
+            .env("TEST_ACCESS_KEY", &s3.access_key)
+            .env("TEST_SECRET_KEY", &s3.secret_key)
+            .output()
+            .expect("failed to execute waloy binary");
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        assert!(output.status.success(), "waloy {args:?} failed: {stderr}");
+        stdout
+    };
+
+    let stdout = run(&["replicate", "--exec", "sleep 0.5"]);
+    assert!(stdout.contains(&format!("{}/app", s3.prefix)), "{stdout}");
+    assert!(stdout.contains(&format!("{}/jobs", s3.prefix)), "{stdout}");
+
+    let stdout = run(&["generations", "--db", jobs_path.to_str().unwrap()]);
+    assert!(stdout.contains("Generations (1)"), "{stdout}");
+
+    for (db, conn) in [(&app_path, &app_conn), (&jobs_path, &jobs_conn)] {
+        let restore_path = tmp.path().join("restored.db");
+        let restore_path_str = restore_path.to_str().unwrap();
+        run(&[
+            "restore",
+            "--db",
+            db.to_str().unwrap(),
+            "--target",
+            restore_path_str,
+        ]);
+        let restored = Connection::open(restore_path_str).expect("open restored db");
+        assert_eq!(count_rows(&restored), count_rows(conn));
+        drop(restored);
+        std::fs::remove_file(&restore_path).expect("remove restored db");
+    }
+
+    // Several databases: restore needs --db to pick one.
+    let output = Command::new(env!("CARGO_BIN_EXE_waloy"))
+        .arg("--config")
+        .arg(&config_path)
+        .args(["restore", "--target", "unused.db"])
+        .env("TEST_ACCESS_KEY", &s3.access_key)
+        .env("TEST_SECRET_KEY", &s3.secret_key)
+        .output()
+        .expect("failed to execute waloy binary");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("choose one with --db"));
+
+    println!("=== test_cli_config_file PASSED ===");
+}
+
+#[tokio::test]
+async fn test_cli_file_replica() {
+    // A local directory needs no S3 credentials, so this always runs.
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 15);
+
+    let replica = format!("file://{}", tmp.path().join("replica").display());
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_waloy"))
+            .env_clear()
+            .env("PATH", env::var("PATH").unwrap_or_default())
+            .args(["--replica", &replica])
+            .args(args)
+            .output()
+            .expect("failed to execute waloy binary");
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        assert!(output.status.success(), "waloy {args:?} failed: {stderr}");
+        stdout
+    };
+
+    let stdout = run(&["replicate", "--db", &db_path_str, "--exec", "true"]);
+    assert!(
+        stdout.contains(&format!("Replicating {db_path_str} to {replica}")),
+        "{stdout}"
+    );
+
+    let restore_path = tmp.path().join("restored.db");
+    let restore_path_str = restore_path.to_str().unwrap();
+    run(&["restore", "--target", restore_path_str]);
+    let restored_conn = Connection::open(restore_path_str).expect("open restored db");
+    assert_eq!(count_rows(&restored_conn), 15);
+
+    // `--timestamp` takes RFC 3339 as well as milliseconds.
+    let pitr_path = tmp.path().join("pitr.db");
+    let pitr_path_str = pitr_path.to_str().unwrap();
+    run(&[
+        "restore",
+        "-o",
+        pitr_path_str,
+        "--timestamp",
+        "2999-01-01T00:00:00Z",
+    ]);
+    let pitr_conn = Connection::open(pitr_path_str).expect("open restored db");
+    assert_eq!(count_rows(&pitr_conn), 15);
+
+    println!("=== test_cli_file_replica PASSED ===");
+}
+
+#[tokio::test]
+async fn test_cli_encrypted_backup() {
+    // A local directory needs no S3 credentials, so this always runs.
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 12);
+
+    let replica = format!("file://{}", tmp.path().join("replica").display());
+    let config = BackupConfig {
+        encryption_key: Some("correct horse battery staple".into()),
+        compression: waloy::CompressionAlgorithm::Zstd,
+        ..test_config(db_path_str, S3Config::from_url(&replica).unwrap())
+    };
+    let mut mgr = BackupManager::new(config).await.expect("create manager");
+    insert_rows(&app_conn, 13, 8);
+    mgr.sync_wal().await.expect("sync wal");
+    mgr.shutdown().await.expect("shutdown");
+
+    let key_file = tmp.path().join("key");
+    std::fs::write(&key_file, "correct horse battery staple\n").expect("write key");
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_waloy"))
+            .env_clear()
+            .env("PATH", env::var("PATH").unwrap_or_default())
+            .args(["--replica", &replica])
+            .args(args)
+            .output()
+            .expect("failed to execute waloy binary")
+    };
+    let stdout = |output: &std::process::Output| {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(output.status.success(), "waloy failed: {stderr}");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    let key_args = ["--encryption-key-file", key_file.to_str().unwrap()];
+    let generations = stdout(&run(&[&key_args[..], &["generations"]].concat()));
+    assert!(generations.contains("segments=1"), "{generations}");
+    let inspect = stdout(&run(&[&key_args[..], &["inspect"]].concat()));
+    assert!(inspect.contains("WAL segments: 1"), "{inspect}");
+
+    let restore_path = tmp.path().join("restored.db");
+    let restore_path_str = restore_path.to_str().unwrap();
+    stdout(&run(&[
+        &key_args[..],
+        &["restore", "--target", restore_path_str],
+    ]
+    .concat()));
+    let restored_conn = Connection::open(restore_path_str).expect("open restored db");
+    assert_eq!(count_rows(&restored_conn), 20);
+
+    let without_key = run(&["inspect"]);
+    assert!(!without_key.status.success());
+    let stderr = String::from_utf8_lossy(&without_key.stderr);
+    assert!(stderr.contains("no encryption key"), "{stderr}");
+
+    let wrong_key = run(&["--encryption-key", "wrong", "generations"]);
+    assert!(!wrong_key.status.success());
+    let stderr = String::from_utf8_lossy(&wrong_key.stderr);
+    assert!(stderr.contains("wrong encryption key"), "{stderr}");
+
+    println!("=== test_cli_encrypted_backup PASSED ===");
+}
+
+#[tokio::test]
+async fn test_cli_json_output() {
+    // A local directory needs no S3 credentials, so this always runs.
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    let replica = format!("file://{}", tmp.path().join("replica").display());
+    let config = test_config(db_path_str.clone(), S3Config::from_url(&replica).unwrap());
+    let mut mgr = BackupManager::new(config).await.expect("create manager");
+    insert_rows(&app_conn, 11, 10);
+    mgr.sync_wal().await.expect("sync wal");
+    mgr.checkpoint().await.expect("checkpoint");
+    let latest = mgr.generation().to_string();
+    mgr.shutdown().await.expect("shutdown");
+
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_waloy"))
+            .env_clear()
+            .env("PATH", env::var("PATH").unwrap_or_default())
+            .args(["--replica", &replica, "--output", "json"])
+            .args(args)
+            .output()
+            .expect("failed to execute waloy binary");
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        (output.status, stdout, stderr)
+    };
+    let json = |args: &[&str]| {
+        let (status, stdout, stderr) = run(args);
+        assert!(status.success(), "waloy {args:?} failed: {stderr}");
+        serde_json::from_str::<serde_json::Value>(&stdout).expect("json output")
+    };
+
+    let generations = json(&["generations"]);
+    let generations = generations.as_array().expect("array of generations");
+    assert_eq!(generations.len(), 2);
+    let current = generations
+        .iter()
+        .find(|g| g["latest"] == true)
+        .expect("latest generation");
+    assert_eq!(current["generation"], latest.as_str());
+    assert_eq!(current["encrypted"], false);
+    assert_eq!(current["compression"], "none");
+    assert!(current["total_bytes"].as_u64().unwrap() > 0);
+    let older = generations.iter().find(|g| g["latest"] == false).unwrap();
+    assert!(older["wal_bytes"].as_u64().unwrap() > 0);
+    assert!(older["restorable_to_ms"].as_u64() >= older["restorable_from_ms"].as_u64());
+
+    let older_id = older["generation"].as_str().unwrap();
+    let inspect = json(&["inspect", "--generation", older_id]);
+    assert_eq!(inspect["latest"], false);
+    let segments = inspect["segments"].as_array().expect("segments");
+    assert_eq!(segments.len(), 1);
+    assert_eq!(segments[0]["index"], 0);
+    assert!(segments[0]["stored_bytes"].as_u64().unwrap() > 0);
+
+    let restore_path = tmp.path().join("restored.db");
+    let report = json(&["restore", "--target", restore_path.to_str().unwrap()]);
+    assert_eq!(report["generation"], latest.as_str());
+    assert!(report["database_bytes"].as_u64().unwrap() > 0);
+    assert!(report["duration_ms"].is_u64());
+
+    let (status, stdout, stderr) = run(&["inspect", "--generation", "missing"]);
+    assert!(!status.success());
+    assert!(stdout.is_empty());
+    let error: serde_json::Value = serde_json::from_str(&stderr).expect("json error");
+    assert!(
+        error["error"].as_str().unwrap().contains("not found"),
+        "{stderr}"
+    );
+
+    println!("=== test_cli_json_output PASSED ===");
+}
+
+#[tokio::test]
+async fn test_cli_verify() {
+    // A local directory needs no S3 credentials, so this always runs.
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    let replica = format!("file://{}", tmp.path().join("replica").display());
+    let config = test_config(db_path_str.clone(), S3Config::from_url(&replica).unwrap());
+    let mut mgr = BackupManager::new(config).await.expect("create manager");
+    let first = mgr.generation().to_string();
+    insert_rows(&app_conn, 11, 10);
+    mgr.sync_wal().await.expect("sync wal");
+    mgr.checkpoint().await.expect("checkpoint");
+    insert_rows(&app_conn, 21, 10);
+    mgr.sync_wal().await.expect("sync wal after checkpoint");
+    mgr.shutdown().await.expect("shutdown");
+
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_waloy"))
+            .env_clear()
+            .env("PATH", env::var("PATH").unwrap_or_default())
+            .args(["--replica", &replica])
+            .args(args)
+            .output()
+            .expect("failed to execute waloy binary");
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        (output.status, stdout, stderr)
+    };
+
+    let (status, stdout, stderr) = run(&["verify", "--assert", "SELECT count(*) = 30 FROM items"]);
+    assert!(status.success(), "verify failed: {stdout}{stderr}");
+    assert!(stdout.contains("Integrity check: ok"), "{stdout}");
+    assert!(stdout.contains("Assertion ok: SELECT count(*) = 30 FROM items = 1"));
+    assert!(stdout.contains("Verification passed"), "{stdout}");
+
+    let (status, stdout, stderr) = run(&[
+        "--output",
+        "json",
+        "verify",
+        "--generation",
+        &first,
+        "--assert",
+        "SELECT count(*) = 20 FROM items",
+        "--assert",
+        "SELECT count(*) > 20 FROM items",
+        "--assert",
+        "SELECT * FROM missing",
+    ]);
+    assert_eq!(status.code(), Some(2), "{stderr}");
+    let report: serde_json::Value = serde_json::from_str(&stdout).expect("json output");
+    assert_eq!(report["passed"], false);
+    assert_eq!(report["generation"], first.as_str());
+    assert_eq!(report["integrity"], serde_json::json!(["ok"]));
+    assert!(report["restore_ms"].is_u64());
+    let assertions = report["assertions"].as_array().expect("assertions");
+    assert_eq!(assertions[0]["passed"], true);
+    assert_eq!(assertions[1]["passed"], false);
+    assert_eq!(assertions[1]["value"], 0);
+    assert!(
+        assertions[2]["error"]
+            .as_str()
+            .unwrap()
+            .contains("no such table")
+    );
+
+    // The first generation's snapshot is newer than the epoch.
+    let (status, _, stderr) = run(&[
+        "verify",
+        "--generation",
+        &first,
+        "--at",
+        "1970-01-01T00:00:01Z",
+    ]);
+    assert_eq!(status.code(), Some(1));
+    assert!(stderr.contains("no snapshot before"), "{stderr}");
+
+    println!("=== test_cli_verify PASSED ===");
+}
+
+#[tokio::test]
+async fn test_cli_maintenance() {
+    // A local directory needs no S3 credentials, so this always runs.
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    let replica = format!("file://{}", tmp.path().join("replica").display());
+    let config = BackupConfig {
+        encryption_key: Some("maintenance".into()),
+        compression: waloy::CompressionAlgorithm::Zstd,
+        ..test_config(db_path_str.clone(), S3Config::from_url(&replica).unwrap())
+    };
+    let mut mgr = BackupManager::new(config).await.expect("create manager");
+    let first = mgr.generation().to_string();
+    for start in [11, 21] {
+        insert_rows(&app_conn, start, 10);
+        mgr.sync_wal().await.expect("sync wal");
+    }
+    mgr.checkpoint().await.expect("checkpoint");
+    let latest = mgr.generation().to_string();
+    mgr.shutdown().await.expect("shutdown");
+
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_waloy"))
+            .env_clear()
+            .env("PATH", env::var("PATH").unwrap_or_default())
+            .env("WALOY_ENCRYPTION_KEY", "maintenance")
+            .args(["--replica", &replica])
+            .args(args)
+            .output()
+            .expect("failed to execute waloy binary");
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        (output.status, stdout, stderr)
+    };
+    let json = |args: &[&str]| {
+        let (status, stdout, stderr) = run(&[&["--output", "json"], args].concat());
+        assert!(status.success(), "waloy {args:?} failed: {stderr}");
+        serde_json::from_str::<serde_json::Value>(&stdout).expect("json output")
+    };
+
+    let (status, stdout, stderr) = run(&["compact", "--generation", &first]);
+    assert!(status.success(), "compact failed: {stderr}");
+    assert!(stdout.contains("2 segments -> 1"), "{stdout}");
+    let inspect = json(&["inspect", "--generation", &first]);
+    assert_eq!(inspect["segments"].as_array().unwrap().len(), 1);
+    assert_eq!(inspect["compression"], "zstd");
+    assert_eq!(inspect["encrypted"], true);
+
+    let (status, _, stderr) = run(&["compact", "--generation", &latest]);
+    assert!(!status.success());
+    assert!(stderr.contains("--force"), "{stderr}");
+
+    let snapshot = json(&["snapshot", "--db", &db_path_str]);
+    let newest = snapshot["generation"].as_str().expect("generation");
+    assert_ne!(newest, latest);
+    assert_eq!(json(&["generations"]).as_array().unwrap().len(), 3);
+
+    let (status, stdout, stderr) = run(&[
+        "prune",
+        "--older-than",
+        "0s",
+        "--keep-last",
+        "1",
+        "--dry-run",
+    ]);
+    assert!(status.success(), "prune --dry-run failed: {stderr}");
+    assert!(stdout.contains("Would delete 2 generations"), "{stdout}");
+    assert_eq!(json(&["generations"]).as_array().unwrap().len(), 3);
+
+    let pruned = json(&["prune", "--older-than", "0s"]);
+    assert_eq!(pruned["dry_run"], false);
+    assert_eq!(pruned["deleted"], serde_json::json!([first, latest]));
+    let generations = json(&["generations"]);
+    assert_eq!(generations.as_array().unwrap().len(), 1);
+    assert_eq!(generations[0]["generation"], newest);
+
+    println!("=== test_cli_maintenance PASSED ===");
+}
+
+#[tokio::test]
+async fn test_cli_wal_dump() {
+    // A local directory needs no S3 credentials, so this always runs.
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    let replica_dir = tmp.path().join("replica");
+    let replica = format!("file://{}", replica_dir.display());
+    let config = test_config(db_path_str.clone(), S3Config::from_url(&replica).unwrap());
+    let mut mgr = BackupManager::new(config).await.expect("create manager");
+    let generation = mgr.generation().to_string();
+    for start in [11, 21] {
+        insert_rows(&app_conn, start, 10);
+        mgr.sync_wal().await.expect("sync wal");
+    }
+    mgr.shutdown().await.expect("shutdown");
+
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_waloy"))
+            .env_clear()
+            .env("PATH", env::var("PATH").unwrap_or_default())
+            .args(["--replica", &replica])
+            .args(args)
+            .output()
+            .expect("failed to execute waloy binary");
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        assert!(output.status.success(), "waloy {args:?} failed: {stderr}");
+        stdout
+    };
+
+    let dump = run(&["wal-dump"]);
+    assert!(dump.contains("WAL header: magic=0x377f0682"), "{dump}");
+    assert!(dump.contains("Segment [00000001]"), "{dump}");
+    assert!(dump.contains("commits, 0 invalid)"), "{dump}");
+
+    let json: serde_json::Value =
+        serde_json::from_str(&run(&["--output", "json", "wal-dump", "--segment", "1"]))
+            .expect("json output");
+    assert_eq!(json["generation"], generation.as_str());
+    assert_eq!(json["header"]["checksum_valid"], true);
+    let segments = json["segments"].as_array().expect("segments");
+    assert_eq!(segments.len(), 1);
+    assert_eq!(segments[0]["index"], 1);
+    assert!(segments[0]["wal_offset"].as_u64().unwrap() > 0);
+    let frames = segments[0]["frames"].as_array().expect("frames");
+    assert!(!frames.is_empty());
+    assert!(frames.iter().all(|f| f["checksum_valid"] == true));
+
+    // Corrupt a page in the stored segment; only its frame is flagged.
+    let segment_path = replica_dir.join(&generation).join("wal/00000001");
+    let mut stored = std::fs::read(&segment_path).expect("read segment");
+    stored[100] ^= 0xff;
+    std::fs::write(&segment_path, stored).expect("write segment");
+    let dump = run(&["wal-dump", "--generation", &generation, "--segment", "1"]);
+    assert_eq!(dump.matches("BAD checksum").count(), 1, "{dump}");
+
+    println!("=== test_cli_wal_dump PASSED ===");
+}
+
+#[tokio::test]
+async fn test_cli_copy() {
+    // A local directory needs no S3 credentials, so this always runs.
+    let tmp = tempfile::tempdir().expect("tempdir");
+    let db_path = tmp.path().join("source.db");
+    let db_path_str = db_path.to_str().unwrap().to_string();
+    let app_conn = create_test_db(&db_path_str);
+    insert_rows(&app_conn, 1, 10);
+
+    let from = format!("file://{}", tmp.path().join("from").display());
+    let to = format!("file://{}", tmp.path().join("to").display());
+    let config = test_config(db_path_str.clone(), S3Config::from_url(&from).unwrap());
+    let mut mgr = BackupManager::new(config).await.expect("create manager");
+    insert_rows(&app_conn, 11, 10);
+    mgr.sync_wal().await.expect("sync wal");
+    let generation = mgr.generation().to_string();
+    mgr.shutdown().await.expect("shutdown");
+
+    let run = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_waloy"))
+            .env_clear()
+            .env("PATH", env::var("PATH").unwrap_or_default())
+            .args(args)
+            .output()
+            .expect("failed to execute waloy binary");
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        assert!(output.status.success(), "waloy {args:?} failed: {stderr}");
+        stdout
+    };
+
+    let copied = run(&[
+        "copy",
+        "--from",
+        &from,
+        "--to",
+        &to,
+        "--compression",
+        "zstd",
+        "--to-encryption-key",
+        "new key",
+    ]);
+    assert!(copied.contains("1 generations"), "{copied}");
+    assert!(copied.contains("re-encoded"), "{copied}");
+
+    // Re-running writes nothing new.
+    let report: serde_json::Value = serde_json::from_str(&run(&[
+        "--output",
+        "json",
+        "copy",
+        "--from",
+        &from,
+        "--to",
+        &to,
+        "--compression",
+        "zstd",
+        "--to-encryption-key",
+        "new key",
+    ]))
+    .expect("json output");
+    assert_eq!(report["objects_copied"], 0);
+    assert!(report["objects_skipped"].as_u64().unwrap() > 0);
+
+    let inspect: serde_json::Value = serde_json::from_str(&run(&[
+        "--replica",
+        &to,
+        "--encryption-key",
+        "new key",
+        "--output",
+        "json",
+        "inspect",
+    ]))
+    .expect("json output");
+    assert_eq!(inspect["generation"], generation.as_str());
+    assert_eq!(inspect["compression"], "zstd");
+    assert_eq!(inspect["encrypted"], true);
+
+    let restore_path = tmp.path().join("restored.db");
+    run(&[
+        "--replica",
+        &to,
+        "--encryption-key",
+        "new key",
+        "restore",
+        "--target",
+        restore_path.to_str().unwrap(),
+    ]);
+    let restored_conn = Connection::open(&restore_path).expect("open restored db");
+    assert_eq!(count_rows(&restored_conn), 20);
+
+    println!("=== test_cli_copy PASSED ===");
+}